@@ -0,0 +1,23 @@
+#![no_main]
+
+use base64::{Engine as _, engine::general_purpose};
+use libfuzzer_sys::fuzz_target;
+
+#[allow(dead_code)]
+#[path = "../../src/utils/encoding.rs"]
+mod encoding;
+
+fuzz_target!(|data: &[u8]| {
+    // Raw strings exercise the base64 layer, encoded bytes exercise the message parser.
+    if let Ok(text) = std::str::from_utf8(data) {
+        let _ = encoding::decode_track(text);
+    }
+
+    let encoded = general_purpose::STANDARD.encode(data);
+    if let Ok(track) = encoding::decode_track(&encoded) {
+        let reencoded = encoding::encode_track(&track.info);
+        let again = encoding::decode_track(&reencoded).expect("re-encoded track must decode");
+        assert_eq!(again.info.source_data, track.info.source_data);
+        assert_eq!(again.info.position, track.info.position);
+    }
+});
@@ -1,39 +1,80 @@
-use std::sync::{Arc, Mutex};
-use sysinfo::{System, RefreshKind, CpuRefreshKind, MemoryRefreshKind};
+use std::sync::Arc;
+use crate::managers::cache::TrackCache;
+use crate::managers::plugins::PluginManager;
 use crate::managers::sessions::SessionManager;
 use crate::managers::sources::SourceManager;
 use crate::managers::stats::StatsManager;
 use crate::managers::route_planner::RoutePlannerManager;
+use crate::managers::search::{MirrorSettings, SearchSettings};
+use crate::playback::opus::OpusSettings;
+use crate::playback::workers::AudioWorkers;
+use crate::utils::{log, Level};
+use crate::sources::external::ExternalSource;
+use crate::sources::hls::HlsSource;
+use crate::sources::http::HttpSource;
 use crate::sources::local::LocalSource;
+use crate::sources::local::sandbox::LocalSandbox;
 
 pub struct Aelira {
     pub version: String,
     pub password: Option<String>,
-    pub system: Arc<Mutex<System>>,
-    pub sessions: Mutex<SessionManager>,
+    pub sessions: SessionManager,
     pub sources: Arc<SourceManager>,
+    pub plugins: Arc<PluginManager>,
     pub stats: Arc<StatsManager>,
     pub route_planner: Arc<RoutePlannerManager>,
+    pub opus: OpusSettings,
+    pub workers: Arc<AudioWorkers>,
 }
 
 impl Aelira {
     pub fn new(config: &crate::config::Config, version: String) -> Self {
-        let refresh = RefreshKind::nothing()
-            .with_cpu(CpuRefreshKind::nothing().with_cpu_usage())
-            .with_memory(MemoryRefreshKind::nothing().with_ram());
-            
-        let system = System::new_with_specifics(refresh);
+        let local_config = config.sources.as_ref()
+            .and_then(|s| s.local.clone())
+            .unwrap_or_default();
+
         let mut sources = SourceManager::new();
-        sources.register(Box::new(LocalSource));
+        sources.set_search(SearchSettings::from_config(config.search.clone().unwrap_or_default()));
+        sources.set_mirroring(MirrorSettings::from_config(config.mirroring.clone().unwrap_or_default()));
+        sources.set_cache(TrackCache::new(config.cache.clone().unwrap_or_default()));
+        if local_config.enabled.unwrap_or(true) {
+            let roots: Vec<String> = local_config.allowed_roots.iter()
+                .chain(local_config.library_roots.iter())
+                .flatten()
+                .cloned()
+                .collect();
+
+            let sandbox = Arc::new(LocalSandbox::new(&roots));
+            sources.set_local_sandbox(sandbox.clone());
+            sources.register(Box::new(LocalSource::new(local_config, &config.server.public_url(), sandbox)));
+        }
+        sources.register(Box::new(HlsSource::new()));
+        sources.register(Box::new(HttpSource::new()));
+
+        let external = config.sources.as_ref().and_then(|s| s.external.clone()).unwrap_or_default();
+        for source_config in external {
+            if sources.names().contains(&source_config.name) {
+                log(Level::Warn, "Aelira", format!("External source '{}' conflicts with an existing source, skipping", source_config.name));
+                continue;
+            }
+            sources.register(Box::new(ExternalSource::new(source_config)));
+        }
+
+        let plugins = PluginManager::load(config.plugins.as_ref(), &crate::plugins::available());
+        for plugin in plugins.plugins() {
+            sources.register_plugin(plugin);
+        }
 
         Aelira {
             version,
             password: config.server.password.clone(),
-            system: Arc::new(Mutex::new(system)),
-            sessions: Mutex::new(SessionManager::new()),
+            sessions: SessionManager::new(),
             sources: Arc::new(sources),
+            plugins: Arc::new(plugins),
             stats: Arc::new(StatsManager::new()),
             route_planner: Arc::new(RoutePlannerManager::new()),
+            opus: OpusSettings::from_config(config.opus.clone().unwrap_or_default()),
+            workers: Arc::new(AudioWorkers::new(config.audio_workers())),
         }
     }
 }
@@ -0,0 +1,97 @@
+use std::error::Error;
+use serde::Serialize;
+use warp::{Filter, Reply};
+use warp::http::StatusCode;
+use warp::path::FullPath;
+use warp::reject::Reject;
+use crate::utils::cause_chain;
+
+/// An error returned to REST clients as Lavalink's error body.
+#[derive(Debug, Clone)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub message: String,
+    pub trace: Option<String>,
+}
+
+impl Reject for ApiError {}
+
+impl ApiError {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+            trace: None,
+        }
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, message)
+    }
+
+    pub fn with_cause(mut self, cause: &dyn Error) -> Self {
+        self.trace = Some(cause_chain(cause));
+        self
+    }
+
+    pub fn reject(self) -> warp::Rejection {
+        warp::reject::custom(self)
+    }
+}
+
+/// Carries the request path and `trace` flag to `handle_rejection`, which only receives the rejection.
+#[derive(Debug)]
+pub struct RequestContext {
+    pub path: String,
+    pub trace: bool,
+}
+
+impl Reject for RequestContext {}
+
+/// Always rejects with the request context. Placed last in the route chain so the context is
+/// combined into whatever rejection the other routes produced.
+pub fn request_context() -> impl Filter<Extract = (warp::reply::Response,), Error = warp::Rejection> + Clone {
+    warp::path::full()
+        .and(warp::query::raw().or(warp::any().map(String::new)).unify())
+        .and_then(|path: FullPath, query: String| async move {
+            let trace = url::form_urlencoded::parse(query.as_bytes())
+                .any(|(key, value)| key == "trace" && value == "true");
+
+            Err::<warp::reply::Response, _>(warp::reject::custom(RequestContext {
+                path: path.as_str().to_string(),
+                trace,
+            }))
+        })
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    timestamp: u128,
+    status: u16,
+    error: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trace: Option<&'a str>,
+    message: &'a str,
+    path: &'a str,
+}
+
+pub fn error_response(error: &ApiError, context: Option<&RequestContext>) -> warp::reply::Response {
+    let include_trace = context.is_some_and(|c| c.trace);
+    let body = ErrorBody {
+        timestamp: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or(0),
+        status: error.status.as_u16(),
+        error: error.status.canonical_reason().unwrap_or("Unknown"),
+        trace: include_trace.then(|| error.trace.as_deref().unwrap_or(&error.message)),
+        message: &error.message,
+        path: context.map(|c| c.path.as_str()).unwrap_or("/"),
+    };
+
+    warp::reply::with_status(warp::reply::json(&body), error.status).into_response()
+}
@@ -13,10 +13,9 @@ pub fn with_auth(password: Option<String>) -> impl Filter<Extract = (), Error =
                 return true;
             }
             
-            if let Some(ref pass) = password {
-                if let Some(header) = auth_header {
-                    return header == *pass;
-                }
+            if let Some(ref pass) = password
+                && let Some(header) = auth_header {
+                return header == *pass;
             }
             false
         })
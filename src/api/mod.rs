@@ -1,17 +1,102 @@
 pub mod routes;
 pub mod middlewares;
+pub mod errors;
 
+use std::error::Error;
 use warp::http::StatusCode;
+use warp::reject::{InvalidHeader, InvalidQuery, LengthRequired, MethodNotAllowed, MissingHeader, PayloadTooLarge, UnsupportedMediaType};
+use warp::body::BodyDeserializeError;
+use errors::{ApiError, RequestContext, error_response};
+use crate::utils::{log, Level};
 
 pub async fn handle_rejection(err: warp::Rejection) -> Result<impl warp::Reply, std::convert::Infallible> {
-    if err.is_not_found() {
-        return Ok(warp::reply::with_status("Not Found", StatusCode::NOT_FOUND));
+    let context = err.find::<RequestContext>();
+
+    let error = if let Some(e) = err.find::<ApiError>() {
+        e.clone()
+    } else if err.find::<middlewares::auth::AuthError>().is_some() {
+        ApiError::new(StatusCode::UNAUTHORIZED, "Unauthorized")
+    } else if let Some(e) = err.find::<BodyDeserializeError>() {
+        let detail = e.source().map(|s| s.to_string()).unwrap_or_else(|| e.to_string());
+        ApiError::bad_request(format!("Invalid JSON body: {}", detail)).with_cause(e)
+    } else if let Some(e) = err.find::<InvalidQuery>() {
+        ApiError::bad_request(e.to_string()).with_cause(e)
+    } else if let Some(e) = err.find::<MissingHeader>() {
+        ApiError::bad_request(e.to_string())
+    } else if let Some(e) = err.find::<InvalidHeader>() {
+        ApiError::bad_request(e.to_string())
+    } else if err.find::<UnsupportedMediaType>().is_some() {
+        ApiError::new(StatusCode::UNSUPPORTED_MEDIA_TYPE, "Request body must be JSON")
+    } else if err.find::<PayloadTooLarge>().is_some() {
+        ApiError::new(StatusCode::PAYLOAD_TOO_LARGE, "Request body is too large")
+    } else if err.find::<LengthRequired>().is_some() {
+        ApiError::new(StatusCode::LENGTH_REQUIRED, "Request body needs a content length")
+    } else if err.find::<MethodNotAllowed>().is_some() {
+        ApiError::new(StatusCode::METHOD_NOT_ALLOWED, "Method not allowed")
+    } else if err.is_not_found() || context.is_some() {
+        ApiError::not_found("Not Found")
+    } else {
+        log(Level::Error, "API", format!("Unhandled rejection: {:?}", err));
+        ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error")
+    };
+
+    Ok(error_response(&error, context))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use warp::Filter;
+    use crate::aelira::Aelira;
+
+    fn routes() -> impl Filter<Extract = (impl warp::Reply,), Error = std::convert::Infallible> + Clone {
+        let config = toml::from_str("[server]\nhost = \"127.0.0.1\"\nport = 2333\n\n[cluster]\naudio_workers = 1").unwrap();
+        routes::all_routes(Arc::new(Aelira::new(&config, "1.0.0".to_string()))).recover(handle_rejection)
+    }
+
+    fn assert_bad_request(response: warp::http::Response<bytes::Bytes>, message: &str, path: &str) {
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let mut body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert!(body["timestamp"].as_u64().is_some_and(|t| t > 0), "{}", body);
+        body.as_object_mut().unwrap().remove("timestamp");
+        assert_eq!(body, serde_json::json!({
+            "status": 400,
+            "error": "Bad Request",
+            "message": message,
+            "path": path,
+        }));
     }
 
-    if let Some(_) = err.find::<middlewares::auth::AuthError>() {
-        return Ok(warp::reply::with_status("Unauthorized", StatusCode::UNAUTHORIZED));
+    #[tokio::test]
+    async fn rejects_invalid_tracks_with_lavalink_errors() {
+        let routes = routes();
+
+        let response = warp::test::request()
+            .path("/v4/decodetrack?encodedTrack=AAAAPgAE")
+            .reply(&routes)
+            .await;
+        assert_bad_request(response, "Failed to decode track: track data ends before its message body", "/v4/decodetrack");
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/v4/decodetracks")
+            .json(&["QAAAZQIABlN0cmVhbQAFUmFkaW9//////////wAaaHR0cHM6Ly9yYWRpby5leGFtcGxlL2xpdmUBAQAaaHR0cHM6Ly9yYWRpby5leGFtcGxlL2xpdmUABGh0dHAAA21wMwAAAAAAAAAA", "QAAAAQQ="])
+            .reply(&routes)
+            .await;
+        assert_bad_request(response, "Failed to decode track: unknown track version 4", "/v4/decodetracks");
     }
 
-    eprintln!("Unhandled rejection: {:?}", err);
-    Ok(warp::reply::with_status("Internal Server Error", StatusCode::INTERNAL_SERVER_ERROR))
-}
\ No newline at end of file
+    #[tokio::test]
+    async fn includes_the_cause_when_tracing() {
+        let response = warp::test::request()
+            .path("/v4/decodetrack?encodedTrack=not-base64&trace=true")
+            .reply(&routes())
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert!(body["message"].as_str().unwrap().starts_with("Failed to decode track: invalid base64"));
+        assert!(body["trace"].as_str().unwrap().contains("invalid base64"));
+    }
+}
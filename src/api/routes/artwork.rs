@@ -0,0 +1,39 @@
+use warp::{Filter, Reply};
+use crate::aelira::AeliraRef;
+use crate::api::errors::ApiError;
+use crate::sources::local::LocalSource;
+use crate::sources::local::sandbox::LocalAccess;
+
+pub fn handler(aelira: AeliraRef) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let with_aelira = warp::any().map(move || aelira.clone());
+
+    warp::path("v4")
+        .and(warp::path("local"))
+        .and(warp::path("artwork"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(with_aelira)
+        .and_then(|id: String, aelira: AeliraRef| async move {
+            aelira.stats.increment_api_request("/v4/local/artwork");
+
+            let path = match LocalSource::artwork_path(&id) {
+                Some(p) => p,
+                None => return Err(ApiError::bad_request("Invalid artwork id").reject()),
+            };
+
+            let path = match aelira.sources.local_sandbox().map(|sandbox| sandbox.check(&path)) {
+                Some(LocalAccess::Allowed(p)) => p,
+                _ => return Err(ApiError::not_found("Artwork not found").reject()),
+            };
+
+            match tokio::task::spawn_blocking(move || LocalSource::read_artwork(&path)).await {
+                Ok(Some((mime, data))) => Ok::<_, warp::Rejection>(warp::http::Response::builder()
+                    .header("content-type", mime)
+                    .header("cache-control", "public, max-age=86400")
+                    .body(data)
+                    .into_response()),
+                _ => Err(ApiError::not_found("Artwork not found").reject()),
+            }
+        })
+}
@@ -1,8 +1,8 @@
 use warp::{Filter, Reply};
 use crate::aelira::AeliraRef;
+use crate::api::errors::ApiError;
 use crate::utils::encoding::decode_track;
 use serde::Deserialize;
-use serde_json::json;
 
 #[derive(Deserialize)]
 pub struct DecodeTrackQuery {
@@ -18,21 +18,12 @@ pub fn handler(aelira: AeliraRef) -> impl Filter<Extract = (impl warp::Reply,),
         .and(warp::get())
         .and(warp::query::<DecodeTrackQuery>())
         .and(with_aelira)
-        .map(|query: DecodeTrackQuery, _aelira: AeliraRef| {
+        .and_then(|query: DecodeTrackQuery, _aelira: AeliraRef| async move {
             let encoded = query.encoded_track.replace(' ', "+");
-            
+
             match decode_track(&encoded) {
-                Ok(decoded) => warp::reply::json(&decoded).into_response(),
-                Err(e) => {
-                    let error = json!({
-                        "timestamp": std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis(),
-                        "status": 400,
-                        "error": "Bad Request",
-                        "message": format!("Failed to decode track: {}", e),
-                        "path": "/v4/decodetrack"
-                    });
-                    warp::reply::with_status(warp::reply::json(&error), warp::http::StatusCode::BAD_REQUEST).into_response()
-                }
+                Ok(decoded) => Ok(warp::reply::json(&decoded).into_response()),
+                Err(e) => Err(ApiError::bad_request(format!("Failed to decode track: {}", e)).with_cause(&e).reject()),
             }
         })
 }
@@ -1,7 +1,7 @@
 use warp::{Filter, Reply};
 use crate::aelira::AeliraRef;
+use crate::api::errors::ApiError;
 use crate::utils::encoding::decode_track;
-use serde_json::json;
 
 pub fn handler(aelira: AeliraRef) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
     let with_aelira = warp::any().map(move || aelira.clone());
@@ -11,26 +11,17 @@ pub fn handler(aelira: AeliraRef) -> impl Filter<Extract = (impl warp::Reply,),
         .and(warp::post())
         .and(warp::body::json::<Vec<String>>())
         .and(with_aelira)
-        .map(|tracks: Vec<String>, _aelira: AeliraRef| {
+        .and_then(|tracks: Vec<String>, _aelira: AeliraRef| async move {
             let mut decoded_tracks = Vec::new();
             
             for encoded in tracks {
                 let encoded = encoded.replace(' ', "+");
                 match decode_track(&encoded) {
                     Ok(decoded) => decoded_tracks.push(decoded),
-                    Err(e) => {
-                        let error = json!({
-                            "timestamp": std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis(),
-                            "status": 400,
-                            "error": "Bad Request",
-                            "message": format!("Failed to decode track: {}", e),
-                            "path": "/v4/decodetracks"
-                        });
-                        return warp::reply::with_status(warp::reply::json(&error), warp::http::StatusCode::BAD_REQUEST).into_response();
-                    }
+                    Err(e) => return Err(ApiError::bad_request(format!("Failed to decode track: {}", e)).with_cause(&e).reject()),
                 }
             }
             
-            warp::reply::json(&decoded_tracks).into_response()
+            Ok(warp::reply::json(&decoded_tracks).into_response())
         })
 }
@@ -1,8 +1,8 @@
 use warp::{Filter, Reply};
 use crate::aelira::AeliraRef;
+use crate::api::errors::ApiError;
 use crate::utils::encoding::{encode_track, DecodedInfo};
 use serde::Deserialize;
-use serde_json::json;
 
 #[derive(Deserialize)]
 pub struct EncodeTrackQuery {
@@ -17,22 +17,13 @@ pub fn handler(aelira: AeliraRef) -> impl Filter<Extract = (impl warp::Reply,),
         .and(warp::get())
         .and(warp::query::<EncodeTrackQuery>())
         .and(with_aelira)
-        .map(|query: EncodeTrackQuery, _aelira: AeliraRef| {
+        .and_then(|query: EncodeTrackQuery, _aelira: AeliraRef| async move {
             match serde_json::from_str::<DecodedInfo>(&query.track) {
                 Ok(info) => {
                     let encoded = encode_track(&info);
-                    warp::reply::json(&encoded).into_response()
+                    Ok(warp::reply::json(&encoded).into_response())
                 },
-                Err(e) => {
-                    let error = json!({
-                        "timestamp": std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis(),
-                        "status": 400,
-                        "error": "Bad Request",
-                        "message": format!("Failed to parse track info: {}", e),
-                        "path": "/v4/encodetrack"
-                    });
-                    warp::reply::with_status(warp::reply::json(&error), warp::http::StatusCode::BAD_REQUEST).into_response()
-                }
+                Err(e) => Err(ApiError::bad_request(format!("Failed to parse track info: {}", e)).with_cause(&e).reject()),
             }
         })
 }
@@ -1,5 +1,6 @@
 use warp::Filter;
 use crate::aelira::AeliraRef;
+use crate::managers::players::BUILT_IN_FILTERS;
 use serde_json::json;
 use sysinfo::System;
 
@@ -14,12 +15,13 @@ pub fn handler(aelira: AeliraRef) -> impl Filter<Extract = (impl warp::Reply,),
             aelira.stats.increment_api_request("/v4/info");
             let version_str = aelira.version.clone();
             let parts: Vec<&str> = version_str.split('.').collect();
-            let major = parts.get(0).unwrap_or(&"0").parse().unwrap_or(0);
+            let major = parts.first().unwrap_or(&"0").parse().unwrap_or(0);
             let minor = parts.get(1).unwrap_or(&"0").parse().unwrap_or(0);
             let patch = parts.get(2).unwrap_or(&"0").parse().unwrap_or(0);
 
             let os = System::name().unwrap_or_else(|| "unknown".to_string());
             let _kernel = System::kernel_version().unwrap_or_else(|| "unknown".to_string());
+            let filters: Vec<&str> = BUILT_IN_FILTERS.iter().copied().chain(aelira.plugins.filter_names()).collect();
 
             let response = json!({
                 "version": {
@@ -45,9 +47,9 @@ pub fn handler(aelira: AeliraRef) -> impl Filter<Extract = (impl warp::Reply,),
                     "name": "aelira-voice",
                     "version": "1.0.0"
                 },
-                "sourceManagers": ["local"],
-                "filters": [],
-                "plugins": []
+                "sourceManagers": aelira.sources.names(),
+                "filters": filters,
+                "plugins": aelira.plugins.descriptors()
             });
 
             warp::reply::json(&response)
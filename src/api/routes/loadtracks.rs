@@ -1,5 +1,6 @@
 use warp::Filter;
 use crate::aelira::AeliraRef;
+use crate::api::middlewares::auth::with_auth;
 use serde::Deserialize;
 
 #[derive(Deserialize)]
@@ -7,17 +8,48 @@ pub struct LoadTracksQuery {
     pub identifier: String,
 }
 
+#[derive(Deserialize)]
+pub struct PurgeQuery {
+    pub identifier: Option<String>,
+}
+
 pub fn handler(aelira: AeliraRef) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let auth = with_auth(aelira.password.clone());
     let with_aelira = warp::any().map(move || aelira.clone());
 
-    warp::path("v4")
+    let load = warp::path("v4")
         .and(warp::path("loadtracks"))
+        .and(warp::path::end())
         .and(warp::get())
         .and(warp::query::<LoadTracksQuery>())
-        .and(with_aelira)
+        .and(with_aelira.clone())
         .and_then(|query: LoadTracksQuery, aelira: AeliraRef| async move {
             aelira.stats.increment_api_request("/v4/loadtracks");
             let response = aelira.sources.load_tracks(&query.identifier).await;
             Ok::<_, warp::Rejection>(warp::reply::json(&response))
-        })
+        });
+
+    let cache_stats = warp::path("v4")
+        .and(warp::path("loadtracks"))
+        .and(warp::path("cache"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(auth.clone())
+        .and(with_aelira.clone())
+        .map(|aelira: AeliraRef| warp::reply::json(&aelira.sources.cache().stats()));
+
+    let cache_purge = warp::path("v4")
+        .and(warp::path("loadtracks"))
+        .and(warp::path("cache"))
+        .and(warp::path::end())
+        .and(warp::delete())
+        .and(auth)
+        .and(warp::query::<PurgeQuery>())
+        .and(with_aelira)
+        .map(|query: PurgeQuery, aelira: AeliraRef| {
+            let purged = aelira.sources.cache().purge(query.identifier.as_deref());
+            warp::reply::json(&serde_json::json!({ "purged": purged }))
+        });
+
+    load.or(cache_stats).or(cache_purge)
 }
@@ -1,5 +1,6 @@
 use warp::Filter;
 use crate::aelira::AeliraRef;
+use crate::api::errors::request_context;
 use crate::api::middlewares::auth::with_auth;
 
 mod websocket;
@@ -13,6 +14,8 @@ mod decodetracks;
 mod encodetrack;
 mod encodetracks;
 mod routeplanner;
+mod artwork;
+mod plugins;
 
 pub fn all_routes(aelira: AeliraRef) -> impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     let auth = with_auth(aelira.password.clone());
@@ -28,6 +31,8 @@ pub fn all_routes(aelira: AeliraRef) -> impl warp::Filter<Extract = impl warp::R
     let encodetrack_route = encodetrack::handler(aelira.clone());
     let encodetracks_route = encodetracks::handler(aelira.clone());
     let routeplanner_route = routeplanner::handler(aelira.clone());
+    let artwork_route = artwork::handler(aelira.clone());
+    let plugins_route = plugins::handler(aelira.clone());
 
     let v4_stats = warp::path("v4")
         .and(auth.clone())
@@ -45,4 +50,7 @@ pub fn all_routes(aelira: AeliraRef) -> impl warp::Filter<Extract = impl warp::R
         .or(encodetrack_route)
         .or(encodetracks_route)
         .or(routeplanner_route)
+        .or(artwork_route)
+        .or(plugins_route)
+        .or(request_context())
 }
@@ -0,0 +1,42 @@
+use std::collections::HashMap;
+use bytes::Bytes;
+use warp::Filter;
+use warp::http::{Method, StatusCode};
+use warp::path::FullPath;
+use crate::aelira::AeliraRef;
+use crate::api::middlewares::auth::with_auth;
+use crate::managers::plugins::PluginRequest;
+
+pub fn handler(aelira: AeliraRef) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let auth = with_auth(aelira.password.clone());
+    let with_aelira = warp::any().map(move || aelira.clone());
+
+    warp::method()
+        .and(warp::path::full())
+        .and(with_aelira)
+        .and_then(|method: Method, path: FullPath, aelira: AeliraRef| async move {
+            if aelira.plugins.has_route(&method, path.as_str()) {
+                Ok((method, path, aelira))
+            } else {
+                Err(warp::reject::not_found())
+            }
+        })
+        .untuple_one()
+        .and(auth)
+        .and(warp::query::<HashMap<String, String>>())
+        .and(warp::body::bytes())
+        .and_then(|method: Method, path: FullPath, aelira: AeliraRef, query: HashMap<String, String>, body: Bytes| async move {
+            aelira.stats.increment_api_request(path.as_str());
+            let request = PluginRequest {
+                path: path.as_str().to_string(),
+                query,
+                body,
+            };
+
+            let Some(response) = aelira.plugins.handle(&method, request).await else {
+                return Err(warp::reject::not_found());
+            };
+            let status = StatusCode::from_u16(response.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+            Ok(warp::reply::with_status(warp::reply::json(&response.body), status))
+        })
+}
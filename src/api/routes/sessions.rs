@@ -1,8 +1,12 @@
 use crate::aelira::AeliraRef;
-use crate::managers::players::{Player, TrackData, VoiceState};
-use crate::utils::encoding::decode_track;
+use crate::config::OpusConfig;
+use crate::api::errors::ApiError;
+use crate::managers::players::{PlayerCommand, PlayerContext, VoiceState};
+use crate::models::load_tracks::LoadResultData;
+use crate::utils::encoding::{decode_track, DecodeError, DecodedTrack};
 use crate::utils::{log, Level};
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
+use std::sync::Arc;
 use warp::http::StatusCode;
 use warp::{Filter, Reply};
 
@@ -18,23 +22,48 @@ pub struct SessionUpdatePayload {
 #[allow(dead_code)]
 pub struct PlayerUpdatePayload {
     pub track: Option<UpdatePlayerTrack>,
-    pub encoded_track: Option<String>,
-    pub _position: Option<i64>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub encoded_track: Option<Option<String>>,
+    pub position: Option<u64>,
     pub _end_time: Option<i64>,
     pub volume: Option<u16>,
     pub paused: Option<bool>,
     pub voice: Option<VoiceState>,
-    pub _filters: Option<serde_json::Value>,
-    pub _no_replace: Option<bool>,
+    pub filters: Option<serde_json::Value>,
+    pub opus: Option<OpusConfig>,
+    pub no_replace: Option<bool>,
 }
 
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
-#[allow(dead_code)]
 pub struct UpdatePlayerTrack {
-    pub encoded: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub encoded: Option<Option<String>>,
     pub identifier: Option<String>,
-    pub _user_data: Option<serde_json::Value>,
+    pub user_data: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerUpdateQuery {
+    pub no_replace: Option<bool>,
+}
+
+/// Tells an explicit `null` (stop the track) apart from a missing field (leave it alone).
+fn nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+fn session_not_found() -> warp::Rejection {
+    ApiError::not_found("Session not found").reject()
+}
+
+fn invalid_track(e: DecodeError) -> warp::Rejection {
+    ApiError::bad_request(format!("Failed to decode track: {}", e)).with_cause(&e).reject()
 }
 
 pub fn handler(aelira: AeliraRef) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
@@ -44,149 +73,160 @@ pub fn handler(aelira: AeliraRef) -> impl Filter<Extract = (impl warp::Reply,),
         .and(warp::path("sessions"))
         .and(warp::path::param::<String>());
 
-    let update_session = base_sessions.clone()
+    let update_session = base_sessions
         .and(warp::path::end())
         .and(warp::patch())
         .and(warp::body::json())
         .and(with_aelira.clone())
-        .map(|session_id: String, body: SessionUpdatePayload, aelira: AeliraRef| {
-            let manager = aelira.sessions.lock().unwrap();
-
-            if manager.sessions.contains_key(&session_id) {
+        .and_then(|session_id: String, body: SessionUpdatePayload, aelira: AeliraRef| async move {
+            if aelira.sessions.sessions.contains_key(&session_id) {
                 let response = serde_json::json!({
                     "resuming": body.resuming.unwrap_or(false),
                     "timeout": body.timeout.unwrap_or(60)
                 });
-                return warp::reply::json(&response).into_response();
+                return Ok(warp::reply::json(&response).into_response());
             }
-            warp::reply::with_status("Session not found", StatusCode::NOT_FOUND).into_response()
+            Err(session_not_found())
         });
 
-    let base_players = base_sessions.clone()
+    let base_players = base_sessions
         .and(warp::path("players"));
 
-    let get_players = base_players.clone()
+    let get_players = base_players
         .and(warp::path::end())
         .and(warp::get())
         .and(with_aelira.clone())
-        .map(|session_id: String, aelira: AeliraRef| {
-            let manager = aelira.sessions.lock().unwrap();
-            if let Some(session) = manager.sessions.get(&session_id) {
-                let players = session.players.lock().unwrap();
-                let player_list: Vec<&Player> = players.players.values().collect();
-                return warp::reply::json(&player_list).into_response();
-            }
-            warp::reply::with_status("Session not found", StatusCode::NOT_FOUND).into_response()
+        .and_then(|session_id: String, aelira: AeliraRef| async move {
+            let session = aelira.sessions.get(&session_id).ok_or_else(session_not_found)?;
+            Ok::<_, warp::Rejection>(warp::reply::json(&session.players.snapshots()).into_response())
         });
 
-    let player_by_id = base_players.clone()
+    let player_by_id = base_players
         .and(warp::path::param::<String>());
 
-    let get_player = player_by_id.clone()
+    let get_player = player_by_id
         .and(warp::path::end())
         .and(warp::get())
         .and(with_aelira.clone())
-        .map(|session_id: String, guild_id: String, aelira: AeliraRef| {
-            let manager = aelira.sessions.lock().unwrap();
-            if let Some(session) = manager.sessions.get(&session_id) {
-                let mut players = session.players.lock().unwrap();
-                let player = players.get_or_create(guild_id);
-                return warp::reply::json(&player).into_response();
-            }
-            warp::reply::with_status("Session not found", StatusCode::NOT_FOUND).into_response()
+        .and_then(|session_id: String, guild_id: String, aelira: AeliraRef| async move {
+            let session = aelira.sessions.get(&session_id).ok_or_else(session_not_found)?;
+            let player = session.players.get_or_create(&guild_id, || PlayerContext {
+                user_id: session.user_id.clone(),
+                session: Arc::downgrade(&session),
+                sources: aelira.sources.clone(),
+                plugins: aelira.plugins.clone(),
+                opus: aelira.opus,
+                workers: aelira.workers.clone(),
+            });
+            Ok::<_, warp::Rejection>(warp::reply::json(&player.snapshot()).into_response())
         });
 
-    let patch_player = player_by_id.clone()
+    let patch_player = player_by_id
         .and(warp::path::end())
         .and(warp::patch())
+        .and(warp::query::<PlayerUpdateQuery>())
         .and(warp::body::json())
         .and(with_aelira.clone())
-        .and_then(|session_id: String, guild_id: String, body: PlayerUpdatePayload, aelira: AeliraRef| async move {
+        .and_then(|session_id: String, guild_id: String, query: PlayerUpdateQuery, body: PlayerUpdatePayload, aelira: AeliraRef| async move {
             log(Level::Debug, "API", format!("PATCH Player Session: {}, Guild: {}", session_id, guild_id));
 
-            let mut identifier_to_resolve = None;
-
-            {
-                let manager = aelira.sessions.lock().unwrap();
-                if let Some(session) = manager.sessions.get(&session_id) {
-                    let mut players = session.players.lock().unwrap();
-                    let player = players.get_or_create(guild_id.clone());
+            let session = aelira.sessions.get(&session_id).ok_or_else(session_not_found)?;
+
+            let encoded = match &body.track {
+                Some(track) => track.encoded.clone(),
+                None => body.encoded_track.clone(),
+            };
+            let identifier = body.track.as_ref().and_then(|t| t.identifier.clone());
+            let user_data = body.track.as_ref().and_then(|t| t.user_data.clone());
+
+            // Resolve the new track before touching the player so a bad track leaves it unchanged.
+            let track: Option<Option<Box<DecodedTrack>>> = match (encoded, identifier) {
+                (Some(Some(encoded)), _) => {
+                    let mut decoded = decode_track(&encoded).map_err(invalid_track)?;
+                    aelira.sources.apply_track_plugin_info(&mut decoded);
+                    Some(Some(Box::new(decoded)))
+                },
+                (Some(None), _) => Some(None),
+                (None, Some(identifier)) => match aelira.sources.load_tracks(&identifier).await.data {
+                    LoadResultData::Track(track) => Some(Some(track)),
+                    _ => return Err(ApiError::bad_request("Track resolution failed").reject()),
+                },
+                (None, None) => None,
+            };
+
+            // Live streams can only be read forward in real time, so seeking would play silence.
+            let seeks_stream = match &track {
+                Some(Some(track)) => track.info.is_stream && body.position.is_some_and(|p| p > 0),
+                Some(None) => false,
+                None => body.position.is_some_and(|p| p > 0) && session.players.get(&guild_id)
+                    .and_then(|player| player.snapshot().track)
+                    .is_some_and(|track| track.info.is_stream),
+            };
+            if seeks_stream {
+                return Err(ApiError::bad_request("Cannot seek a stream track").reject());
+            }
 
-                    if let Some(voice) = &body.voice {
-                        let should_connect = match &player.voice {
-                            Some(current) => current.token != voice.token || current.endpoint != voice.endpoint || current.session_id != voice.session_id,
-                            None => true
-                        };
+            let mut commands = Vec::new();
+            if let Some(voice) = body.voice {
+                commands.push(PlayerCommand::Voice(voice));
+            }
+            if let Some(filters) = body.filters {
+                commands.push(PlayerCommand::Filters(filters));
+            }
+            if let Some(opus) = body.opus {
+                commands.push(PlayerCommand::Opus(opus));
+            }
+            if let Some(volume) = body.volume {
+                commands.push(PlayerCommand::Volume(volume));
+            }
+            if let Some(paused) = body.paused {
+                commands.push(PlayerCommand::Pause(paused));
+            }
 
-                        if should_connect {
-                             player.connect(voice.clone(), session.user_id.clone());
-                        }
+            match track {
+                Some(Some(mut track)) => {
+                    if let Some(user_data) = user_data {
+                        track.user_data = user_data;
                     }
-
-                    if let Some(paused) = body.paused { player.paused = paused; }
-                    if let Some(vol) = body.volume { player.volume = vol; }
-
-                    if let Some(track_upd) = &body.track {
-                        if let Some(encoded) = &track_upd.encoded {
-                             if let Ok(decoded) = decode_track(encoded) {
-                                 player.track = Some(TrackData { encoded: encoded.clone(), info: decoded.info });
-                                 player.play();
-                             }
-                        } else {
-                            identifier_to_resolve = track_upd.identifier.clone();
-                        }
-                    } else if let Some(encoded) = &body.encoded_track {
-                        if let Ok(decoded) = decode_track(encoded) {
-                            player.track = Some(TrackData { encoded: encoded.clone(), info: decoded.info });
-                            player.play();
-                        }
+                    commands.push(PlayerCommand::Play {
+                        track,
+                        position: body.position.unwrap_or(0),
+                        no_replace: query.no_replace.or(body.no_replace).unwrap_or(false),
+                    });
+                },
+                Some(None) => commands.push(PlayerCommand::Stop),
+                None => {
+                    if let Some(user_data) = user_data {
+                        commands.push(PlayerCommand::UserData(user_data));
                     }
-                } else {
-                    return Ok::<_, warp::Rejection>(warp::reply::with_status("Session not found", StatusCode::NOT_FOUND).into_response());
-                }
-            }
-
-            if let Some(identifier) = identifier_to_resolve {
-                let res = aelira.sources.load_tracks(&identifier).await;
-                if let crate::models::load_tracks::LoadResultData::Track(track) = res.data {
-                    let manager = aelira.sessions.lock().unwrap();
-                    if let Some(session) = manager.sessions.get(&session_id) {
-                        let mut players = session.players.lock().unwrap();
-                        if let Some(player) = players.players.get_mut(&guild_id) {
-                            player.track = Some(TrackData { encoded: track.encoded, info: track.info });
-                            player.play();
-                        }
+                    if let Some(position) = body.position {
+                        commands.push(PlayerCommand::Seek(position));
                     }
-                } else {
-                     return Ok(warp::reply::with_status("Track resolution failed", StatusCode::BAD_REQUEST).into_response());
-                }
-            }
-
-            let manager = aelira.sessions.lock().unwrap();
-            if let Some(session) = manager.sessions.get(&session_id) {
-                let players = session.players.lock().unwrap();
-                 if let Some(player) = players.players.get(&guild_id) {
-                     return Ok(warp::reply::json(&player).into_response());
-                 }
+                },
             }
 
-            Ok(warp::reply::with_status("Session not found", StatusCode::NOT_FOUND).into_response())
+            let player = session.players.get_or_create(&guild_id, || PlayerContext {
+                user_id: session.user_id.clone(),
+                session: Arc::downgrade(&session),
+                sources: aelira.sources.clone(),
+                plugins: aelira.plugins.clone(),
+                opus: aelira.opus,
+                workers: aelira.workers.clone(),
+            });
+            let snapshot = player.update(commands).await;
+            Ok::<_, warp::Rejection>(warp::reply::json(&snapshot).into_response())
         });
 
-    let delete_player = player_by_id.clone()
+    let delete_player = player_by_id
         .and(warp::path::end())
         .and(warp::delete())
         .and(with_aelira.clone())
-        .map(|session_id: String, guild_id: String, aelira: AeliraRef| {
-            let manager = aelira.sessions.lock().unwrap();
-            if let Some(session) = manager.sessions.get(&session_id) {
-                let mut players = session.players.lock().unwrap();
-                if players.players.remove(&guild_id).is_some() {
-                    return StatusCode::NO_CONTENT.into_response();
-                }
-                return warp::reply::with_status("Player not found", StatusCode::NOT_FOUND).into_response();
+        .and_then(|session_id: String, guild_id: String, aelira: AeliraRef| async move {
+            let session = aelira.sessions.get(&session_id).ok_or_else(session_not_found)?;
+            if session.players.remove(&guild_id) {
+                return Ok(StatusCode::NO_CONTENT.into_response());
             }
-            warp::reply::with_status("Session not found", StatusCode::NOT_FOUND).into_response()
+            Err(ApiError::not_found("Player not found").reject())
         });
 
     update_session
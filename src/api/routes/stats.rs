@@ -1,32 +1,13 @@
 use warp::Filter;
 use serde::Serialize;
-use sysinfo::System;
 use crate::aelira::AeliraRef;
-
-#[derive(Serialize)]
-struct MemoryStats {
-    free: u64,
-    used: u64,
-    allocated: u64,
-    reservable: u64,
-}
-
-#[derive(Serialize)]
-#[serde(rename_all = "camelCase")]
-struct CpuStats {
-    cores: usize,
-    system_load: f32,
-    aelira_load: f32,
-}
+use crate::managers::stats::StatsSnapshot;
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 struct StatsResponse {
-    players: u32,
-    playing_players: u32,
-    uptime: u64,
-    memory: MemoryStats,
-    cpu: CpuStats,
+    #[serde(flatten)]
+    stats: StatsSnapshot,
     frame_stats: Option<()>,
 }
 
@@ -35,26 +16,10 @@ pub fn handler(aelira: AeliraRef) -> impl Filter<Extract = (impl warp::Reply,),
     warp::get()
         .and(with_aelira)
         .map(|aelira: AeliraRef| {
-            let sys = aelira.system.lock().unwrap();
-            let memory = MemoryStats {
-                free: sys.free_memory(),
-                used: sys.used_memory(),
-                allocated: sys.used_memory(),
-                reservable: sys.total_memory(),
-            };
-            let cpu = CpuStats {
-                cores: sys.cpus().len(),
-                system_load: sys.global_cpu_usage(),
-                aelira_load: 0.0,
-            };
             let stats = StatsResponse {
-                players: aelira.stats.players.load(std::sync::atomic::Ordering::Relaxed),
-                playing_players: aelira.stats.playing_players.load(std::sync::atomic::Ordering::Relaxed),
-                uptime: System::uptime() * 1000,
-                memory,
-                cpu,
+                stats: aelira.stats.snapshot(),
                 frame_stats: None,
             };
             warp::reply::json(&stats)
         })
-}
\ No newline at end of file
+}
@@ -1,6 +1,7 @@
 use warp::{Filter, Reply};
 use crate::aelira::AeliraRef;
-use warp::http::StatusCode;
+use crate::api::errors::ApiError;
+use crate::api::middlewares::auth::AuthError;
 
 pub fn handler(aelira: AeliraRef) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
     let with_aelira = warp::any().map(move || aelira.clone());
@@ -13,19 +14,18 @@ pub fn handler(aelira: AeliraRef) -> impl Filter<Extract = (impl warp::Reply,),
         .and(warp::header::header("client-name"))
         .and(warp::header::optional::<String>("session-id"))
         .and(with_aelira)
-        .map(|ws: warp::ws::Ws, auth: String, user_id: String, client_name: String, session_id: Option<String>, aelira: AeliraRef| {
-            if let Some(pass) = &aelira.password {
-                if *pass != auth {
-                    return warp::reply::with_status("Unauthorized", StatusCode::UNAUTHORIZED).into_response();
-                }
+        .and_then(|ws: warp::ws::Ws, auth: String, user_id: String, client_name: String, session_id: Option<String>, aelira: AeliraRef| async move {
+            if let Some(pass) = &aelira.password
+                && *pass != auth {
+                return Err(warp::reject::custom(AuthError));
             }
 
             if !user_id.chars().all(char::is_numeric) {
-                 return warp::reply::with_status("Invalid User ID", StatusCode::BAD_REQUEST).into_response();
+                 return Err(ApiError::bad_request("Invalid User ID").reject());
             }
 
-            ws.on_upgrade(move |socket| async move {
+            Ok(ws.on_upgrade(move |socket| async move {
                 crate::socket::handle_socket(socket, client_name, user_id, session_id, aelira).await;
-            }).into_response()
+            }).into_response())
         })
 }
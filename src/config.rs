@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::fs;
 use serde::Deserialize;
 
@@ -5,6 +6,12 @@ use serde::Deserialize;
 pub struct Config {
     pub server: ServerConfig,
     pub cluster: Option<ClusterConfig>,
+    pub sources: Option<SourcesConfig>,
+    pub search: Option<SearchConfig>,
+    pub mirroring: Option<MirroringConfig>,
+    pub cache: Option<CacheConfig>,
+    pub opus: Option<OpusConfig>,
+    pub plugins: Option<HashMap<String, toml::Value>>,
 }
 
 #[derive(Deserialize)]
@@ -12,11 +19,91 @@ pub struct ServerConfig {
     pub host: String,
     pub port: u16,
     pub password: Option<String>,
+    pub public_url: Option<String>,
 }
 
 #[derive(Deserialize)]
 pub struct ClusterConfig {
     pub workers: Option<usize>,
+    pub audio_workers: Option<usize>,
+}
+
+#[derive(Deserialize, Default)]
+pub struct SourcesConfig {
+    pub local: Option<LocalSourceConfig>,
+    pub external: Option<Vec<ExternalSourceConfig>>,
+}
+
+#[derive(Deserialize, Clone, Default)]
+pub struct LocalSourceConfig {
+    pub enabled: Option<bool>,
+    pub allowed_roots: Option<Vec<String>>,
+    pub recursive: Option<bool>,
+    pub library_roots: Option<Vec<String>>,
+    pub library_index: Option<String>,
+    pub library_watch: Option<bool>,
+    pub search_limit: Option<usize>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct ExternalSourceConfig {
+    pub name: String,
+    pub command: String,
+    pub args: Option<Vec<String>>,
+    pub search_terms: Option<Vec<String>>,
+    pub patterns: Option<Vec<String>>,
+    pub priority: Option<u32>,
+    pub timeout_ms: Option<u64>,
+    pub metadata_only: Option<bool>,
+}
+
+#[derive(Deserialize, Clone, Default)]
+pub struct SearchConfig {
+    pub default_prefix: Option<String>,
+    pub timeout_ms: Option<u64>,
+    pub source_timeouts: Option<HashMap<String, u64>>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize, Clone, Default)]
+pub struct MirroringConfig {
+    pub sources: Option<Vec<String>>,
+    pub duration_tolerance_ms: Option<u64>,
+}
+
+#[derive(Deserialize, Clone, Default)]
+pub struct CacheConfig {
+    pub enabled: Option<bool>,
+    pub max_entries: Option<usize>,
+    pub max_tracks: Option<usize>,
+    pub ttl_seconds: Option<u64>,
+    pub excluded_sources: Option<Vec<String>>,
+}
+
+/// Node-wide encoder settings. Players can override any field through `opus` in a player update.
+#[derive(Deserialize, Clone, Default)]
+pub struct OpusConfig {
+    pub bitrate: Option<u32>,
+    pub complexity: Option<u8>,
+    pub vbr: Option<bool>,
+    pub fec: Option<bool>,
+    #[serde(alias = "packetLoss")]
+    pub packet_loss: Option<u8>,
+    pub dtx: Option<bool>,
+}
+
+impl ServerConfig {
+    pub fn public_url(&self) -> String {
+        if let Some(url) = &self.public_url {
+            return url.trim_end_matches('/').to_string();
+        }
+
+        let host = match self.host.as_str() {
+            "0.0.0.0" | "::" => "127.0.0.1",
+            host => host,
+        };
+        format!("http://{}:{}", host, self.port)
+    }
 }
 
 impl Config {
@@ -25,4 +112,20 @@ impl Config {
         let config: Config = toml::from_str(&config_data)?;
         Ok(config)
     }
+
+    /// Runtime worker threads; 0 or unset means one per CPU.
+    pub fn workers(&self) -> usize {
+        match self.cluster.as_ref().and_then(|c| c.workers).unwrap_or(0) {
+            0 => std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            n => n,
+        }
+    }
+
+    /// Audio worker threads; 0 or unset means the same as `workers`.
+    pub fn audio_workers(&self) -> usize {
+        match self.cluster.as_ref().and_then(|c| c.audio_workers).unwrap_or(0) {
+            0 => self.workers(),
+            n => n,
+        }
+    }
 }
\ No newline at end of file
@@ -7,26 +7,19 @@ pub mod utils;
 pub mod playback;
 pub mod sources;
 pub mod models;
+pub mod plugins;
 
 use std::fs;
 use config::Config;
+use sysinfo::{System, RefreshKind, CpuRefreshKind, MemoryRefreshKind};
 use warp::Filter;
 use utils::{log, Level};
 
 fn main() {
     let config = Config::load().expect("Failed to load configuration");
 
-    let workers = if let Some(cluster) = &config.cluster {
-        match cluster.workers.unwrap_or(0) {
-            0 => std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
-            n => n,
-        }
-    } else {
-        std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
-    };
-
     tokio::runtime::Builder::new_multi_thread()
-        .worker_threads(workers)
+        .worker_threads(config.workers())
         .enable_all()
         .build()
         .unwrap()
@@ -50,77 +43,51 @@ async fn async_main(config: Config) {
 
     let aelira_clone = aelira.clone();
     tokio::spawn(async move {
+        let refresh = RefreshKind::nothing()
+            .with_cpu(CpuRefreshKind::nothing().with_cpu_usage())
+            .with_memory(MemoryRefreshKind::nothing().with_ram());
+        let mut sys = System::new_with_specifics(refresh);
+
         let mut interval = tokio::time::interval(std::time::Duration::from_secs(1));
         loop {
             interval.tick().await;
-            {
-                let mut sys = aelira_clone.system.lock().unwrap();
-                sys.refresh_cpu_usage();
-                sys.refresh_memory();
-            }
+            sys.refresh_cpu_usage();
+            sys.refresh_memory();
+            aelira_clone.stats.set_system(&sys);
 
+            let sessions = aelira_clone.sessions.all();
             let mut total_players = 0;
             let mut playing_players = 0;
-            {
-                let sessions = aelira_clone.sessions.lock().unwrap();
-                for session in sessions.sessions.values() {
-                    let players = session.players.lock().unwrap();
-                    total_players += players.players.len() as u32;
-                    for player in players.players.values() {
-                        if !player.paused && player.track.is_some() {
-                            playing_players += 1;
-                        }
-                    }
-                }
+            let players: Vec<_> = sessions.iter().map(|session| session.players.snapshots()).collect();
+            for snapshots in &players {
+                total_players += snapshots.len() as u32;
+                playing_players += snapshots.iter()
+                    .filter(|p| !p.paused && p.track.is_some())
+                    .count() as u32;
             }
 
             aelira_clone.stats.set_players(total_players);
             aelira_clone.stats.set_playing_players(playing_players);
 
-            let stats_payload = {
-                let sys = aelira_clone.system.lock().unwrap();
-                serde_json::json!({
-                    "op": "stats",
-                    "players": total_players,
-                    "playingPlayers": playing_players,
-                    "uptime": sysinfo::System::uptime() * 1000,
-                    "memory": {
-                        "free": sys.free_memory(),
-                        "used": sys.used_memory(),
-                        "allocated": sys.used_memory(),
-                        "reservable": sys.total_memory(),
-                    },
-                    "cpu": {
-                        "cores": sys.cpus().len(),
-                        "systemLoad": sys.global_cpu_usage(),
-                        "aeliraLoad": 0.0,
-                    }
-                }).to_string()
-            };
-
-            let sessions = aelira_clone.sessions.lock().unwrap();
-            for session in sessions.sessions.values() {
-                {
-                    let sender = session.sender.lock().unwrap();
-                    let _ = sender.send(warp::ws::Message::text(&stats_payload));
-                }
+            let mut stats_payload = serde_json::to_value(aelira_clone.stats.snapshot()).unwrap_or_default();
+            if let Some(payload) = stats_payload.as_object_mut() {
+                payload.insert("op".to_string(), "stats".into());
+            }
+
+            for (session, snapshots) in sessions.iter().zip(&players) {
+                session.send(&stats_payload);
+
+                let updates: Vec<serde_json::Value> = snapshots.iter()
+                    .filter(|p| p.track.is_some())
+                    .map(|player| serde_json::json!({
+                        "op": "playerUpdate",
+                        "guildId": player.guild_id,
+                        "state": player.state,
+                    }))
+                    .collect();
 
-                let players = session.players.lock().unwrap();
-                for player in players.players.values() {
-                    if player.track.is_some() {
-                        let update = serde_json::json!({
-                            "op": "playerUpdate",
-                            "guildId": player.guild_id,
-                            "state": {
-                                "time": std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis(),
-                                "position": player.state.position,
-                                "connected": player.connection.is_some(),
-                                "ping": player.state.ping
-                            }
-                        }).to_string();
-                        let sender = session.sender.lock().unwrap();
-                        let _ = sender.send(warp::ws::Message::text(&update));
-                    }
+                for update in &updates {
+                    session.send(update);
                 }
             }
         }
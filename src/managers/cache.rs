@@ -0,0 +1,316 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use crate::config::CacheConfig;
+use crate::models::load_tracks::{LoadResultData, LoadTracksResponse};
+
+const DEFAULT_MAX_ENTRIES: usize = 1000;
+const DEFAULT_TTL_SECS: u64 = 600;
+const DEFAULT_MAX_TRACKS: usize = 500;
+
+struct CacheEntry {
+    response: LoadTracksResponse,
+    expires: Instant,
+    tick: u64,
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<String, CacheEntry>,
+    recency: BTreeMap<u64, String>,
+    tick: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheStats {
+    pub enabled: bool,
+    pub entries: usize,
+    pub max_entries: usize,
+    pub max_tracks: usize,
+    pub ttl_seconds: u64,
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub expirations: u64,
+}
+
+pub struct TrackCache {
+    enabled: bool,
+    max_entries: usize,
+    max_tracks: usize,
+    ttl: Duration,
+    excluded_sources: HashSet<String>,
+    state: Mutex<CacheState>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    expirations: AtomicU64,
+}
+
+impl CacheState {
+    fn remove(&mut self, identifier: &str) -> Option<CacheEntry> {
+        let entry = self.entries.remove(identifier)?;
+        self.recency.remove(&entry.tick);
+        Some(entry)
+    }
+
+    fn touch(&mut self, identifier: &str) {
+        self.tick += 1;
+        let tick = self.tick;
+        if let Some(entry) = self.entries.get_mut(identifier) {
+            self.recency.remove(&entry.tick);
+            entry.tick = tick;
+            self.recency.insert(tick, identifier.to_string());
+        }
+    }
+}
+
+impl Default for TrackCache {
+    fn default() -> Self {
+        Self::new(CacheConfig::default())
+    }
+}
+
+impl TrackCache {
+    pub fn new(config: CacheConfig) -> Self {
+        let max_entries = config.max_entries.unwrap_or(DEFAULT_MAX_ENTRIES);
+        Self {
+            enabled: config.enabled.unwrap_or(true) && max_entries > 0,
+            max_entries,
+            max_tracks: config.max_tracks.unwrap_or(DEFAULT_MAX_TRACKS),
+            ttl: Duration::from_secs(config.ttl_seconds.unwrap_or(DEFAULT_TTL_SECS)),
+            excluded_sources: config.excluded_sources.unwrap_or_default().into_iter().collect(),
+            state: Mutex::new(CacheState::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+            expirations: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get(&self, identifier: &str) -> Option<LoadTracksResponse> {
+        if !self.enabled {
+            return None;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let expired = match state.entries.get(identifier) {
+            Some(entry) => entry.expires <= Instant::now(),
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                return None;
+            }
+        };
+
+        if expired {
+            state.remove(identifier);
+            self.expirations.fetch_add(1, Ordering::Relaxed);
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+
+        state.touch(identifier);
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        state.entries.get(identifier).map(|entry| entry.response.clone())
+    }
+
+    fn cacheable(&self, response: &LoadTracksResponse) -> bool {
+        let tracks = match &response.data {
+            LoadResultData::Track(track) => std::slice::from_ref(track.as_ref()),
+            LoadResultData::Playlist(playlist) => playlist.tracks.as_slice(),
+            LoadResultData::Search(tracks) => tracks.as_slice(),
+            LoadResultData::Empty(_) | LoadResultData::Error(_) => return false,
+        };
+
+        tracks.len() <= self.max_tracks
+            && !tracks.iter().any(|t| self.excluded_sources.contains(&t.info.source_name))
+    }
+
+    pub fn insert(&self, identifier: &str, response: &LoadTracksResponse) {
+        if !self.enabled || !self.cacheable(response) {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.remove(identifier);
+
+        while state.entries.len() >= self.max_entries {
+            let Some((_, oldest)) = state.recency.pop_first() else { break };
+            state.entries.remove(&oldest);
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+
+        state.tick += 1;
+        let tick = state.tick;
+        state.recency.insert(tick, identifier.to_string());
+        state.entries.insert(identifier.to_string(), CacheEntry {
+            response: response.clone(),
+            expires: Instant::now() + self.ttl,
+            tick,
+        });
+    }
+
+    pub fn purge(&self, identifier: Option<&str>) -> usize {
+        let mut state = self.state.lock().unwrap();
+        match identifier {
+            Some(identifier) => state.remove(identifier).map_or(0, |_| 1),
+            None => {
+                let count = state.entries.len();
+                *state = CacheState::default();
+                count
+            }
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            enabled: self.enabled,
+            entries: self.state.lock().unwrap().entries.len(),
+            max_entries: self.max_entries,
+            max_tracks: self.max_tracks,
+            ttl_seconds: self.ttl.as_secs(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            expirations: self.expirations.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::load_tracks::{ErrorData, LoadType, Severity};
+    use crate::utils::encoding::{encode_track, DecodedInfo, DecodedTrack};
+
+    fn track(source: &str, identifier: &str) -> DecodedTrack {
+        let info = DecodedInfo {
+            title: identifier.to_string(),
+            author: "Artist".to_string(),
+            length: 200_000,
+            identifier: identifier.to_string(),
+            is_stream: false,
+            uri: None,
+            artwork_url: None,
+            isrc: None,
+            source_name: source.to_string(),
+            position: 0,
+            source_data: Vec::new(),
+        };
+        DecodedTrack {
+            encoded: encode_track(&info),
+            info,
+            plugin_info: serde_json::json!({}),
+            user_data: serde_json::json!({}),
+        }
+    }
+
+    fn search(source: &str, count: usize) -> LoadTracksResponse {
+        LoadTracksResponse {
+            load_type: LoadType::Search,
+            data: LoadResultData::Search((0..count).map(|i| track(source, &i.to_string())).collect()),
+        }
+    }
+
+    fn cached(cache: &TrackCache, identifiers: &[&str]) -> Vec<bool> {
+        identifiers.iter().map(|i| cache.get(i).is_some()).collect()
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used_entry() {
+        let cache = TrackCache::new(CacheConfig { max_entries: Some(3), ..Default::default() });
+        for identifier in ["a", "b", "c"] {
+            cache.insert(identifier, &search("youtube", 1));
+        }
+
+        assert!(cache.get("a").is_some());
+        cache.insert("d", &search("youtube", 1));
+        cache.insert("c", &search("youtube", 2));
+        cache.insert("e", &search("youtube", 1));
+
+        assert_eq!(cached(&cache, &["a", "b", "c", "d", "e"]), [false, false, true, true, true]);
+        assert_eq!(cache.stats().evictions, 2);
+        assert_eq!(cache.stats().entries, 3);
+    }
+
+    #[test]
+    fn expires_entries_after_the_ttl() {
+        let cache = TrackCache::new(CacheConfig { ttl_seconds: Some(60), ..Default::default() });
+        cache.insert("a", &search("youtube", 1));
+        cache.insert("b", &search("youtube", 1));
+        assert!(cache.get("a").is_some());
+
+        cache.state.lock().unwrap().entries.get_mut("a").unwrap().expires = Instant::now();
+        assert_eq!(cached(&cache, &["a", "b"]), [false, true]);
+        assert_eq!(cache.stats().expirations, 1);
+        assert_eq!(cache.stats().entries, 1);
+
+        let immediate = TrackCache::new(CacheConfig { ttl_seconds: Some(0), ..Default::default() });
+        immediate.insert("a", &search("youtube", 1));
+        assert!(immediate.get("a").is_none());
+    }
+
+    #[test]
+    fn skips_results_over_max_tracks() {
+        let cache = TrackCache::new(CacheConfig { max_tracks: Some(3), ..Default::default() });
+        cache.insert("three", &search("youtube", 3));
+        cache.insert("four", &search("youtube", 4));
+        assert_eq!(cached(&cache, &["three", "four"]), [true, false]);
+    }
+
+    #[test]
+    fn skips_results_from_excluded_sources() {
+        let cache = TrackCache::new(CacheConfig { excluded_sources: Some(vec!["http".to_string()]), ..Default::default() });
+        let mixed = LoadTracksResponse {
+            load_type: LoadType::Search,
+            data: LoadResultData::Search(vec![track("youtube", "a"), track("http", "b")]),
+        };
+        cache.insert("youtube", &search("youtube", 2));
+        cache.insert("http", &search("http", 1));
+        cache.insert("mixed", &mixed);
+        assert_eq!(cached(&cache, &["youtube", "http", "mixed"]), [true, false, false]);
+    }
+
+    #[test]
+    fn only_caches_results_with_tracks() {
+        let cache = TrackCache::new(CacheConfig::default());
+        let responses = [
+            ("error", LoadTracksResponse::error(ErrorData::new("Failed", Severity::Fault, "Test"))),
+            ("empty", LoadTracksResponse { load_type: LoadType::Empty, data: LoadResultData::Empty(serde_json::json!({})) }),
+            ("track", LoadTracksResponse { load_type: LoadType::Track, data: LoadResultData::Track(Box::new(track("youtube", "a"))) }),
+        ];
+        for (identifier, response) in &responses {
+            cache.insert(identifier, response);
+        }
+        assert_eq!(cached(&cache, &["error", "empty", "track"]), [false, false, true]);
+    }
+
+    #[test]
+    fn counts_hits_and_misses() {
+        let cache = TrackCache::new(CacheConfig::default());
+        assert!(cache.get("a").is_none());
+        cache.insert("a", &search("youtube", 1));
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("b").is_none());
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (2, 2, 1));
+        assert_eq!(cache.purge(Some("a")), 1);
+        assert_eq!(cache.purge(None), 0);
+    }
+
+    #[test]
+    fn does_nothing_when_disabled() {
+        for config in [CacheConfig { enabled: Some(false), ..Default::default() }, CacheConfig { max_entries: Some(0), ..Default::default() }] {
+            let cache = TrackCache::new(config);
+            cache.insert("a", &search("youtube", 1));
+            assert!(cache.get("a").is_none());
+            let stats = cache.stats();
+            assert_eq!((stats.enabled, stats.entries, stats.misses), (false, 0, 0));
+        }
+    }
+}
@@ -1,5 +1,8 @@
+pub mod cache;
 pub mod sessions;
 pub mod players;
+pub mod plugins;
+pub mod search;
 pub mod sources;
 pub mod stats;
 pub mod route_planner;
\ No newline at end of file
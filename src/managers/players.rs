@@ -1,27 +1,32 @@
+use crate::config::OpusConfig;
+use crate::playback::control::PlaybackControl;
+use crate::playback::opus::OpusSettings;
+use crate::playback::workers::AudioWorkers;
 use crate::playback::processor::AudioProcessor;
 use crate::playback::voice::connection::VoiceConnection;
-use crate::playback::voice::stream::AudioStream;
-use crate::sources::local::LocalSource;
-use crate::utils::encoding::DecodedInfo;
+use crate::playback::voice::stream::{AudioStream, Prefetch};
+use crate::managers::plugins::{PluginEvent, PluginManager};
+use crate::managers::sessions::Session;
+use crate::managers::sources::SourceManager;
+use crate::models::load_tracks::{ErrorData, Severity};
+use crate::utils::encoding::DecodedTrack;
 use crate::utils::{log, Level};
 use futures_util::stream;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use dashmap::DashMap;
+use std::sync::{Arc, Weak};
+use tokio::runtime::Handle;
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::task::JoinHandle;
 
-#[derive(Serialize, Clone)]
-#[serde(rename_all = "camelCase")]
-pub struct TrackData {
-    pub encoded: String,
-    pub info: DecodedInfo,
-}
+/// Filters the player applies itself; plugins add their own through `pluginFilters`.
+pub const BUILT_IN_FILTERS: &[&str] = &["volume"];
 
 #[derive(Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct PlayerState {
     pub time: u64,
-    pub position: i64,
+    pub position: u64,
     pub connected: bool,
     pub ping: i64,
 }
@@ -32,25 +37,80 @@ pub struct VoiceState {
     pub token: String,
     pub endpoint: String,
     pub session_id: String,
+    /// The voice channel's bitrate in bits per second, used when the Opus bitrate is automatic.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel_bitrate: Option<u32>,
 }
 
-#[derive(Serialize)]
+impl VoiceState {
+    fn same_server(&self, other: &VoiceState) -> bool {
+        self.token == other.token && self.endpoint == other.endpoint && self.session_id == other.session_id
+    }
+}
+
+/// The player as REST clients and `playerUpdate` see it. Only the player's actor writes it.
+#[derive(Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
-pub struct Player {
+pub struct PlayerSnapshot {
     pub guild_id: String,
-    pub track: Option<TrackData>,
+    pub track: Option<DecodedTrack>,
     pub volume: u16,
     pub paused: bool,
     pub state: PlayerState,
     pub voice: Option<VoiceState>,
+    pub filters: serde_json::Value,
+    pub opus: OpusSettings,
     #[serde(skip)]
-    pub connection: Option<Arc<VoiceConnection>>,
+    control: Option<Arc<PlaybackControl>>,
 }
 
-impl Player {
-    pub fn new(guild_id: String) -> Self {
-        Self {
-            guild_id,
+/// A change requested of a player. Commands are applied in order by the player's actor.
+pub enum PlayerCommand {
+    Play { track: Box<DecodedTrack>, position: u64, no_replace: bool },
+    Stop,
+    Pause(bool),
+    Seek(u64),
+    Volume(u16),
+    Filters(serde_json::Value),
+    Opus(OpusConfig),
+    Voice(VoiceState),
+    UserData(serde_json::Value),
+    Destroy,
+}
+
+enum PlaybackEvent {
+    Started,
+    Exception(ErrorData),
+    Ended(&'static str),
+}
+
+struct Envelope {
+    commands: Vec<PlayerCommand>,
+    reply: Option<oneshot::Sender<()>>,
+}
+
+/// Everything a player needs from outside to load and announce tracks.
+pub struct PlayerContext {
+    pub user_id: String,
+    pub session: Weak<Session>,
+    pub sources: Arc<SourceManager>,
+    pub plugins: Arc<PluginManager>,
+    pub opus: OpusSettings,
+    pub workers: Arc<AudioWorkers>,
+}
+
+/// Cheap handle to a player's actor: commands go in through the channel and state comes out
+/// through the snapshot, so REST handlers never touch the playback task directly.
+#[derive(Clone)]
+pub struct PlayerHandle {
+    commands: mpsc::UnboundedSender<Envelope>,
+    snapshot: watch::Receiver<PlayerSnapshot>,
+}
+
+impl PlayerHandle {
+    fn spawn(guild_id: String, context: PlayerContext) -> Self {
+        let snapshot = PlayerSnapshot {
+            guild_id: guild_id.clone(),
             track: None,
             volume: 100,
             paused: false,
@@ -61,117 +121,667 @@ impl Player {
                 ping: -1,
             },
             voice: None,
+            filters: serde_json::json!({}),
+            opus: context.opus,
+            control: None,
+        };
+
+        let (command_tx, command_rx) = mpsc::unbounded_channel();
+        let (event_tx, event_rx) = mpsc::unbounded_channel();
+        let (publisher, snapshot_rx) = watch::channel(snapshot.clone());
+
+        let player = Player {
+            guild_id,
+            snapshot,
+            publisher,
+            context: Arc::new(context),
+            events: event_tx,
             connection: None,
+            voice: watch::channel(None).0,
+            playback: None,
+            pending_position: 0,
+            generation: 0,
+        };
+        tokio::spawn(player.run(command_rx, event_rx));
+
+        Self {
+            commands: command_tx,
+            snapshot: snapshot_rx,
+        }
+    }
+
+    pub fn send(&self, command: PlayerCommand) {
+        let _ = self.commands.send(Envelope { commands: vec![command], reply: None });
+    }
+
+    /// Applies the commands and returns the state once the actor has processed all of them.
+    pub async fn update(&self, commands: Vec<PlayerCommand>) -> PlayerSnapshot {
+        let (reply, done) = oneshot::channel();
+        if self.commands.send(Envelope { commands, reply: Some(reply) }).is_ok() {
+            let _ = done.await;
         }
+        self.snapshot()
+    }
+
+    pub fn snapshot(&self) -> PlayerSnapshot {
+        let mut snapshot = self.snapshot.borrow().clone();
+        if let Some(control) = &snapshot.control {
+            snapshot.state.position = control.position();
+        }
+        snapshot.state.time = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        snapshot
+    }
+}
+
+struct Playback {
+    id: u64,
+    control: Arc<PlaybackControl>,
+    task: JoinHandle<()>,
+    announce: bool,
+}
+
+/// The actor owning a guild's voice connection and playback task.
+struct Player {
+    guild_id: String,
+    snapshot: PlayerSnapshot,
+    publisher: watch::Sender<PlayerSnapshot>,
+    context: Arc<PlayerContext>,
+    events: mpsc::UnboundedSender<(u64, PlaybackEvent)>,
+    connection: Option<(Arc<VoiceConnection>, JoinHandle<()>)>,
+    /// The connection playback sends on. Replacing it hands the running track over.
+    voice: watch::Sender<Option<Arc<VoiceConnection>>>,
+    playback: Option<Playback>,
+    /// Where the current track starts once the player connects to voice.
+    pending_position: u64,
+    generation: u64,
+}
+
+impl Player {
+    async fn run(mut self, mut commands: mpsc::UnboundedReceiver<Envelope>, mut events: mpsc::UnboundedReceiver<(u64, PlaybackEvent)>) {
+        loop {
+            tokio::select! {
+                envelope = commands.recv() => {
+                    let Some(envelope) = envelope else { break };
+                    let mut destroyed = false;
+                    for command in envelope.commands {
+                        destroyed |= matches!(command, PlayerCommand::Destroy);
+                        self.apply(command);
+                    }
+                    self.publish();
+                    if let Some(reply) = envelope.reply {
+                        let _ = reply.send(());
+                    }
+                    if destroyed {
+                        return;
+                    }
+                },
+                Some((id, event)) = events.recv() => {
+                    self.on_playback(id, event);
+                    self.publish();
+                },
+            }
+        }
+
+        self.apply(PlayerCommand::Destroy);
+    }
+
+    fn publish(&mut self) {
+        self.snapshot.control = self.playback.as_ref().map(|p| p.control.clone());
+        self.snapshot.state.connected = self.connection.is_some();
+        self.publisher.send_replace(self.snapshot.clone());
     }
 
-    pub fn connect(&mut self, voice: VoiceState, user_id: String) {
+    fn apply(&mut self, command: PlayerCommand) {
+        match command {
+            PlayerCommand::Play { track, position, no_replace } => {
+                if no_replace && self.snapshot.track.is_some() {
+                    return;
+                }
+                self.end_playback("replaced");
+                self.snapshot.track = Some(*track);
+                self.start(position, true);
+            },
+            PlayerCommand::Stop => {
+                self.end_playback("stopped");
+                self.snapshot.track = None;
+            },
+            PlayerCommand::Pause(paused) => {
+                self.snapshot.paused = paused;
+                if let Some(playback) = &self.playback {
+                    playback.control.set_paused(paused);
+                    if let Some((connection, _)) = &self.connection {
+                        let connection = connection.clone();
+                        tokio::spawn(async move { connection.set_speaking(!paused).await });
+                    }
+                }
+            },
+            PlayerCommand::Seek(position) => {
+                if self.snapshot.track.as_ref().is_none_or(|t| t.info.is_stream) {
+                    return;
+                }
+                match &self.playback {
+                    Some(playback) if position >= playback.control.position() => playback.control.seek_to(position),
+                    // Sources only read forward, so seeking back reopens the stream.
+                    Some(_) => self.restart(position),
+                    // The track hasn't started because there is no connection yet.
+                    None => self.pending_position = position,
+                }
+            },
+            PlayerCommand::Volume(volume) => {
+                self.snapshot.volume = volume.min(1000);
+                self.update_gain();
+            },
+            PlayerCommand::Filters(filters) => {
+                self.snapshot.filters = filters;
+                self.update_gain();
+                self.update_filters();
+            },
+            PlayerCommand::Opus(overrides) => {
+                self.snapshot.opus = self.snapshot.opus.merge(&overrides);
+                self.update_opus();
+            },
+            PlayerCommand::Voice(voice) => {
+                if let Some(current) = &mut self.snapshot.voice
+                    && current.same_server(&voice) {
+                    current.channel_bitrate = voice.channel_bitrate.or(current.channel_bitrate);
+                    self.update_opus();
+                    return;
+                }
+                self.connect(voice);
+                self.update_opus();
+
+                // A running track follows the new connection on its own; a track set before
+                // the player had a connection starts now.
+                if self.playback.is_none() {
+                    let position = std::mem::take(&mut self.pending_position);
+                    self.start(position, true);
+                }
+            },
+            PlayerCommand::UserData(user_data) => {
+                if let Some(track) = &mut self.snapshot.track {
+                    track.user_data = user_data;
+                }
+            },
+            PlayerCommand::Destroy => {
+                self.end_playback("cleanup");
+                self.snapshot.track = None;
+                if let Some((_, task)) = self.connection.take() {
+                    task.abort();
+                }
+                self.voice.send_replace(None);
+            },
+        }
+    }
+
+    fn gain(&self) -> f32 {
+        let filter = self.snapshot.filters.get("volume").and_then(|v| v.as_f64()).unwrap_or(1.0);
+        (self.snapshot.volume as f64 / 100.0 * filter.clamp(0.0, 5.0)) as f32
+    }
+
+    fn update_gain(&self) {
+        if let Some(playback) = &self.playback {
+            playback.control.set_gain(self.gain());
+        }
+    }
+
+    fn update_filters(&self) {
+        if let Some(playback) = &self.playback {
+            playback.control.set_filters(self.context.plugins.create_filters(&self.snapshot.filters));
+        }
+    }
+
+    /// The encoder settings with an automatic bitrate resolved against the voice channel.
+    fn opus(&self) -> OpusSettings {
+        self.snapshot.opus.for_channel(self.snapshot.voice.as_ref().and_then(|v| v.channel_bitrate))
+    }
+
+    fn update_opus(&self) {
+        if let Some(playback) = &self.playback {
+            playback.control.set_opus(self.opus());
+        }
+    }
+
+    fn connect(&mut self, voice: VoiceState) {
         log(Level::Info, "Player", format!("Connecting to voice: {} (Session: {})", voice.endpoint, voice.session_id));
 
-        let conn = Arc::new(VoiceConnection::new(
+        if let Some((_, task)) = self.connection.take() {
+            task.abort();
+        }
+
+        let connection = Arc::new(VoiceConnection::new(
             self.guild_id.clone(),
             voice.session_id.clone(),
             voice.token.clone(),
             voice.endpoint.clone(),
-            user_id,
+            self.context.user_id.clone(),
         ));
 
-        self.connection = Some(conn.clone());
-        self.voice = Some(voice);
+        let runner = connection.clone();
+        let task = tokio::spawn(async move {
+            runner.run().await;
+        });
 
-        tokio::spawn(async move {
-            conn.run().await;
+        self.voice.send_replace(Some(connection.clone()));
+        self.connection = Some((connection, task));
+        self.snapshot.voice = Some(voice);
+    }
+
+    /// Starts a playback task for the current track. `announce` is false when an already
+    /// started track is reopened, so clients don't see a second TrackStartEvent.
+    fn start(&mut self, position: u64, announce: bool) {
+        let Some(track) = self.snapshot.track.clone() else { return };
+        if self.connection.is_none() {
+            log(Level::Warn, "Player", "No active voice connection to play on");
+            self.pending_position = position;
+            return;
+        }
+
+        log(Level::Debug, "Player", format!("Play request for track: {}", track.info.identifier));
+
+        self.generation += 1;
+        let control = Arc::new(PlaybackControl::new(position, self.snapshot.paused, self.gain(), self.opus()));
+        control.set_filters(self.context.plugins.create_filters(&self.snapshot.filters));
+        let task = tokio::spawn(playback(
+            self.generation,
+            self.guild_id.clone(),
+            track,
+            self.voice.subscribe(),
+            control.clone(),
+            self.context.clone(),
+            self.events.clone(),
+        ));
+
+        self.playback = Some(Playback {
+            id: self.generation,
+            control,
+            task,
+            announce,
         });
     }
 
-    pub fn play(&mut self) {
-        let track = match &self.track {
-            Some(t) => t,
-            None => {
-                log(Level::Warn, "Player", "Attempted to play without track");
-                return;
+    fn restart(&mut self, position: u64) {
+        let announce = match self.playback.take() {
+            Some(playback) => {
+                playback.task.abort();
+                playback.announce
             },
+            None => true,
         };
+        self.start(position, announce);
+    }
 
-        log(Level::Debug, "Player", format!("Play request for track: {}", track.info.identifier));
+    /// Stops the playback task and reports the current track as ended.
+    fn end_playback(&mut self, reason: &'static str) {
+        if let Some(playback) = self.playback.take() {
+            playback.task.abort();
+            if let Some((connection, _)) = &self.connection {
+                let connection = connection.clone();
+                tokio::spawn(async move {
+                    connection.set_speaking(false).await;
+                    connection.send_silence().await;
+                });
+            }
+        }
 
-        if let Some(conn) = &self.connection {
-            let conn_arc = conn.clone();
-            let identifier = track.info.identifier.clone();
-
-            tokio::spawn(async move {
-                let (udp, crypto) = {
-                    let mut attempts = 0;
-                    loop {
-                        let udp_lock = conn_arc.udp.lock().await;
-                        let crypto_lock = conn_arc.crypto.lock().await;
-
-                        if let (Some(udp), Some(crypto)) = (udp_lock.as_ref(), crypto_lock.as_ref()) {
-                            let udp_final = Arc::new(Mutex::new(udp.clone()));
-                            let crypto_final = Arc::new(crypto.clone());
-                            break (udp_final, crypto_final);
-                        }
-
-                        drop(udp_lock);
-                        drop(crypto_lock);
-
-                        if attempts > 50 {
-                            log(Level::Error, "Player", "Timeout waiting for voice connection");
-                            return;
-                        }
-                        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-                        attempts += 1;
-                    }
-                };
-
-                let stream_handler = AudioStream::new(udp, crypto);
-                use crate::managers::sources::Source;
-
-                match LocalSource.load_stream(&identifier).await {
-                    Some(framed) => {
-                        log(Level::Info, "Player", format!("Stream loaded for: {}", identifier));
-                        conn_arc.set_speaking(true).await;
-
-                        let processor: AudioProcessor<tokio::fs::File> = AudioProcessor::new(framed.into_inner(), "webm/opus").await;
-                        let source_stream = stream::unfold(processor, |mut proc: AudioProcessor<tokio::fs::File>| async move {
-                            match proc.next_packet().await {
-                                Some(Ok(packet)) => Some((Ok(packet), proc)),
-                                Some(Err(e)) => {
-                                    log(Level::Error, "Player", format!("Error reading packet: {}", e));
-                                    Some((Err(e), proc))
-                                },
-                                None => None,
-                            }
-                        });
-
-                        stream_handler.play(Box::pin(source_stream)).await;
-                        conn_arc.set_speaking(false).await;
-                        conn_arc.send_silence().await;
-                        log(Level::Info, "Player", "Playback finished");
-                    },
-                    None => {
-                        log(Level::Error, "Player", format!("Failed to load stream for: {}", identifier));
-                    }
+        if let Some(track) = &self.snapshot.track {
+            self.send_event("TrackEndEvent", serde_json::json!({ "reason": reason }));
+            self.context.plugins.emit(PluginEvent::TrackEnd { guild_id: &self.guild_id, track: &track.info, reason });
+        }
+    }
+
+    fn on_playback(&mut self, id: u64, event: PlaybackEvent) {
+        if self.playback.as_ref().map(|p| p.id) != Some(id) {
+            return;
+        }
+
+        match event {
+            PlaybackEvent::Started => {
+                let Some(playback) = &mut self.playback else { return };
+                if !std::mem::take(&mut playback.announce) {
+                    return;
                 }
-            });
-        } else {
-            log(Level::Warn, "Player", "No active voice connection to play on");
+                if let Some(track) = &self.snapshot.track {
+                    self.send_event("TrackStartEvent", serde_json::json!({}));
+                    self.context.plugins.emit(PluginEvent::TrackStart { guild_id: &self.guild_id, track: &track.info });
+                }
+            },
+            PlaybackEvent::Exception(exception) => {
+                self.send_event("TrackExceptionEvent", serde_json::json!({ "exception": exception }));
+            },
+            PlaybackEvent::Ended(reason) => {
+                self.playback = None;
+                if let Some(track) = self.snapshot.track.take() {
+                    self.send_track_event(&track, "TrackEndEvent", serde_json::json!({ "reason": reason }));
+                    self.context.plugins.emit(PluginEvent::TrackEnd { guild_id: &self.guild_id, track: &track.info, reason });
+                }
+            },
+        }
+    }
+
+    fn send_event(&self, event: &str, fields: serde_json::Value) {
+        if let Some(track) = &self.snapshot.track {
+            self.send_track_event(track, event, fields);
+        }
+    }
+
+    fn send_track_event(&self, track: &DecodedTrack, event: &str, fields: serde_json::Value) {
+        let Some(session) = self.context.session.upgrade() else { return };
+        let mut payload = serde_json::json!({
+            "op": "event",
+            "type": event,
+            "guildId": self.guild_id,
+            "track": track,
+        });
+        if let (Some(payload), serde_json::Value::Object(fields)) = (payload.as_object_mut(), fields) {
+            payload.extend(fields);
         }
+        session.send(&payload);
+    }
+}
+
+/// Loads the track and sends it to the voice connection, reporting progress to the actor.
+async fn playback(
+    id: u64,
+    guild_id: String,
+    track: DecodedTrack,
+    voice: watch::Receiver<Option<Arc<VoiceConnection>>>,
+    control: Arc<PlaybackControl>,
+    context: Arc<PlayerContext>,
+    events: mpsc::UnboundedSender<(u64, PlaybackEvent)>,
+) {
+    let report = |event| {
+        let _ = events.send((id, event));
+    };
+    let fail = |exception: ErrorData| {
+        report(PlaybackEvent::Exception(exception));
+        report(PlaybackEvent::Ended("loadFailed"));
+    };
+
+    let identifier = track.info.identifier.clone();
+    let mut stream_handler = AudioStream::new(voice);
+
+    let mut media = match context.sources.load_stream(&track.info).await {
+        Ok(media) => media,
+        Err(exception) => {
+            log(Level::Error, "Player", format!("Failed to load stream for {}: {}", identifier, exception.cause));
+            fail(exception);
+            return;
+        }
+    };
+    log(Level::Info, "Player", format!("Stream loaded for: {} ({:?}/{:?})", identifier, media.info.container, media.info.codec));
+
+    if let Some(mut metadata) = media.metadata.take() {
+        let context = context.clone();
+        let guild_id = guild_id.clone();
+        tokio::spawn(async move {
+            while let Some(update) = metadata.recv().await {
+                context.plugins.emit(PluginEvent::StreamTitle {
+                    guild_id: &guild_id,
+                    title: &update.title,
+                    url: update.url.as_deref(),
+                });
+
+                let Some(session) = context.session.upgrade() else { break };
+                session.send(&serde_json::json!({
+                    "op": "event",
+                    "type": "StreamTitleEvent",
+                    "guildId": guild_id,
+                    "title": update.title,
+                    "url": update.url,
+                }));
+            }
+        });
+    }
+
+    // The whole pipeline, from probing to encoding, runs on an audio worker.
+    let format = format!("{:?}/{:?}", media.info.container, media.info.codec);
+    let (ready_tx, ready_rx) = oneshot::channel();
+    let (error_tx, mut error_rx) = mpsc::unbounded_channel();
+    let processor_control = control.clone();
+    let io = Handle::current();
+    let open = async move {
+        let processor = AudioProcessor::new(media, processor_control, io).await;
+        let _ = ready_tx.send(processor.is_some());
+
+        processor.map(|processor| Box::pin(stream::unfold(processor, move |mut proc: AudioProcessor| {
+            let error_tx = error_tx.clone();
+            async move {
+                match proc.next_packet().await {
+                    Some(Ok(packet)) => Some((Ok(packet), proc)),
+                    Some(Err(e)) => {
+                        log(Level::Error, "Player", format!("Error reading packet: {}", e));
+                        let _ = error_tx.send(ErrorData::from_error("Something went wrong while decoding the track", Severity::Fault, &e));
+                        Some((Err(e), proc))
+                    },
+                    None => None,
+                }
+            }
+        })))
+    };
+    let prefetch = Prefetch::spawn(open, &context.workers);
+
+    if !ready_rx.await.unwrap_or(false) {
+        log(Level::Error, "Player", format!("Unsupported stream format for: {}", identifier));
+        fail(ErrorData::new("Unsupported audio format", Severity::Common, format!("No decoder could read the {} stream", format)));
+        return;
+    }
+
+    report(PlaybackEvent::Started);
+
+    let result = stream_handler.play(prefetch, &control).await;
+    if let Some(connection) = stream_handler.connection() {
+        connection.set_speaking(false).await;
+        connection.send_silence().await;
+    }
+    log(Level::Info, "Player", "Playback finished");
+
+    if let Err(e) = result {
+        log(Level::Error, "Player", format!("Lost voice connection for {}: {}", identifier, e));
+        fail(ErrorData::from_error("Voice connection timed out", Severity::Common, &e));
+        return;
+    }
+    match error_rx.try_recv() {
+        Ok(exception) => fail(exception),
+        Err(_) => report(PlaybackEvent::Ended("finished")),
     }
 }
 
 pub struct PlayerManager {
-    pub players: HashMap<String, Player>,
+    pub players: DashMap<String, PlayerHandle>,
+}
+
+impl Default for PlayerManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl PlayerManager {
     pub fn new() -> Self {
         Self {
-            players: HashMap::new(),
+            players: DashMap::new(),
         }
     }
 
-    pub fn get_or_create(&mut self, guild_id: String) -> &mut Player {
-        self.players.entry(guild_id.clone()).or_insert_with(|| Player::new(guild_id))
+    pub fn get(&self, guild_id: &str) -> Option<PlayerHandle> {
+        self.players.get(guild_id).map(|p| p.clone())
     }
-}
\ No newline at end of file
+
+    /// Returns the guild's player, spawning its actor with `context` if it doesn't exist yet.
+    pub fn get_or_create(&self, guild_id: &str, context: impl FnOnce() -> PlayerContext) -> PlayerHandle {
+        self.players.entry(guild_id.to_string())
+            .or_insert_with(|| PlayerHandle::spawn(guild_id.to_string(), context()))
+            .clone()
+    }
+
+    /// Removes the player and stops its actor. Returns false if there was no such player.
+    pub fn remove(&self, guild_id: &str) -> bool {
+        match self.players.remove(guild_id) {
+            Some((_, player)) => {
+                player.send(PlayerCommand::Destroy);
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn snapshots(&self) -> Vec<PlayerSnapshot> {
+        self.players.iter().map(|p| p.snapshot()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use crate::managers::sources::Source;
+    use crate::models::load_tracks::LoadTracksResponse;
+    use crate::playback::media::MediaStream;
+    use crate::utils::encoding::{encode_track, DecodedInfo};
+
+    /// Never finishes opening a stream, so a started track stays playing.
+    struct SilentSource;
+
+    #[async_trait]
+    impl Source for SilentSource {
+        fn name(&self) -> &'static str {
+            "silent"
+        }
+
+        async fn search(&self, _query: &str, _search_type: &str) -> LoadTracksResponse {
+            LoadTracksResponse::error(ErrorData::new("Not supported", Severity::Common, "Test source"))
+        }
+
+        async fn resolve(&self, _url: &str) -> LoadTracksResponse {
+            LoadTracksResponse::error(ErrorData::new("Not supported", Severity::Common, "Test source"))
+        }
+
+        async fn load_stream(&self, _track: &DecodedInfo) -> Result<MediaStream, ErrorData> {
+            std::future::pending().await
+        }
+    }
+
+    fn spawn_player() -> PlayerHandle {
+        let mut sources = SourceManager::new();
+        sources.register(Box::new(SilentSource));
+        PlayerHandle::spawn("1".to_string(), PlayerContext {
+            user_id: "2".to_string(),
+            session: Weak::new(),
+            sources: Arc::new(sources),
+            plugins: Arc::new(PluginManager::new()),
+            opus: OpusSettings::default(),
+            workers: Arc::new(AudioWorkers::new(1)),
+        })
+    }
+
+    fn track(identifier: &str, is_stream: bool) -> Box<DecodedTrack> {
+        let info = DecodedInfo {
+            title: identifier.to_string(),
+            author: "Artist".to_string(),
+            length: 200_000,
+            identifier: identifier.to_string(),
+            is_stream,
+            uri: None,
+            artwork_url: None,
+            isrc: None,
+            source_name: "silent".to_string(),
+            position: 0,
+            source_data: Vec::new(),
+        };
+        Box::new(DecodedTrack {
+            encoded: encode_track(&info),
+            info,
+            plugin_info: serde_json::json!({}),
+            user_data: serde_json::json!({}),
+        })
+    }
+
+    fn play(identifier: &str, position: u64, no_replace: bool) -> PlayerCommand {
+        PlayerCommand::Play { track: track(identifier, false), position, no_replace }
+    }
+
+    fn voice() -> PlayerCommand {
+        PlayerCommand::Voice(VoiceState {
+            token: "token".to_string(),
+            endpoint: "127.0.0.1:1".to_string(),
+            session_id: "session".to_string(),
+            channel_bitrate: None,
+        })
+    }
+
+    /// The playing track and where its playback was asked to start, if it started.
+    fn playing(snapshot: &PlayerSnapshot) -> (Option<String>, Option<u64>) {
+        let track = snapshot.track.as_ref().map(|t| t.info.identifier.clone());
+        (track, snapshot.control.as_ref().map(|c| c.seek_target()))
+    }
+
+    #[tokio::test]
+    async fn starts_a_track_set_before_voice_at_its_position() {
+        let player = spawn_player();
+        let snapshot = player.update(vec![play("a", 30_000, false)]).await;
+        assert_eq!(playing(&snapshot), (Some("a".to_string()), None));
+        assert!(!snapshot.state.connected);
+
+        let snapshot = player.update(vec![voice()]).await;
+        assert_eq!(playing(&snapshot), (Some("a".to_string()), Some(30_000)));
+        assert!(snapshot.state.connected);
+    }
+
+    #[tokio::test]
+    async fn keeps_seeks_made_before_voice() {
+        let player = spawn_player();
+        player.update(vec![play("a", 0, false)]).await;
+        player.update(vec![PlayerCommand::Seek(45_000)]).await;
+        let snapshot = player.update(vec![voice()]).await;
+        assert_eq!(playing(&snapshot), (Some("a".to_string()), Some(45_000)));
+
+        let snapshot = player.update(vec![PlayerCommand::Seek(60_000)]).await;
+        assert_eq!(playing(&snapshot), (Some("a".to_string()), Some(60_000)));
+    }
+
+    #[tokio::test]
+    async fn ignores_seeks_without_a_seekable_track() {
+        let player = spawn_player();
+        player.update(vec![PlayerCommand::Seek(45_000)]).await;
+        player.update(vec![play("a", 0, false)]).await;
+        let snapshot = player.update(vec![voice()]).await;
+        assert_eq!(playing(&snapshot), (Some("a".to_string()), Some(0)));
+
+        let live = spawn_player();
+        live.update(vec![PlayerCommand::Play { track: track("live", true), position: 0, no_replace: false }]).await;
+        live.update(vec![PlayerCommand::Seek(45_000)]).await;
+        let snapshot = live.update(vec![voice()]).await;
+        assert_eq!(playing(&snapshot), (Some("live".to_string()), Some(0)));
+    }
+
+    #[tokio::test]
+    async fn applies_commands_of_one_update_in_order() {
+        let player = spawn_player();
+        let snapshot = player.update(vec![voice(), play("a", 10_000, false)]).await;
+        assert_eq!(playing(&snapshot), (Some("a".to_string()), Some(10_000)));
+
+        let other = spawn_player();
+        let snapshot = other.update(vec![play("a", 10_000, false), PlayerCommand::Seek(20_000), voice()]).await;
+        assert_eq!(playing(&snapshot), (Some("a".to_string()), Some(20_000)));
+    }
+
+    #[tokio::test]
+    async fn does_not_replace_a_track_with_no_replace() {
+        let player = spawn_player();
+        player.update(vec![play("a", 0, true)]).await;
+        let snapshot = player.update(vec![play("b", 5_000, true)]).await;
+        assert_eq!(playing(&snapshot), (Some("a".to_string()), None));
+
+        let snapshot = player.update(vec![voice(), play("c", 5_000, true)]).await;
+        assert_eq!(playing(&snapshot), (Some("a".to_string()), Some(0)));
+
+        let snapshot = player.update(vec![play("d", 5_000, false)]).await;
+        assert_eq!(playing(&snapshot), (Some("d".to_string()), Some(5_000)));
+
+        let snapshot = player.update(vec![PlayerCommand::Stop, play("e", 0, true)]).await;
+        assert_eq!(playing(&snapshot), (Some("e".to_string()), Some(0)));
+    }
+}
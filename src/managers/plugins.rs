@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use async_trait::async_trait;
+use bytes::Bytes;
+use serde::Serialize;
+use warp::http::Method;
+use crate::managers::sources::Source;
+use crate::models::load_tracks::PlaylistData;
+use crate::utils::encoding::{DecodedInfo, DecodedTrack};
+use crate::utils::{log, Level};
+
+pub type PluginFactory = fn(&toml::Value) -> Result<Box<dyn Plugin>, String>;
+
+pub struct PluginRoute {
+    pub method: Method,
+    pub path: &'static str,
+}
+
+pub struct PluginRequest {
+    pub path: String,
+    pub query: HashMap<String, String>,
+    pub body: Bytes,
+}
+
+pub struct PluginResponse {
+    pub status: u16,
+    pub body: serde_json::Value,
+}
+
+pub enum PluginEvent<'a> {
+    TrackStart { guild_id: &'a str, track: &'a DecodedInfo },
+    TrackEnd { guild_id: &'a str, track: &'a DecodedInfo, reason: &'a str },
+    StreamTitle { guild_id: &'a str, title: &'a str, url: Option<&'a str> },
+}
+
+/// Processes decoded audio before it is encoded, one 20 ms frame of interleaved 48 kHz stereo
+/// samples at a time.
+pub trait PcmFilter: Send {
+    fn process(&mut self, samples: &mut [f32]);
+}
+
+#[async_trait]
+pub trait Plugin: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn version(&self) -> &'static str;
+    fn sources(&self) -> Vec<Box<dyn Source>> { Vec::new() }
+    fn filters(&self) -> Vec<&'static str> { Vec::new() }
+    /// Creates the filter `name` from its settings in a player's `filters.pluginFilters`.
+    fn create_filter(&self, _name: &str, _settings: &serde_json::Value) -> Option<Box<dyn PcmFilter>> { None }
+    fn routes(&self) -> Vec<PluginRoute> { Vec::new() }
+    async fn handle(&self, _route: &PluginRoute, _request: PluginRequest) -> PluginResponse {
+        PluginResponse::not_found()
+    }
+    fn track_info(&self, _track: &DecodedTrack) -> Option<serde_json::Value> { None }
+    fn playlist_info(&self, _playlist: &PlaylistData) -> Option<serde_json::Value> { None }
+    fn on_event(&self, _event: &PluginEvent<'_>) {}
+}
+
+#[derive(Serialize)]
+pub struct PluginDescriptor {
+    pub name: &'static str,
+    pub version: &'static str,
+}
+
+struct RegisteredRoute {
+    plugin: usize,
+    route: PluginRoute,
+}
+
+pub struct PluginManager {
+    plugins: Vec<Arc<dyn Plugin>>,
+    routes: Vec<RegisteredRoute>,
+}
+
+impl PluginResponse {
+    pub fn json(status: u16, body: serde_json::Value) -> Self {
+        Self { status, body }
+    }
+
+    pub fn not_found() -> Self {
+        Self::json(404, serde_json::json!({ "error": "Not Found" }))
+    }
+}
+
+impl Default for PluginManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PluginManager {
+    pub fn new() -> Self {
+        Self {
+            plugins: Vec::new(),
+            routes: Vec::new(),
+        }
+    }
+
+    pub fn load(configs: Option<&HashMap<String, toml::Value>>, available: &[(&'static str, PluginFactory)]) -> Self {
+        let mut manager = Self::new();
+        let Some(configs) = configs else { return manager };
+
+        let mut names: Vec<&String> = configs.keys().collect();
+        names.sort();
+
+        for name in names {
+            let config = &configs[name];
+            if config.get("enabled").and_then(toml::Value::as_bool) == Some(false) {
+                continue;
+            }
+
+            let Some((_, factory)) = available.iter().find(|(n, _)| n == name) else {
+                log(Level::Warn, "PluginManager", format!("Unknown plugin '{}' in config, skipping", name));
+                continue;
+            };
+
+            match factory(config) {
+                Ok(plugin) => manager.register(plugin),
+                Err(e) => log(Level::Error, "PluginManager", format!("Failed to load plugin '{}': {}", name, e)),
+            }
+        }
+
+        manager
+    }
+
+    pub fn register(&mut self, plugin: Box<dyn Plugin>) {
+        let index = self.plugins.len();
+        for route in plugin.routes() {
+            if self.find_route(&route.method, route.path).is_some() {
+                log(Level::Warn, "PluginManager", format!("{} {} is already registered, ignoring it for {}", route.method, route.path, plugin.name()));
+                continue;
+            }
+            self.routes.push(RegisteredRoute { plugin: index, route });
+        }
+
+        log(Level::Info, "PluginManager", format!("Loaded plugin {} v{}", plugin.name(), plugin.version()));
+        self.plugins.push(Arc::from(plugin));
+    }
+
+    pub fn plugins(&self) -> &[Arc<dyn Plugin>] {
+        &self.plugins
+    }
+
+    pub fn descriptors(&self) -> Vec<PluginDescriptor> {
+        self.plugins.iter()
+            .map(|p| PluginDescriptor { name: p.name(), version: p.version() })
+            .collect()
+    }
+
+    pub fn filter_names(&self) -> Vec<&'static str> {
+        self.plugins.iter().flat_map(|p| p.filters()).collect()
+    }
+
+    /// Builds the plugin filters a player's `filters` enable. Unknown names and null settings are ignored.
+    pub fn create_filters(&self, filters: &serde_json::Value) -> Vec<Box<dyn PcmFilter>> {
+        let Some(enabled) = filters.get("pluginFilters").and_then(|f| f.as_object()) else { return Vec::new() };
+        enabled.iter()
+            .filter(|(_, settings)| !settings.is_null())
+            .filter_map(|(name, settings)| {
+                let plugin = self.plugins.iter().find(|p| p.filters().contains(&name.as_str()))?;
+                plugin.create_filter(name, settings)
+            })
+            .collect()
+    }
+
+    fn find_route(&self, method: &Method, path: &str) -> Option<&RegisteredRoute> {
+        self.routes.iter().find(|r| r.route.method == method && r.route.path == path)
+    }
+
+    pub fn has_route(&self, method: &Method, path: &str) -> bool {
+        self.find_route(method, path).is_some()
+    }
+
+    pub async fn handle(&self, method: &Method, request: PluginRequest) -> Option<PluginResponse> {
+        let registered = self.find_route(method, &request.path)?;
+        Some(self.plugins[registered.plugin].handle(&registered.route, request).await)
+    }
+
+    pub fn emit(&self, event: PluginEvent<'_>) {
+        for plugin in &self.plugins {
+            plugin.on_event(&event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::playback::control::PlaybackControl;
+    use crate::playback::opus::OpusSettings;
+
+    struct Scale(f32);
+
+    impl PcmFilter for Scale {
+        fn process(&mut self, samples: &mut [f32]) {
+            samples.iter_mut().for_each(|s| *s *= self.0);
+        }
+    }
+
+    struct FilterPlugin;
+
+    impl Plugin for FilterPlugin {
+        fn name(&self) -> &'static str {
+            "filters"
+        }
+
+        fn version(&self) -> &'static str {
+            "1.0.0"
+        }
+
+        fn filters(&self) -> Vec<&'static str> {
+            vec!["scale", "mute"]
+        }
+
+        fn create_filter(&self, name: &str, settings: &serde_json::Value) -> Option<Box<dyn PcmFilter>> {
+            match name {
+                "scale" => Some(Box::new(Scale(settings.get("factor")?.as_f64()? as f32))),
+                "mute" => Some(Box::new(Scale(0.0))),
+                _ => None,
+            }
+        }
+    }
+
+    fn manager() -> PluginManager {
+        let mut manager = PluginManager::new();
+        manager.register(Box::new(FilterPlugin));
+        manager
+    }
+
+    fn filtered(manager: &PluginManager, filters: serde_json::Value) -> Vec<f32> {
+        let control = PlaybackControl::new(0, false, 1.0, OpusSettings::default());
+        control.set_filters(manager.create_filters(&filters));
+        let mut samples = vec![0.5, -0.25];
+        control.filter(&mut samples);
+        samples
+    }
+
+    #[test]
+    fn reports_plugin_filter_names() {
+        assert_eq!(manager().filter_names(), ["scale", "mute"]);
+        assert!(PluginManager::new().filter_names().is_empty());
+    }
+
+    #[test]
+    fn applies_the_enabled_plugin_filters() {
+        let manager = manager();
+        let cases = [
+            (serde_json::json!({}), vec![0.5, -0.25]),
+            (serde_json::json!({ "volume": 0.5 }), vec![0.5, -0.25]),
+            (serde_json::json!({ "pluginFilters": { "scale": { "factor": 2.0 } } }), vec![1.0, -0.5]),
+            (serde_json::json!({ "pluginFilters": { "scale": { "factor": 2.0 }, "mute": {} } }), vec![0.0, -0.0]),
+            (serde_json::json!({ "pluginFilters": { "scale": null } }), vec![0.5, -0.25]),
+            (serde_json::json!({ "pluginFilters": { "scale": {} } }), vec![0.5, -0.25]),
+            (serde_json::json!({ "pluginFilters": { "unknown": {} } }), vec![0.5, -0.25]),
+        ];
+        for (filters, expected) in cases {
+            assert_eq!(filtered(&manager, filters.clone()), expected, "{}", filters);
+        }
+    }
+}
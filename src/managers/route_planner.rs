@@ -38,6 +38,12 @@ pub struct RoutePlannerManager {
     pub banned_ips: Mutex<HashMap<String, u64>>,
 }
 
+impl Default for RoutePlannerManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl RoutePlannerManager {
     pub fn new() -> Self {
         Self {
@@ -0,0 +1,305 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use crate::config::{MirroringConfig, SearchConfig};
+use crate::utils::encoding::DecodedTrack;
+use crate::utils::text::{match_token, tokenize};
+
+const DEFAULT_TIMEOUT_MS: u64 = 5000;
+const DEFAULT_LIMIT: usize = 50;
+const DEFAULT_DURATION_TOLERANCE_MS: u64 = 5000;
+const MIN_MIRROR_RELEVANCE: f32 = 1.5;
+
+pub struct SearchSettings {
+    pub default_prefix: Option<String>,
+    pub timeout: Duration,
+    pub source_timeouts: HashMap<String, Duration>,
+    pub limit: usize,
+}
+
+pub struct MirrorSettings {
+    pub sources: Vec<String>,
+    pub duration_tolerance: u64,
+}
+
+pub struct Candidate {
+    pub track: DecodedTrack,
+    pub priority: u32,
+    pub index: usize,
+}
+
+impl Default for SearchSettings {
+    fn default() -> Self {
+        Self {
+            default_prefix: None,
+            timeout: Duration::from_millis(DEFAULT_TIMEOUT_MS),
+            source_timeouts: HashMap::new(),
+            limit: DEFAULT_LIMIT,
+        }
+    }
+}
+
+impl SearchSettings {
+    pub fn from_config(config: SearchConfig) -> Self {
+        Self {
+            default_prefix: config.default_prefix
+                .map(|p| p.trim_end_matches(':').to_string())
+                .filter(|p| !p.is_empty()),
+            timeout: Duration::from_millis(config.timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS)),
+            source_timeouts: config.source_timeouts.unwrap_or_default()
+                .into_iter()
+                .map(|(name, ms)| (name, Duration::from_millis(ms)))
+                .collect(),
+            limit: config.limit.unwrap_or(DEFAULT_LIMIT),
+        }
+    }
+
+    pub fn timeout_for(&self, source: &str) -> Duration {
+        self.source_timeouts.get(source).copied().unwrap_or(self.timeout)
+    }
+}
+
+impl MirrorSettings {
+    pub fn from_config(config: MirroringConfig) -> Self {
+        Self {
+            sources: config.sources.unwrap_or_default(),
+            duration_tolerance: config.duration_tolerance_ms.unwrap_or(DEFAULT_DURATION_TOLERANCE_MS),
+        }
+    }
+
+    fn duration_matches(&self, original: &DecodedTrack, candidate: &DecodedTrack) -> bool {
+        let (a, b) = (&original.info, &candidate.info);
+        if a.is_stream || b.is_stream || a.length == 0 || b.length == 0 {
+            return true;
+        }
+        a.length.abs_diff(b.length) <= self.duration_tolerance
+    }
+
+    pub fn best_match(&self, original: &DecodedTrack, candidates: Vec<DecodedTrack>) -> Option<DecodedTrack> {
+        let isrc = original.info.isrc.as_deref().map(str::trim).filter(|i| !i.is_empty());
+        let query = tokenize(&format!("{} {}", original.info.title, original.info.author));
+
+        candidates.into_iter()
+            .filter(|c| self.duration_matches(original, c))
+            .filter_map(|c| {
+                let same_isrc = isrc.is_some_and(|i| c.info.isrc.as_deref().is_some_and(|ci| ci.trim().eq_ignore_ascii_case(i)));
+                let score = relevance(&query, &c);
+                (same_isrc || score >= MIN_MIRROR_RELEVANCE).then_some((same_isrc, score, c))
+            })
+            .max_by(|(a_isrc, a_score, a), (b_isrc, b_score, b)| {
+                a_isrc.cmp(b_isrc)
+                    .then(a_score.total_cmp(b_score))
+                    .then(b.info.length.abs_diff(original.info.length).cmp(&a.info.length.abs_diff(original.info.length)))
+            })
+            .map(|(_, _, c)| c)
+    }
+}
+
+fn relevance(query: &[String], track: &DecodedTrack) -> f32 {
+    if query.is_empty() {
+        return 0.0;
+    }
+
+    let title = tokenize(&track.info.title);
+    let author = tokenize(&track.info.author);
+
+    let total: f32 = query.iter()
+        .map(|token| {
+            let best = |words: &[String]| words.iter().map(|w| match_token(token, w)).fold(0.0, f32::max);
+            (best(&title) * 3.0).max(best(&author) * 2.0)
+        })
+        .sum();
+
+    // Favors tighter fields, but is capped so a short title can't pass as a match without matching words.
+    let field_len = (title.len() + author.len()).max(1) as f32;
+    total / query.len() as f32 + (query.len() as f32 / field_len).min(1.0)
+}
+
+fn dedup_key(track: &DecodedTrack) -> String {
+    if let Some(isrc) = track.info.isrc.as_deref().map(str::trim).filter(|i| !i.is_empty()) {
+        return format!("isrc:{}", isrc.to_ascii_uppercase());
+    }
+
+    format!("{}|{}", tokenize(&track.info.title).join(" "), tokenize(&track.info.author).join(" "))
+}
+
+pub fn rank(query: &str, candidates: Vec<Candidate>, limit: usize) -> Vec<DecodedTrack> {
+    let tokens = tokenize(query);
+    let mut scored: Vec<(f32, Candidate)> = candidates.into_iter()
+        .map(|c| (relevance(&tokens, &c.track), c))
+        .collect();
+
+    scored.sort_by(|(a_score, a), (b_score, b)| {
+        b_score.total_cmp(a_score)
+            .then(b.priority.cmp(&a.priority))
+            .then(a.index.cmp(&b.index))
+    });
+
+    let mut seen = HashSet::new();
+    scored.into_iter()
+        .map(|(_, c)| c.track)
+        .filter(|track| seen.insert(dedup_key(track)))
+        .take(limit)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::encoding::{encode_track, DecodedInfo};
+
+    fn track(identifier: &str, title: &str, author: &str, isrc: Option<&str>) -> DecodedTrack {
+        let info = DecodedInfo {
+            title: title.to_string(),
+            author: author.to_string(),
+            length: 200_000,
+            identifier: identifier.to_string(),
+            is_stream: false,
+            uri: None,
+            artwork_url: None,
+            isrc: isrc.map(str::to_string),
+            source_name: "test".to_string(),
+            position: 0,
+            source_data: Vec::new(),
+        };
+        DecodedTrack {
+            encoded: encode_track(&info),
+            info,
+            plugin_info: serde_json::json!({}),
+            user_data: serde_json::json!({}),
+        }
+    }
+
+    fn candidates(tracks: Vec<(DecodedTrack, u32)>) -> Vec<Candidate> {
+        tracks.into_iter()
+            .enumerate()
+            .map(|(index, (track, priority))| Candidate { track, priority, index })
+            .collect()
+    }
+
+    fn identifiers(tracks: &[DecodedTrack]) -> Vec<&str> {
+        tracks.iter().map(|t| t.info.identifier.as_str()).collect()
+    }
+
+    #[test]
+    fn ranks_by_relevance_before_source_order() {
+        let ranked = rank("never gonna give you up", candidates(vec![
+            (track("other", "Together Forever", "Rick Astley", None), 10),
+            (track("author", "Up", "Never Gonna", None), 10),
+            (track("exact", "Never Gonna Give You Up", "Rick Astley", None), 10),
+            (track("typo", "Nevr Gonna Give You Up", "Rick Astley", None), 10),
+        ]), 10);
+        assert_eq!(identifiers(&ranked), ["exact", "typo", "author", "other"]);
+    }
+
+    #[test]
+    fn removes_duplicates_by_isrc_then_title_and_author() {
+        let ranked = rank("never gonna give you up", candidates(vec![
+            (track("first", "Never Gonna Give You Up", "Rick Astley", Some("GBARL9300135")), 10),
+            (track("same-isrc", "Never Gonna Give You Up (Remastered)", "Rick Astley", Some(" gbarl9300135 ")), 10),
+            (track("no-isrc", "Never Gonna Give You Up", "Rick Astley", None), 10),
+            (track("same-title", "Never gonna give you up!", "rick astley", None), 10),
+            (track("other-author", "Never Gonna Give You Up", "Cover Band", None), 10),
+            (track("blank-isrc", "Never Gonna Give You Up", "Rick Astley", Some("  ")), 10),
+        ]), 10);
+        assert_eq!(identifiers(&ranked), ["first", "no-isrc", "other-author"]);
+    }
+
+    #[test]
+    fn breaks_ties_by_priority_then_index() {
+        let ranked = rank("song", candidates(vec![
+            (track("low-0", "Song", "Artist", Some("A")), 5),
+            (track("high-0", "Song", "Artist", Some("B")), 20),
+            (track("low-1", "Song", "Artist", Some("C")), 5),
+            (track("high-1", "Song", "Artist", Some("D")), 20),
+        ]), 10);
+        assert_eq!(identifiers(&ranked), ["high-0", "high-1", "low-0", "low-1"]);
+    }
+
+    #[test]
+    fn applies_the_limit_after_removing_duplicates() {
+        let tracks = (0..10)
+            .map(|i| (track(&i.to_string(), &format!("Song {}", i / 2), "Artist", None), 10))
+            .collect();
+        let ranked = rank("song", candidates(tracks), 3);
+        assert_eq!(identifiers(&ranked), ["0", "2", "4"]);
+        assert!(rank("song", Vec::new(), 3).is_empty());
+        assert!(rank("song", candidates(vec![(track("a", "Song", "Artist", None), 10)]), 0).is_empty());
+    }
+
+    fn mirror() -> MirrorSettings {
+        MirrorSettings { sources: vec!["test".to_string()], duration_tolerance: 5000 }
+    }
+
+    fn lasting(mut track: DecodedTrack, length: u64) -> DecodedTrack {
+        track.info.length = length;
+        track
+    }
+
+    fn best(original: &DecodedTrack, candidates: Vec<DecodedTrack>) -> Option<String> {
+        mirror().best_match(original, candidates).map(|t| t.info.identifier)
+    }
+
+    #[test]
+    fn prefers_isrc_matches_over_better_titles() {
+        let original = track("original", "Never Gonna Give You Up", "Rick Astley", Some("GBARL9300135"));
+        let candidates = vec![
+            track("title", "Never Gonna Give You Up", "Rick Astley", None),
+            track("isrc", "Unrelated Upload", "Someone", Some("gbarl9300135")),
+            track("other-isrc", "Never Gonna Give You Up", "Rick Astley", Some("USXXX0000001")),
+        ];
+        assert_eq!(best(&original, candidates).as_deref(), Some("isrc"));
+
+        let without_isrc = track("original", "Never Gonna Give You Up", "Rick Astley", Some(" "));
+        let candidates = vec![
+            track("isrc", "Unrelated Upload", "Someone", Some(" ")),
+            track("title", "Never Gonna Give You Up", "Rick Astley", None),
+        ];
+        assert_eq!(best(&without_isrc, candidates).as_deref(), Some("title"));
+    }
+
+    #[test]
+    fn rejects_candidates_outside_the_duration_tolerance() {
+        let original = track("original", "Song Title", "Artist", Some("ISRC1"));
+        let cases = [
+            (200_000, true),
+            (205_000, true),
+            (194_500, false),
+            (205_001, false),
+            (0, true),
+        ];
+        for (length, accepted) in cases {
+            let candidates = vec![lasting(track("candidate", "Song Title", "Artist", Some("ISRC1")), length)];
+            assert_eq!(best(&original, candidates).is_some(), accepted, "{} ms", length);
+        }
+
+        let mut stream = track("stream", "Song Title", "Artist", None);
+        stream.info.is_stream = true;
+        assert_eq!(best(&original, vec![lasting(stream, 3_600_000)]).as_deref(), Some("stream"));
+    }
+
+    #[test]
+    fn rejects_candidates_below_the_minimum_relevance() {
+        let original = track("original", "Never Gonna Give You Up", "Rick Astley", None);
+        let cases = [
+            ("Together Forever", "Someone Else", false),
+            ("Never Gonna Give You Up", "Someone Else", true),
+            ("Never Gonna Give You Up (Official Video)", "RickAstleyVEVO", true),
+        ];
+        for (title, author, accepted) in cases {
+            let candidates = vec![track("candidate", title, author, None)];
+            assert_eq!(best(&original, candidates).is_some(), accepted, "{} - {}", title, author);
+        }
+        assert!(best(&original, Vec::new()).is_none());
+    }
+
+    #[test]
+    fn prefers_the_closest_duration_between_equal_scores() {
+        let original = track("original", "Song Title", "Artist", None);
+        let candidates = vec![
+            lasting(track("far", "Song Title", "Artist", None), 204_000),
+            lasting(track("close", "Song Title", "Artist", None), 199_000),
+            lasting(track("farther", "Song Title", "Artist", None), 196_000),
+        ];
+        assert_eq!(best(&original, candidates).as_deref(), Some("close"));
+    }
+}
@@ -1,5 +1,5 @@
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, RwLock};
+use dashmap::DashMap;
 use tokio::sync::mpsc;
 use warp::ws::Message;
 use rand::{distr::Alphanumeric, Rng};
@@ -9,23 +9,36 @@ pub struct Session {
     pub id: String,
     pub user_id: String,
     pub _client_name: String,
-    pub sender: Mutex<mpsc::UnboundedSender<Message>>,
-    pub players: Mutex<PlayerManager>,
+    pub sender: RwLock<mpsc::UnboundedSender<Message>>,
+    pub players: PlayerManager,
+}
+
+impl Session {
+    pub fn send(&self, payload: &serde_json::Value) {
+        let sender = self.sender.read().unwrap();
+        let _ = sender.send(Message::text(payload.to_string()));
+    }
 }
 
 pub struct SessionManager {
-    pub sessions: HashMap<String, Arc<Session>>,
+    pub sessions: DashMap<String, Arc<Session>>,
+}
+
+impl Default for SessionManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SessionManager {
     pub fn new() -> Self {
         Self {
-            sessions: HashMap::new(),
+            sessions: DashMap::new(),
         }
     }
 
     pub fn create(
-        &mut self,
+        &self,
         user_id: String,
         client_name: String,
         sender: mpsc::UnboundedSender<Message>,
@@ -40,8 +53,8 @@ impl SessionManager {
             id: id.clone(),
             user_id,
             _client_name: client_name,
-            sender: Mutex::new(sender),
-            players: Mutex::new(PlayerManager::new()),
+            sender: RwLock::new(sender),
+            players: PlayerManager::new(),
         });
 
         self.sessions.insert(id, session.clone());
@@ -49,11 +62,17 @@ impl SessionManager {
     }
 
     pub fn resume(&self, session_id: &str, new_sender: mpsc::UnboundedSender<Message>) -> Option<Arc<Session>> {
-        if let Some(session) = self.sessions.get(session_id) {
-            let mut sender = session.sender.lock().unwrap();
-            *sender = new_sender;
-            return Some(session.clone());
-        }
-        None
+        let session = self.sessions.get(session_id)?.clone();
+        *session.sender.write().unwrap() = new_sender;
+        Some(session)
+    }
+
+    pub fn get(&self, session_id: &str) -> Option<Arc<Session>> {
+        self.sessions.get(session_id).map(|s| s.clone())
+    }
+
+    /// Snapshot of the current sessions, so callers never hold a map shard while working with one.
+    pub fn all(&self) -> Vec<Arc<Session>> {
+        self.sessions.iter().map(|s| s.clone()).collect()
     }
 }
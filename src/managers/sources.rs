@@ -1,30 +1,42 @@
 use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
 use async_trait::async_trait;
-use crate::playback::demuxers::webm::WebmOpusDemuxer;
-use crate::models::load_tracks::{LoadTracksResponse, LoadType, LoadResultData};
-use tokio::fs::File;
-use tokio_util::codec::FramedRead;
+use futures_util::StreamExt;
+use crate::managers::cache::TrackCache;
+use crate::managers::plugins::Plugin;
+use crate::managers::search::{self, Candidate, MirrorSettings, SearchSettings};
+use crate::models::load_tracks::{ErrorData, Severity, LoadTracksResponse, LoadType, LoadResultData, PlaylistData, PlaylistInfo};
+use crate::playback::media::MediaStream;
+use crate::sources::local::LocalSource;
+use crate::sources::local::sandbox::{self, LocalAccess, LocalSandbox};
+use crate::sources::playlist::{self, PlaylistFormat};
+use crate::utils::http::is_http_url;
+use crate::utils::encoding::{DecodedInfo, DecodedTrack};
+use crate::utils::{log, Level};
 use regex::Regex;
 
+const PLAYLIST_RESOLVE_CONCURRENCY: usize = 8;
+
 #[async_trait]
 pub trait Source: Send + Sync {
     fn name(&self) -> &'static str;
     fn priority(&self) -> u32 { 10 }
     fn search_terms(&self) -> Vec<&'static str> { Vec::new() }
     fn patterns(&self) -> Vec<&'static str> { Vec::new() }
+    fn can_stream(&self) -> bool { true }
     fn _matches(&self, query: &str) -> bool {
         for pattern in self.patterns() {
-            if let Ok(re) = Regex::new(pattern) {
-                if re.is_match(query) {
-                    return true;
-                }
+            if let Ok(re) = Regex::new(pattern)
+                && re.is_match(query) {
+                return true;
             }
         }
         false
     }
     async fn search(&self, query: &str, search_type: &str) -> LoadTracksResponse;
     async fn resolve(&self, url: &str) -> LoadTracksResponse;
-    async fn load_stream(&self, identifier: &str) -> Option<FramedRead<File, WebmOpusDemuxer>>;
+    async fn load_stream(&self, track: &DecodedInfo) -> Result<MediaStream, ErrorData>;
 }
 
 struct SourcePattern {
@@ -37,21 +49,61 @@ pub struct SourceManager {
     sources: HashMap<String, Box<dyn Source>>,
     search_term_map: HashMap<String, String>,
     patterns: Vec<SourcePattern>,
+    local_sandbox: Option<Arc<LocalSandbox>>,
+    owners: HashMap<String, Arc<dyn Plugin>>,
+    search: SearchSettings,
+    mirroring: MirrorSettings,
+    cache: TrackCache,
+}
+
+impl Default for SourceManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SourceManager {
     pub fn new() -> Self {
-        Self { 
+        Self {
             sources: HashMap::new(),
             search_term_map: HashMap::new(),
             patterns: Vec::new(),
+            local_sandbox: None,
+            owners: HashMap::new(),
+            search: SearchSettings::default(),
+            mirroring: MirrorSettings::from_config(Default::default()),
+            cache: TrackCache::default(),
         }
     }
 
+    pub fn set_local_sandbox(&mut self, sandbox: Arc<LocalSandbox>) {
+        self.local_sandbox = Some(sandbox);
+    }
+
+    pub fn set_search(&mut self, settings: SearchSettings) {
+        self.search = settings;
+    }
+
+    pub fn set_mirroring(&mut self, settings: MirrorSettings) {
+        self.mirroring = settings;
+    }
+
+    pub fn set_cache(&mut self, cache: TrackCache) {
+        self.cache = cache;
+    }
+
+    pub fn cache(&self) -> &TrackCache {
+        &self.cache
+    }
+
+    pub fn local_sandbox(&self) -> Option<&LocalSandbox> {
+        self.local_sandbox.as_deref()
+    }
+
     pub fn register(&mut self, source: Box<dyn Source>) {
         let name = source.name().to_string();
         let priority = source.priority();
-        
+
         for term in source.search_terms() {
             self.search_term_map.insert(term.to_string(), name.clone());
         }
@@ -65,40 +117,92 @@ impl SourceManager {
                 });
             }
         }
-        
-        self.patterns.sort_by(|a, b| b.priority.cmp(&a.priority));
+
+        self.patterns.sort_by_key(|p| std::cmp::Reverse(p.priority));
         self.sources.insert(name, source);
     }
 
+    pub fn register_plugin(&mut self, plugin: &Arc<dyn Plugin>) {
+        for source in plugin.sources() {
+            self.owners.insert(source.name().to_string(), plugin.clone());
+            self.register(source);
+        }
+    }
+
     pub async fn load_tracks(&self, identifier: &str) -> LoadTracksResponse {
-        if std::path::Path::new(identifier).exists() {
-            if let Some(source) = self.sources.get("local") {
-                let res = source.resolve(identifier).await;
-                if !matches!(res.load_type, LoadType::Empty) {
-                    return res;
+        if let Some(res) = self.cache.get(identifier) {
+            return res;
+        }
+
+        let mut res = self.find_tracks(identifier).await;
+        self.apply_plugin_info(&mut res);
+        self.cache.insert(identifier, &res);
+        res
+    }
+
+    /// Fills in `pluginInfo` from the plugin that owns the track's source, if any.
+    pub fn apply_track_plugin_info(&self, track: &mut DecodedTrack) {
+        if let Some(plugin) = self.owners.get(&track.info.source_name)
+            && let Some(info) = plugin.track_info(track) {
+            track.plugin_info = info;
+        }
+    }
+
+    fn apply_plugin_info(&self, res: &mut LoadTracksResponse) {
+        if self.owners.is_empty() {
+            return;
+        }
+
+        let decorate = |track: &mut DecodedTrack| self.apply_track_plugin_info(track);
+
+        match &mut res.data {
+            LoadResultData::Track(track) => decorate(track),
+            LoadResultData::Search(tracks) => tracks.iter_mut().for_each(decorate),
+            LoadResultData::Playlist(playlist) => {
+                playlist.tracks.iter_mut().for_each(decorate);
+
+                let mut owners = playlist.tracks.iter().map(|t| self.owners.get(&t.info.source_name));
+                if let Some(Some(owner)) = owners.next()
+                    && owners.all(|o| o.is_some_and(|o| Arc::ptr_eq(o, owner)))
+                    && let Some(info) = owner.playlist_info(playlist) {
+                    playlist.plugin_info = info;
                 }
+            },
+            _ => {}
+        }
+    }
+
+    async fn find_tracks(&self, identifier: &str) -> LoadTracksResponse {
+        if let Some(format) = PlaylistFormat::detect(identifier) {
+            let res = self.load_playlist(identifier, format).await;
+            if !matches!(res.load_type, LoadType::Empty) {
+                return res;
             }
         }
 
+        if let Some(res) = self.load_local(identifier).await {
+            return res;
+        }
+
         for pattern in &self.patterns {
-            if pattern.regex.is_match(identifier) {
-                if let Some(source) = self.sources.get(&pattern.source_name) {
-                    let res = source.resolve(identifier).await;
-                    if !matches!(res.load_type, LoadType::Empty) {
-                        return res;
-                    }
+            if pattern.regex.is_match(identifier)
+                && let Some(source) = self.sources.get(&pattern.source_name) {
+                let res = source.resolve(identifier).await;
+                if !matches!(res.load_type, LoadType::Empty) {
+                    return res;
                 }
             }
         }
 
-        if let Some((prefix, query)) = identifier.split_once(':') {
-            if prefix.len() > 1 {
-                if let Some(source_name) = self.search_term_map.get(prefix) {
-                    if let Some(source) = self.sources.get(source_name) {
-                        return source.search(query, "track").await;
-                    }
-                }
-            }
+        if let Some((prefix, query)) = identifier.split_once(':')
+            && prefix.len() > 1
+            && let Some(res) = self.prefixed_search(prefix, query).await {
+            return res;
+        }
+
+        if let Some(prefix) = &self.search.default_prefix
+            && let Some(res) = self.prefixed_search(prefix, identifier).await {
+            return res;
         }
 
         let results = self.unified_search(identifier).await;
@@ -115,41 +219,371 @@ impl SourceManager {
         }
     }
 
-    pub async fn unified_search(&self, query: &str) -> Vec<crate::utils::encoding::DecodedTrack> {
-        let mut results = Vec::new();
-        for source in self.sources.values() {
-            let res = source.search(query, "track").await;
-            if let LoadResultData::Search(tracks) = res.data {
-                results.extend(tracks);
-            } else if let LoadResultData::Track(track) = res.data {
-                results.push(track);
+    async fn load_playlist(&self, identifier: &str, format: PlaylistFormat) -> LoadTracksResponse {
+        let (identifier, selected) = playlist::split_selection(identifier);
+        let empty = LoadTracksResponse {
+            load_type: LoadType::Empty,
+            data: LoadResultData::Empty(serde_json::json!({})),
+        };
+
+        let target = if is_http_url(identifier) {
+            identifier.to_string()
+        } else {
+            let Some(sandbox) = &self.local_sandbox else { return sandbox::disabled() };
+            match sandbox.check(Path::new(LocalSource::clean_path(identifier))) {
+                LocalAccess::Allowed(path) => path.to_string_lossy().into_owned(),
+                LocalAccess::Missing => return empty,
+                LocalAccess::Denied => return sandbox::denied(LocalSource::clean_path(identifier)),
+            }
+        };
+
+        let content = match playlist::fetch(&target).await {
+            Ok(c) => c,
+            Err(e) => {
+                log(Level::Warn, "SourceManager", format!("{}: {}", identifier, e));
+                return empty;
             }
+        };
+
+        if playlist::is_hls(&content)
+            && is_http_url(identifier)
+            && let Some(source) = self.sources.get("hls") {
+            return source.resolve(identifier).await;
+        }
+
+        let parsed = match playlist::parse(format, &content) {
+            Some(p) => p,
+            None => return empty,
+        };
+
+        let tracks: Vec<(usize, DecodedTrack)> = futures_util::stream::iter(parsed.entries.into_iter().enumerate())
+            .map(|(index, entry)| async move {
+                let location = playlist::resolve_location(identifier, &entry.location);
+                let mut track = self.resolve_entry(&location).await?;
+                entry.apply(&mut track);
+                Some((index, track))
+            })
+            .buffered(PLAYLIST_RESOLVE_CONCURRENCY)
+            .filter_map(|track| async move { track })
+            .collect()
+            .await;
+
+        if tracks.is_empty() {
+            return empty;
+        }
+
+        // Entries that failed to resolve are left out, so the selection is looked up by entry index.
+        let selected_track = selected
+            .and_then(|selected| tracks.iter().position(|(index, _)| *index == selected))
+            .map_or(-1, |position| position as i32);
+
+        LoadTracksResponse {
+            load_type: LoadType::Playlist,
+            data: LoadResultData::Playlist(PlaylistData {
+                info: PlaylistInfo {
+                    name: parsed.name.unwrap_or_else(|| playlist::default_name(identifier)),
+                    selected_track,
+                },
+                plugin_info: serde_json::json!({}),
+                tracks: tracks.into_iter().map(|(_, track)| track).collect(),
+            }),
         }
-        results
     }
 
-    #[allow(dead_code)]
-    pub async fn load_stream(&self, identifier: &str) -> Option<FramedRead<File, WebmOpusDemuxer>> {
+    async fn load_local(&self, identifier: &str) -> Option<LoadTracksResponse> {
+        let explicit = sandbox::is_explicit_path(identifier);
+        let (Some(sandbox), Some(source)) = (&self.local_sandbox, self.sources.get("local")) else {
+            return explicit.then(sandbox::disabled);
+        };
+
+        if identifier.starts_with("local:") || identifier.starts_with("file:") {
+            return None;
+        }
+
+        match sandbox.check(Path::new(identifier)) {
+            LocalAccess::Allowed(_) => {
+                let res = source.resolve(identifier).await;
+                (!matches!(res.load_type, LoadType::Empty)).then_some(res)
+            },
+            LocalAccess::Denied if explicit => Some(sandbox::denied(identifier)),
+            _ => None,
+        }
+    }
+
+    async fn resolve_entry(&self, location: &str) -> Option<DecodedTrack> {
+        if let Some(res) = self.load_local(LocalSource::clean_path(location)).await {
+            return match res.data {
+                LoadResultData::Track(track) => Some(*track),
+                _ => None,
+            };
+        }
+
         for pattern in &self.patterns {
-            if pattern.regex.is_match(identifier) {
-                if let Some(source) = self.sources.get(&pattern.source_name) {
-                    return source.load_stream(identifier).await;
+            if pattern.regex.is_match(location)
+                && let Some(source) = self.sources.get(&pattern.source_name)
+                && let LoadResultData::Track(track) = source.resolve(location).await.data {
+                return Some(*track);
+            }
+        }
+
+        None
+    }
+
+    async fn prefixed_search(&self, prefix: &str, query: &str) -> Option<LoadTracksResponse> {
+        let source = self.sources.get(self.search_term_map.get(prefix)?)?;
+        let timeout = self.search.timeout_for(source.name());
+
+        match tokio::time::timeout(timeout, source.search(query, "track")).await {
+            Ok(res) => Some(res),
+            Err(_) => Some(LoadTracksResponse::error(ErrorData::new(
+                format!("The {} search timed out", source.name()),
+                Severity::Fault,
+                format!("No response within {} ms", timeout.as_millis()),
+            ))),
+        }
+    }
+
+    pub async fn unified_search(&self, query: &str) -> Vec<DecodedTrack> {
+        let searches = self.sources.values().map(|source| async move {
+            let timeout = self.search.timeout_for(source.name());
+            match tokio::time::timeout(timeout, source.search(query, "track")).await {
+                Ok(res) => (source.priority(), res.data),
+                Err(_) => {
+                    log(Level::Warn, "SourceManager", format!("{} search timed out after {} ms", source.name(), timeout.as_millis()));
+                    (source.priority(), LoadResultData::Empty(serde_json::json!({})))
                 }
             }
+        });
+
+        let mut candidates = Vec::new();
+        for (priority, data) in futures_util::future::join_all(searches).await {
+            let tracks = match data {
+                LoadResultData::Search(tracks) => tracks,
+                LoadResultData::Track(track) => vec![*track],
+                _ => continue,
+            };
+            candidates.extend(tracks.into_iter().enumerate().map(|(index, track)| Candidate { track, priority, index }));
+        }
+
+        search::rank(query, candidates, self.search.limit)
+    }
+
+    pub async fn load_stream(&self, track: &DecodedInfo) -> Result<MediaStream, ErrorData> {
+        let failure = match self.sources.get(&track.source_name) {
+            Some(source) if source.can_stream() => match source.load_stream(track).await {
+                Ok(stream) => return Ok(stream),
+                Err(e) => Some(e),
+            },
+            _ => None,
+        };
+
+        let Some(mirror) = self.mirror(track).await else {
+            return Err(failure.unwrap_or_else(|| ErrorData::new(
+                "No playable source for this track",
+                Severity::Common,
+                format!("The {} source cannot stream and no mirror was found", track.source_name),
+            )));
+        };
+        log(Level::Info, "SourceManager", format!("Playing {} from {} as a mirror of {}:{}", mirror.identifier, mirror.source_name, track.source_name, track.identifier));
+
+        match self.sources.get(&mirror.source_name) {
+            Some(source) => source.load_stream(&mirror).await,
+            None => Err(failure.unwrap_or_else(|| ErrorData::new("No playable source for this track", Severity::Fault, "The mirror source disappeared"))),
+        }
+    }
+
+    async fn mirror(&self, track: &DecodedInfo) -> Option<DecodedInfo> {
+        let original = DecodedTrack {
+            encoded: String::new(),
+            info: track.clone(),
+            plugin_info: serde_json::json!({}),
+            user_data: serde_json::json!({}),
+        };
+
+        let providers: Vec<&dyn Source> = self.mirroring.sources.iter()
+            .filter(|name| **name != track.source_name)
+            .filter_map(|name| self.sources.get(name))
+            .filter(|source| source.can_stream())
+            .map(|source| source.as_ref())
+            .collect();
+        if providers.is_empty() {
+            return None;
+        }
+
+        let mut queries = Vec::new();
+        if let Some(isrc) = track.isrc.as_deref().filter(|i| !i.trim().is_empty()) {
+            queries.push(isrc.trim().to_string());
         }
+        queries.push(format!("{} - {}", track.title, track.author));
 
-        if let Some((prefix, _)) = identifier.split_once(':') {
-            if let Some(source_name) = self.search_term_map.get(prefix) {
-                if let Some(source) = self.sources.get(source_name) {
-                    return source.load_stream(identifier).await;
+        for query in &queries {
+            for source in &providers {
+                let timeout = self.search.timeout_for(source.name());
+                let tracks = match tokio::time::timeout(timeout, source.search(query, "track")).await {
+                    Ok(res) => match res.data {
+                        LoadResultData::Search(tracks) => tracks,
+                        LoadResultData::Track(track) => vec![*track],
+                        _ => continue,
+                    },
+                    Err(_) => continue,
+                };
+
+                if let Some(found) = self.mirroring.best_match(&original, tracks) {
+                    return Some(found.info);
                 }
             }
         }
 
+        log(Level::Warn, "SourceManager", format!("No mirror found for {}:{}", track.source_name, track.identifier));
         None
     }
-    
-    pub fn _list(&self) -> Vec<String> {
-        self.sources.keys().cloned().collect()
+
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.sources.keys().cloned().collect();
+        names.sort();
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::LocalSourceConfig;
+    use crate::utils::encoding::encode_track;
+    use std::time::Duration;
+
+    /// Answers searches with fixed titles, or never answers when it has none.
+    struct StaticSource {
+        name: &'static str,
+        priority: u32,
+        titles: Option<Vec<&'static str>>,
+    }
+
+    impl StaticSource {
+        fn track(&self, title: &str) -> DecodedTrack {
+            let info = DecodedInfo {
+                title: title.to_string(),
+                author: "Artist".to_string(),
+                length: 200_000,
+                identifier: format!("{}:{}", self.name, title),
+                is_stream: false,
+                uri: None,
+                artwork_url: None,
+                isrc: None,
+                source_name: self.name.to_string(),
+                position: 0,
+                source_data: Vec::new(),
+            };
+            DecodedTrack {
+                encoded: encode_track(&info),
+                info,
+                plugin_info: serde_json::json!({}),
+                user_data: serde_json::json!({}),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl Source for StaticSource {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn priority(&self) -> u32 {
+            self.priority
+        }
+
+        async fn search(&self, _query: &str, _search_type: &str) -> LoadTracksResponse {
+            let Some(titles) = &self.titles else { return std::future::pending().await };
+            LoadTracksResponse {
+                load_type: LoadType::Search,
+                data: LoadResultData::Search(titles.iter().map(|t| self.track(t)).collect()),
+            }
+        }
+
+        async fn resolve(&self, _url: &str) -> LoadTracksResponse {
+            LoadTracksResponse::error(ErrorData::new("Not supported", Severity::Common, "Test source"))
+        }
+
+        async fn load_stream(&self, _track: &DecodedInfo) -> Result<MediaStream, ErrorData> {
+            Err(ErrorData::new("Not supported", Severity::Common, "Test source"))
+        }
+    }
+
+    fn with_local(root: &Path) -> SourceManager {
+        let sandbox = Arc::new(LocalSandbox::new(&[root.to_string_lossy().to_string()]));
+        let mut sources = SourceManager::new();
+        sources.set_local_sandbox(sandbox.clone());
+        sources.register(Box::new(LocalSource::new(LocalSourceConfig::default(), "http://localhost:2333", sandbox)));
+        sources
+    }
+
+    fn load_failed(message: &str, cause: &str) -> serde_json::Value {
+        serde_json::json!({
+            "loadType": "error",
+            "data": {
+                "message": message,
+                "severity": "common",
+                "cause": cause,
+                "causeStackTrace": cause,
+            },
+        })
+    }
+
+    #[tokio::test]
+    async fn refuses_paths_outside_allowed_roots() {
+        let root = std::env::temp_dir().join(format!("aelira-sources-roots-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let sources = with_local(&root);
+        let outside = "The path is outside of the configured allowed_roots";
+
+        for path in ["/etc/passwd", "/etc/no-such-file"] {
+            let res = serde_json::to_value(sources.load_tracks(path).await).unwrap();
+            assert_eq!(res, load_failed(&format!("Access to {} is not allowed", path), outside));
+        }
+
+        let traversal = format!("{}/../../etc/passwd", root.display());
+        let res = serde_json::to_value(sources.load_tracks(&traversal).await).unwrap();
+        assert_eq!(res, load_failed(&format!("Access to {} is not allowed", traversal), outside));
+
+        let res = serde_json::to_value(sources.load_tracks("local:/etc/passwd").await).unwrap();
+        assert_eq!(res, load_failed("Access to /etc/passwd is not allowed", outside));
+
+        let missing = root.join("missing.mp3").to_string_lossy().to_string();
+        let res = sources.load_tracks(&missing).await;
+        std::fs::remove_dir_all(&root).unwrap();
+        assert!(matches!(res.load_type, LoadType::Empty));
+    }
+
+    #[tokio::test]
+    async fn refuses_paths_when_local_files_are_disabled() {
+        let sources = SourceManager::new();
+        let disabled = load_failed("Local file access is disabled", "The local source is disabled in the configuration");
+
+        for path in ["/etc/passwd", "./song.mp3", "../song.mp3", "file:/etc/passwd"] {
+            let res = serde_json::to_value(sources.load_tracks(path).await).unwrap();
+            assert_eq!(res, disabled, "{}", path);
+        }
+        assert!(matches!(sources.load_tracks("song title").await.load_type, LoadType::Empty));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn unified_search_does_not_wait_for_silent_sources() {
+        let mut sources = SourceManager::new();
+        sources.register(Box::new(StaticSource { name: "low", priority: 5, titles: Some(vec!["Song", "Other Song"]) }));
+        sources.register(Box::new(StaticSource { name: "high", priority: 20, titles: Some(vec!["Song", "Unrelated"]) }));
+        sources.register(Box::new(StaticSource { name: "silent", priority: 30, titles: None }));
+        sources.set_search(SearchSettings {
+            timeout: Duration::from_secs(60),
+            source_timeouts: HashMap::from([("silent".to_string(), Duration::from_millis(200))]),
+            ..SearchSettings::default()
+        });
+
+        let started = tokio::time::Instant::now();
+        let results = sources.unified_search("song").await;
+        assert_eq!(started.elapsed(), Duration::from_millis(200));
+
+        let identifiers: Vec<&str> = results.iter().map(|t| t.info.identifier.as_str()).collect();
+        assert_eq!(identifiers, ["high:Song", "low:Other Song", "high:Unrelated"]);
     }
 }
@@ -1,34 +1,82 @@
-use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use std::collections::HashMap;
-use std::sync::Mutex;
+use dashmap::DashMap;
+use serde::Serialize;
+use sysinfo::System;
+
+#[derive(Serialize)]
+pub struct MemoryStats {
+    pub free: u64,
+    pub used: u64,
+    pub allocated: u64,
+    pub reservable: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CpuStats {
+    pub cores: usize,
+    pub system_load: f32,
+    pub aelira_load: f32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatsSnapshot {
+    pub players: u32,
+    pub playing_players: u32,
+    pub uptime: u64,
+    pub memory: MemoryStats,
+    pub cpu: CpuStats,
+}
 
 pub struct StatsManager {
-    pub api_requests: Mutex<HashMap<String, AtomicU32>>,
-    pub _api_errors: Mutex<HashMap<String, AtomicU32>>,
+    pub api_requests: DashMap<String, AtomicU32>,
+    pub _api_errors: DashMap<String, AtomicU32>,
     pub players: AtomicU32,
     pub playing_players: AtomicU32,
+    memory_free: AtomicU64,
+    memory_used: AtomicU64,
+    memory_total: AtomicU64,
+    cpu_cores: AtomicU32,
+    system_load: AtomicU32,
+}
+
+impl Default for StatsManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl StatsManager {
     pub fn new() -> Self {
         Self {
-            api_requests: Mutex::new(HashMap::new()),
-            _api_errors: Mutex::new(HashMap::new()),
+            api_requests: DashMap::new(),
+            _api_errors: DashMap::new(),
             players: AtomicU32::new(0),
             playing_players: AtomicU32::new(0),
+            memory_free: AtomicU64::new(0),
+            memory_used: AtomicU64::new(0),
+            memory_total: AtomicU64::new(0),
+            cpu_cores: AtomicU32::new(0),
+            system_load: AtomicU32::new(0),
         }
     }
 
+    fn increment(map: &DashMap<String, AtomicU32>, endpoint: &str) {
+        if let Some(counter) = map.get(endpoint) {
+            counter.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        map.entry(endpoint.to_string()).or_insert_with(|| AtomicU32::new(0)).fetch_add(1, Ordering::Relaxed);
+    }
+
     pub fn increment_api_request(&self, endpoint: &str) {
-        let mut map = self.api_requests.lock().unwrap();
-        let counter = map.entry(endpoint.to_string()).or_insert_with(|| AtomicU32::new(0));
-        counter.fetch_add(1, Ordering::Relaxed);
+        Self::increment(&self.api_requests, endpoint);
     }
 
     pub fn _increment_api_error(&self, endpoint: &str) {
-        let mut map = self._api_errors.lock().unwrap();
-        let counter = map.entry(endpoint.to_string()).or_insert_with(|| AtomicU32::new(0));
-        counter.fetch_add(1, Ordering::Relaxed);
+        Self::increment(&self._api_errors, endpoint);
     }
 
     pub fn set_players(&self, count: u32) {
@@ -38,9 +86,37 @@ impl StatsManager {
     pub fn set_playing_players(&self, count: u32) {
         self.playing_players.store(count, Ordering::Relaxed);
     }
-    
+
+    /// Publishes the latest readings from the stats loop, which owns the `System` handle.
+    pub fn set_system(&self, sys: &System) {
+        self.memory_free.store(sys.free_memory(), Ordering::Relaxed);
+        self.memory_used.store(sys.used_memory(), Ordering::Relaxed);
+        self.memory_total.store(sys.total_memory(), Ordering::Relaxed);
+        self.cpu_cores.store(sys.cpus().len() as u32, Ordering::Relaxed);
+        self.system_load.store(sys.global_cpu_usage().to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> StatsSnapshot {
+        let used = self.memory_used.load(Ordering::Relaxed);
+        StatsSnapshot {
+            players: self.players.load(Ordering::Relaxed),
+            playing_players: self.playing_players.load(Ordering::Relaxed),
+            uptime: System::uptime() * 1000,
+            memory: MemoryStats {
+                free: self.memory_free.load(Ordering::Relaxed),
+                used,
+                allocated: used,
+                reservable: self.memory_total.load(Ordering::Relaxed),
+            },
+            cpu: CpuStats {
+                cores: self.cpu_cores.load(Ordering::Relaxed) as usize,
+                system_load: f32::from_bits(self.system_load.load(Ordering::Relaxed)),
+                aelira_load: 0.0,
+            },
+        }
+    }
+
     pub fn _get_api_stats(&self) -> HashMap<String, u32> {
-        let map = self.api_requests.lock().unwrap();
-        map.iter().map(|(k, v)| (k.clone(), v.load(Ordering::Relaxed))).collect()
+        self.api_requests.iter().map(|e| (e.key().clone(), e.value().load(Ordering::Relaxed))).collect()
     }
 }
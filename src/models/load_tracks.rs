@@ -1,16 +1,16 @@
 use serde::Serialize;
+use crate::utils::cause_chain;
 use crate::utils::encoding::DecodedTrack;
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct LoadTracksResponse {
     pub load_type: LoadType,
     pub data: LoadResultData,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 #[serde(rename_all = "lowercase")]
-#[allow(dead_code)]
 pub enum LoadType {
     Track,
     Playlist,
@@ -19,18 +19,17 @@ pub enum LoadType {
     Error,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 #[serde(untagged)]
-#[allow(dead_code)]
 pub enum LoadResultData {
-    Track(DecodedTrack),
+    Track(Box<DecodedTrack>),
     Playlist(PlaylistData),
     Search(Vec<DecodedTrack>),
     Empty(serde_json::Value),
     Error(ErrorData),
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct PlaylistData {
     pub info: PlaylistInfo,
@@ -38,17 +37,56 @@ pub struct PlaylistData {
     pub tracks: Vec<DecodedTrack>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct PlaylistInfo {
     pub name: String,
     pub selected_track: i32,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Common,
+    Suspicious,
+    Fault,
+}
+
+#[derive(Serialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct ErrorData {
     pub message: String,
-    pub severity: String,
+    pub severity: Severity,
     pub cause: String,
+    pub cause_stack_trace: String,
+}
+
+impl ErrorData {
+    pub fn new(message: impl Into<String>, severity: Severity, cause: impl Into<String>) -> Self {
+        let cause = cause.into();
+        Self {
+            message: message.into(),
+            severity,
+            cause_stack_trace: cause.clone(),
+            cause,
+        }
+    }
+
+    pub fn from_error(message: impl Into<String>, severity: Severity, error: &dyn std::error::Error) -> Self {
+        Self {
+            message: message.into(),
+            severity,
+            cause: error.to_string(),
+            cause_stack_trace: cause_chain(error),
+        }
+    }
+}
+
+impl LoadTracksResponse {
+    pub fn error(error: ErrorData) -> Self {
+        Self {
+            load_type: LoadType::Error,
+            data: LoadResultData::Error(error),
+        }
+    }
 }
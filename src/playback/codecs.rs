@@ -1,6 +1,7 @@
+use symphonia::core::codecs::{self, CodecType};
 use symphonia::core::probe::Hint;
 
-#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum AudioContainer {
     Webm,
     Mp4,
@@ -9,19 +10,104 @@ pub enum AudioContainer {
     Mp3,
     Flac,
     Aac,
+    Unknown,
 }
 
-pub fn map_mime_to_hint(mime: &str) -> Hint {
-    let mut hint = Hint::new();
-    match mime {
-        "audio/webm" | "video/webm" => { hint.with_extension("webm"); },
-        "audio/mp4" | "video/mp4" => { hint.with_extension("mp4"); },
-        "audio/ogg" | "application/ogg" => { hint.with_extension("ogg"); },
-        "audio/wav" | "audio/x-wav" => { hint.with_extension("wav"); },
-        "audio/mpeg" | "audio/mp3" => { hint.with_extension("mp3"); },
-        "audio/flac" | "audio/x-flac" => { hint.with_extension("flac"); },
-        "audio/aac" | "audio/aacp" => { hint.with_extension("aac"); },
-        _ => {}
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AudioCodec {
+    Opus,
+    Aac,
+    Mp3,
+    Flac,
+    Vorbis,
+    Pcm,
+    Unknown,
+}
+
+impl AudioContainer {
+    pub fn from_extension(ext: &str) -> Self {
+        match ext.to_ascii_lowercase().as_str() {
+            "webm" | "mkv" | "mka" => AudioContainer::Webm,
+            "mp4" | "m4a" | "m4b" => AudioContainer::Mp4,
+            "ogg" | "oga" | "opus" => AudioContainer::Ogg,
+            "wav" | "wave" => AudioContainer::Wav,
+            "mp3" => AudioContainer::Mp3,
+            "flac" => AudioContainer::Flac,
+            "aac" | "adts" => AudioContainer::Aac,
+            _ => AudioContainer::Unknown,
+        }
+    }
+
+    pub fn from_mime(mime: &str) -> Self {
+        let mime = mime.split(';').next().unwrap_or("").trim();
+        match mime {
+            "audio/webm" | "video/webm" => AudioContainer::Webm,
+            "audio/mp4" | "video/mp4" => AudioContainer::Mp4,
+            "audio/ogg" | "application/ogg" => AudioContainer::Ogg,
+            "audio/wav" | "audio/x-wav" => AudioContainer::Wav,
+            "audio/mpeg" | "audio/mp3" | "audio/x-mpeg" => AudioContainer::Mp3,
+            "audio/flac" | "audio/x-flac" => AudioContainer::Flac,
+            "audio/aac" | "audio/aacp" | "audio/x-aac" => AudioContainer::Aac,
+            _ => AudioContainer::Unknown,
+        }
+    }
+
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            AudioContainer::Webm => Some("webm"),
+            AudioContainer::Mp4 => Some("mp4"),
+            AudioContainer::Ogg => Some("ogg"),
+            AudioContainer::Wav => Some("wav"),
+            AudioContainer::Mp3 => Some("mp3"),
+            AudioContainer::Flac => Some("flac"),
+            AudioContainer::Aac => Some("aac"),
+            AudioContainer::Unknown => None,
+        }
     }
-    hint
+
+    /// Name of the matching Lavalink container probe, stored in HTTP and local track data.
+    pub fn probe_name(&self) -> Option<&'static str> {
+        match self {
+            AudioContainer::Webm => Some("matroska/webm"),
+            AudioContainer::Mp4 => Some("mp4"),
+            AudioContainer::Ogg => Some("ogg"),
+            AudioContainer::Wav => Some("wav"),
+            AudioContainer::Mp3 => Some("mp3"),
+            AudioContainer::Flac => Some("flac"),
+            AudioContainer::Aac => Some("adts"),
+            AudioContainer::Unknown => None,
+        }
+    }
+
+    pub fn hint(&self) -> Hint {
+        let mut hint = Hint::new();
+        if let Some(ext) = self.extension() {
+            hint.with_extension(ext);
+        }
+        hint
+    }
+}
+
+impl AudioCodec {
+    pub fn from_symphonia(codec: CodecType) -> Self {
+        match codec {
+            codecs::CODEC_TYPE_OPUS => AudioCodec::Opus,
+            codecs::CODEC_TYPE_AAC => AudioCodec::Aac,
+            codecs::CODEC_TYPE_MP3 => AudioCodec::Mp3,
+            codecs::CODEC_TYPE_FLAC => AudioCodec::Flac,
+            codecs::CODEC_TYPE_VORBIS => AudioCodec::Vorbis,
+            codecs::CODEC_TYPE_PCM_S16LE
+            | codecs::CODEC_TYPE_PCM_S16BE
+            | codecs::CODEC_TYPE_PCM_S24LE
+            | codecs::CODEC_TYPE_PCM_S32LE
+            | codecs::CODEC_TYPE_PCM_F32LE
+            | codecs::CODEC_TYPE_PCM_F64LE
+            | codecs::CODEC_TYPE_PCM_U8 => AudioCodec::Pcm,
+            _ => AudioCodec::Unknown,
+        }
+    }
+}
+
+pub fn map_mime_to_hint(mime: &str) -> Hint {
+    AudioContainer::from_mime(mime).hint()
 }
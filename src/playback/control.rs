@@ -0,0 +1,110 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::Mutex;
+use crate::managers::plugins::PcmFilter;
+use crate::playback::opus::OpusSettings;
+use crate::playback::voice::stream::FRAME_DURATION;
+
+/// State shared between a player actor and its audio loop. The actor writes pause, gain, seek
+/// and encoder settings; the audio loop reads them once per frame and publishes the position.
+pub struct PlaybackControl {
+    paused: AtomicBool,
+    gain: AtomicU32,
+    position: AtomicU64,
+    seek_target: AtomicU64,
+    opus: Mutex<OpusSettings>,
+    opus_version: AtomicU64,
+    filters: Mutex<Vec<Box<dyn PcmFilter>>>,
+    filtered: AtomicBool,
+}
+
+impl PlaybackControl {
+    pub fn new(position: u64, paused: bool, gain: f32, opus: OpusSettings) -> Self {
+        Self {
+            paused: AtomicBool::new(paused),
+            gain: AtomicU32::new(gain.to_bits()),
+            position: AtomicU64::new(0),
+            seek_target: AtomicU64::new(position),
+            opus: Mutex::new(opus),
+            opus_version: AtomicU64::new(1),
+            filters: Mutex::new(Vec::new()),
+            filtered: AtomicBool::new(false),
+        }
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+    }
+
+    /// Linear gain applied to decoded samples, combining the player volume and the volume filter.
+    pub fn gain(&self) -> f32 {
+        f32::from_bits(self.gain.load(Ordering::Relaxed))
+    }
+
+    pub fn set_gain(&self, gain: f32) {
+        self.gain.store(gain.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Milliseconds of audio consumed from the source so far.
+    pub fn position(&self) -> u64 {
+        self.position.load(Ordering::Relaxed)
+    }
+
+    /// Called by the audio loop for every frame taken from the source.
+    pub fn advance(&self) {
+        self.position.fetch_add(FRAME_DURATION, Ordering::Relaxed);
+    }
+
+    /// Requests a forward seek; frames are consumed without being sent until the target is reached.
+    pub fn seek_to(&self, position: u64) {
+        self.seek_target.store(position, Ordering::Relaxed);
+    }
+
+    pub fn seek_target(&self) -> u64 {
+        self.seek_target.load(Ordering::Relaxed)
+    }
+
+    /// Whether the audio loop is still skipping towards a seek target.
+    pub fn seeking(&self) -> bool {
+        self.position() < self.seek_target()
+    }
+
+    pub fn set_opus(&self, opus: OpusSettings) {
+        *self.opus.lock().unwrap() = opus;
+        self.opus_version.fetch_add(1, Ordering::Release);
+    }
+
+    /// Returns the encoder settings if they changed since `seen`, which is then updated.
+    /// Encoders start with `seen` at 0 so the first call always returns the settings.
+    pub fn opus_changed(&self, seen: &mut u64) -> Option<OpusSettings> {
+        let version = self.opus_version.load(Ordering::Acquire);
+        if version == *seen {
+            return None;
+        }
+        *seen = version;
+        Some(*self.opus.lock().unwrap())
+    }
+
+    pub fn set_filters(&self, filters: Vec<Box<dyn PcmFilter>>) {
+        self.filtered.store(!filters.is_empty(), Ordering::Relaxed);
+        *self.filters.lock().unwrap() = filters;
+    }
+
+    /// Whether plugin filters are set, so passthrough Opus has to be decoded for them.
+    pub fn filtered(&self) -> bool {
+        self.filtered.load(Ordering::Relaxed)
+    }
+
+    /// Runs the plugin filters over a decoded frame.
+    pub fn filter(&self, frame: &mut [f32]) {
+        if !self.filtered() {
+            return;
+        }
+        for filter in self.filters.lock().unwrap().iter_mut() {
+            filter.process(frame);
+        }
+    }
+}
@@ -1,11 +1,14 @@
-use std::io::{Read, Seek};
+use std::io::Read;
 use symphonia::core::io::{MediaSourceStream, ReadOnlySource};
 use symphonia::core::probe::Hint;
 use symphonia::core::formats::{FormatReader};
-use symphonia::core::codecs::{Decoder, DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::codecs::{self, CodecParameters, Decoder, DecoderOptions, CODEC_TYPE_NULL};
 use symphonia::core::errors::Error;
 use symphonia::core::audio::AudioBufferRef;
-use crate::playback::codecs::map_mime_to_hint;
+
+use symphonia::core::formats::Packet;
+
+use crate::playback::codecs::AudioCodec;
 
 pub struct AudioDecoder {
 
@@ -20,25 +23,14 @@ pub struct AudioDecoder {
 
 
 #[allow(dead_code)]
-
 impl AudioDecoder {
 
-    pub fn new<R: Read + Seek + Send + Sync + 'static>(source: R, mime: Option<&str>) -> Result<Self, Error> {
+    pub fn new<R: Read + Send + Sync + 'static>(source: R, hint: Hint) -> Result<Self, Error> {
 
         let source = ReadOnlySource::new(source);
 
         let mss = MediaSourceStream::new(Box::new(source), Default::default());
 
-        let mut hint = Hint::new();
-
-        if let Some(m) = mime {
-
-            hint = map_mime_to_hint(m);
-
-        }
-
-
-
         let probed = symphonia::default::get_probe()
 
             .format(&hint, mss, &Default::default(), &Default::default())?;
@@ -100,3 +92,87 @@ impl AudioDecoder {
     }
 
 }
+
+
+
+pub struct PacketDecoder {
+
+    decoder: Box<dyn Decoder>,
+
+    timestamp: u64,
+
+}
+
+
+
+impl PacketDecoder {
+
+    pub fn new(codec: AudioCodec, sample_rate: u32, channels: u16, extra_data: &[u8]) -> Result<Self, Error> {
+
+        let codec_type = match codec {
+
+            AudioCodec::Aac => codecs::CODEC_TYPE_AAC,
+
+            AudioCodec::Flac => codecs::CODEC_TYPE_FLAC,
+
+            AudioCodec::Mp3 => codecs::CODEC_TYPE_MP3,
+
+            _ => return Err(Error::Unsupported("Unsupported packet codec")),
+
+        };
+
+        let mut params = CodecParameters::new();
+
+        params.for_codec(codec_type);
+
+        if sample_rate > 0 {
+
+            params.with_sample_rate(sample_rate);
+
+        }
+
+        if let Some(layout) = match channels {
+
+            1 => Some(symphonia::core::audio::Layout::Mono),
+
+            2 => Some(symphonia::core::audio::Layout::Stereo),
+
+            _ => None,
+
+        } {
+
+            params.with_channel_layout(layout);
+
+        }
+
+        if !extra_data.is_empty() {
+
+            params.with_extra_data(extra_data.to_vec().into_boxed_slice());
+
+        }
+
+        let decoder = symphonia::default::get_codecs()
+
+            .make(&params, &DecoderOptions::default())?;
+
+        Ok(Self {
+
+            decoder,
+
+            timestamp: 0,
+
+        })
+
+    }
+
+    pub fn decode(&mut self, data: &[u8]) -> Result<AudioBufferRef<'_>, Error> {
+
+        let packet = Packet::new_from_slice(0, self.timestamp, 0, data);
+
+        self.timestamp += 1;
+
+        self.decoder.decode(&packet)
+
+    }
+
+}
@@ -1 +1,2 @@
+pub mod mp4;
 pub mod webm;
\ No newline at end of file
@@ -0,0 +1,851 @@
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use bytes::Bytes;
+use tokio::io::AsyncReadExt;
+use crate::playback::codecs::AudioCodec;
+use crate::playback::media::MediaReader;
+use crate::utils::{log, Level};
+
+const MAX_BOX_SIZE: u64 = 64 * 1024 * 1024;
+/// As many samples as a `MAX_BOX_SIZE` table of 16-byte entries can describe, about a day of AAC.
+const MAX_TRACK_SAMPLES: usize = (MAX_BOX_SIZE / 16) as usize;
+
+const OTI_MPEG4_AUDIO: u8 = 0x40;
+const OTI_MPEG2_AAC_MAIN: u8 = 0x66;
+const OTI_MPEG2_AAC_SSR: u8 = 0x68;
+const OTI_MPEG2_AUDIO: u8 = 0x69;
+const OTI_MPEG1_AUDIO: u8 = 0x6B;
+
+#[derive(Clone, Debug)]
+pub struct Mp4Track {
+    pub id: u32,
+    pub codec: AudioCodec,
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub timescale: u32,
+    pub config: Bytes,
+}
+
+#[derive(Clone, Copy)]
+struct SampleRef {
+    offset: u64,
+    size: u64,
+}
+
+#[derive(Default, Clone, Copy)]
+struct TrackDefaults {
+    sample_size: u32,
+}
+
+pub struct Mp4Demuxer {
+    reader: MediaReader,
+    position: u64,
+    track: Mp4Track,
+    defaults: TrackDefaults,
+    samples: VecDeque<SampleRef>,
+    mdat_end: Option<u64>,
+}
+
+struct BoxHeader {
+    kind: [u8; 4],
+    start: u64,
+    header_len: u64,
+    size: Option<u64>,
+}
+
+impl BoxHeader {
+    fn body_len(&self) -> Option<u64> {
+        self.size.map(|s| s.saturating_sub(self.header_len))
+    }
+
+    /// Where the box ends, or `None` if it runs to the end of the file. `read_header` makes
+    /// sure this doesn't overflow.
+    fn end(&self) -> Option<u64> {
+        self.size.map(|s| self.start + s)
+    }
+}
+
+impl Mp4Demuxer {
+    pub async fn open(reader: MediaReader) -> io::Result<Self> {
+        let mut reader = reader;
+        let mut position = 0;
+        let mut skipped_mdat: Option<u64> = None;
+
+        loop {
+            let header = match read_header(&mut reader, position).await? {
+                Some(h) => h,
+                None => return Err(invalid("No moov box found")),
+            };
+            position += header.header_len;
+
+            match &header.kind {
+                b"moov" => {
+                    let body = read_body(&mut reader, &header).await?;
+                    position += body.len() as u64;
+
+                    let (track, defaults, stbl) = parse_moov(&body)
+                        .ok_or_else(|| invalid("No supported audio track found"))?;
+                    let samples = parse_sample_table(stbl)?;
+
+                    log(Level::Debug, "Mp4Demuxer", format!("Audio track {} selected ({:?}, {} Hz, {} ch)", track.id, track.codec, track.sample_rate, track.channels));
+
+                    if let Some(mdat_start) = skipped_mdat {
+                        reader.seek_to(mdat_start).await?;
+                        position = mdat_start;
+                    }
+
+                    return Ok(Self {
+                        reader,
+                        position,
+                        track,
+                        defaults,
+                        samples: samples.into(),
+                        mdat_end: None,
+                    });
+                },
+                b"mdat" if reader.is_seekable() && skipped_mdat.is_none() => {
+                    let len = header.body_len().ok_or_else(|| invalid("Unbounded mdat before moov"))?;
+                    skipped_mdat = Some(header.start);
+                    reader.skip(len).await?;
+                    position += len;
+                },
+                b"mdat" => return Err(invalid("moov box comes after media data on an unseekable stream")),
+                _ => {
+                    let len = header.body_len().ok_or_else(|| invalid("Unbounded box before moov"))?;
+                    reader.skip(len).await?;
+                    position += len;
+                }
+            }
+        }
+    }
+
+    pub fn track(&self) -> &Mp4Track {
+        &self.track
+    }
+
+    pub async fn next_sample(&mut self) -> Option<io::Result<Bytes>> {
+        match self.read_sample().await {
+            Ok(sample) => sample.map(Ok),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+
+    async fn read_sample(&mut self) -> io::Result<Option<Bytes>> {
+        loop {
+            if let Some(mdat_end) = self.mdat_end {
+                while let Some(sample) = self.samples.front().copied() {
+                    if sample.offset < self.position {
+                        self.samples.pop_front();
+                        continue;
+                    }
+                    let end = sample.offset.checked_add(sample.size).ok_or_else(|| invalid("Sample offset overflows"))?;
+                    if end > mdat_end {
+                        break;
+                    }
+
+                    self.samples.pop_front();
+                    if sample.size > MAX_BOX_SIZE {
+                        return Err(invalid("Sample is too large"));
+                    }
+                    self.reader.skip(sample.offset - self.position).await?;
+                    let mut data = vec![0; sample.size as usize];
+                    self.reader.read_exact(&mut data).await?;
+                    self.position = end;
+                    return Ok(Some(Bytes::from(data)));
+                }
+
+                if mdat_end == u64::MAX {
+                    return Ok(None);
+                }
+                self.reader.skip(mdat_end - self.position).await?;
+                self.position = mdat_end;
+                self.mdat_end = None;
+            }
+
+            let Some(header) = read_header(&mut self.reader, self.position).await? else { return Ok(None) };
+            self.position += header.header_len;
+
+            match &header.kind {
+                b"moof" => {
+                    let body = read_body(&mut self.reader, &header).await?;
+                    self.position += body.len() as u64;
+                    let fragment = parse_moof(&body, header.start, self.track.id, self.defaults, self.samples.len())?;
+                    self.samples.extend(fragment);
+                },
+                b"mdat" => {
+                    self.mdat_end = Some(header.end().unwrap_or(u64::MAX));
+                },
+                _ => {
+                    let Some(len) = header.body_len() else { return Ok(None) };
+                    self.reader.skip(len).await?;
+                    self.position += len;
+                }
+            }
+        }
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+async fn read_header(reader: &mut MediaReader, start: u64) -> io::Result<Option<BoxHeader>> {
+    let mut head = [0u8; 8];
+    match reader.read_exact(&mut head).await {
+        Ok(_) => {},
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+
+    let size = u32::from_be_bytes([head[0], head[1], head[2], head[3]]) as u64;
+    let kind = [head[4], head[5], head[6], head[7]];
+
+    let (size, header_len) = match size {
+        0 => (None, 8),
+        1 => {
+            let mut large = [0u8; 8];
+            reader.read_exact(&mut large).await?;
+            (Some(u64::from_be_bytes(large)), 16)
+        },
+        size => (Some(size), 8),
+    };
+
+    if size.is_some_and(|s| s < header_len || start.checked_add(s).is_none()) {
+        return Err(invalid("Invalid box size"));
+    }
+
+    Ok(Some(BoxHeader { kind, start, header_len, size }))
+}
+
+async fn read_body(reader: &mut MediaReader, header: &BoxHeader) -> io::Result<Vec<u8>> {
+    let len = header.body_len().ok_or_else(|| invalid("Unbounded metadata box"))?;
+    if len > MAX_BOX_SIZE {
+        return Err(invalid("Metadata box is too large"));
+    }
+
+    let mut body = vec![0; len as usize];
+    reader.read_exact(&mut body).await?;
+    Ok(body)
+}
+
+struct Cursor<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let slice = self.data.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(slice)
+    }
+
+    fn skip(&mut self, len: usize) -> Option<()> {
+        self.take(len).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.take(8).map(|b| u64::from_be_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]))
+    }
+
+    fn rest(&self) -> &'a [u8] {
+        &self.data[self.pos.min(self.data.len())..]
+    }
+}
+
+fn children(data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    let mut offset = 0;
+    std::iter::from_fn(move || {
+        let head = data.get(offset..offset + 8)?;
+        let size = u32::from_be_bytes([head[0], head[1], head[2], head[3]]) as usize;
+        let kind = [head[4], head[5], head[6], head[7]];
+
+        let (header_len, size) = match size {
+            0 => (8, data.len() - offset),
+            1 => {
+                let large = data.get(offset + 8..offset + 16)?;
+                (16, u64::from_be_bytes(large.try_into().ok()?) as usize)
+            },
+            size => (8, size),
+        };
+
+        let body = data.get(offset + header_len..offset.checked_add(size)?)?;
+        offset += size.max(header_len);
+        Some((kind, body))
+    })
+}
+
+fn find<'a>(data: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    children(data).find(|(k, _)| k == kind).map(|(_, body)| body)
+}
+
+/// Finds the first supported audio track, returning it with its fragment defaults and sample table.
+fn parse_moov(moov: &[u8]) -> Option<(Mp4Track, TrackDefaults, &[u8])> {
+    let mut trex = HashMap::new();
+    if let Some(mvex) = find(moov, b"mvex") {
+        for (kind, body) in children(mvex) {
+            if &kind == b"trex" {
+                let mut c = Cursor::new(body);
+                c.skip(4)?;
+                let track_id = c.u32()?;
+                c.skip(8)?;
+                trex.insert(track_id, TrackDefaults { sample_size: c.u32()? });
+            }
+        }
+    }
+
+    children(moov)
+        .filter(|(kind, _)| kind == b"trak")
+        .find_map(|(_, trak)| parse_trak(trak))
+        .map(|(track, stbl)| {
+            let defaults = trex.get(&track.id).copied().unwrap_or_default();
+            (track, defaults, stbl)
+        })
+}
+
+fn parse_trak(trak: &[u8]) -> Option<(Mp4Track, &[u8])> {
+    let tkhd = find(trak, b"tkhd")?;
+    let mut c = Cursor::new(tkhd);
+    let version = c.u8()?;
+    c.skip(3 + if version == 1 { 16 } else { 8 })?;
+    let id = c.u32()?;
+
+    let mdia = find(trak, b"mdia")?;
+    let hdlr = find(mdia, b"hdlr")?;
+    if hdlr.get(8..12)? != b"soun" {
+        return None;
+    }
+
+    let mdhd = find(mdia, b"mdhd")?;
+    let mut c = Cursor::new(mdhd);
+    let version = c.u8()?;
+    c.skip(3 + if version == 1 { 16 } else { 8 })?;
+    let timescale = c.u32()?;
+
+    let stbl = find(find(mdia, b"minf")?, b"stbl")?;
+    let mut track = parse_stsd(find(stbl, b"stsd")?)?;
+    track.id = id;
+    track.timescale = timescale;
+
+    Some((track, stbl))
+}
+
+fn parse_stsd(stsd: &[u8]) -> Option<Mp4Track> {
+    let entries = stsd.get(8..)?;
+    let (kind, entry) = children(entries).next()?;
+
+    let mut c = Cursor::new(entry);
+    c.skip(8)?;
+    let version = c.u16()?;
+    c.skip(6)?;
+    let channels = c.u16()?;
+    c.skip(6)?;
+    let sample_rate = c.u32()? >> 16;
+    c.skip(match version {
+        1 => 16,
+        2 => 36,
+        _ => 0,
+    })?;
+    let boxes = c.rest();
+
+    let (codec, config) = match &kind {
+        b"Opus" => (AudioCodec::Opus, Bytes::copy_from_slice(find(boxes, b"dOps").unwrap_or_default())),
+        b"fLaC" => {
+            let dfla = find(boxes, b"dfLa")?;
+            (AudioCodec::Flac, Bytes::copy_from_slice(dfla.get(8..42)?))
+        },
+        b"mp4a" => {
+            let esds = find(boxes, b"esds")
+                .or_else(|| find(boxes, b"wave").and_then(|wave| find(wave, b"esds")))?;
+            parse_esds(esds)?
+        },
+        _ => {
+            log(Level::Warn, "Mp4Demuxer", format!("Unsupported sample entry: {}", String::from_utf8_lossy(&kind)));
+            return None;
+        }
+    };
+
+    Some(Mp4Track {
+        id: 0,
+        codec,
+        sample_rate,
+        channels,
+        timescale: 0,
+        config,
+    })
+}
+
+fn read_descriptor<'a>(c: &mut Cursor<'a>) -> Option<(u8, &'a [u8])> {
+    let tag = c.u8()?;
+    let mut len = 0usize;
+    for _ in 0..4 {
+        let byte = c.u8()?;
+        len = (len << 7) | (byte & 0x7F) as usize;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    Some((tag, c.take(len)?))
+}
+
+fn parse_esds(esds: &[u8]) -> Option<(AudioCodec, Bytes)> {
+    let mut c = Cursor::new(esds.get(4..)?);
+    let (tag, es) = read_descriptor(&mut c)?;
+    if tag != 0x03 {
+        return None;
+    }
+
+    let mut c = Cursor::new(es);
+    c.skip(2)?;
+    let flags = c.u8()?;
+    if flags & 0x80 != 0 {
+        c.skip(2)?;
+    }
+    if flags & 0x40 != 0 {
+        let len = c.u8()? as usize;
+        c.skip(len)?;
+    }
+    if flags & 0x20 != 0 {
+        c.skip(2)?;
+    }
+
+    let (tag, config) = read_descriptor(&mut c)?;
+    if tag != 0x04 {
+        return None;
+    }
+
+    let mut c = Cursor::new(config);
+    let object_type = c.u8()?;
+    c.skip(12)?;
+    let specific = read_descriptor(&mut c)
+        .filter(|(tag, _)| *tag == 0x05)
+        .map(|(_, data)| Bytes::copy_from_slice(data))
+        .unwrap_or_default();
+
+    let codec = match object_type {
+        OTI_MPEG4_AUDIO | OTI_MPEG2_AAC_MAIN..=OTI_MPEG2_AAC_SSR => AudioCodec::Aac,
+        OTI_MPEG2_AUDIO | OTI_MPEG1_AUDIO => AudioCodec::Mp3,
+        _ => return None,
+    };
+    Some((codec, specific))
+}
+
+fn parse_sample_table(stbl: &[u8]) -> io::Result<Vec<SampleRef>> {
+    let Some(stsz) = find(stbl, b"stsz") else { return Ok(Vec::new()) };
+    let mut c = Cursor::new(stsz);
+    let (Some(()), Some(fixed_size), Some(count)) = (c.skip(4), c.u32(), c.u32()) else { return Ok(Vec::new()) };
+    let entry_len = if fixed_size != 0 { 0 } else { 4 };
+    let count = sample_count(count, entry_len, c.rest().len(), 0)?;
+
+    match parse_chunks(stbl, fixed_size, count, &mut c) {
+        Some((sizes, offsets, runs)) => layout_samples(sizes, &offsets, &runs),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Checks a sample count read from a box before anything is allocated for it: samples with
+/// `entry_len` bytes each must fit in the `remaining` bytes of the box, and no track may hold
+/// more than `MAX_TRACK_SAMPLES`.
+fn sample_count(count: u32, entry_len: usize, remaining: usize, existing: usize) -> io::Result<usize> {
+    let count = count as usize;
+    let fits = count.checked_mul(entry_len).is_some_and(|len| len <= remaining);
+    if !fits || existing.saturating_add(count) > MAX_TRACK_SAMPLES {
+        return Err(invalid("Sample count exceeds what the box can hold"));
+    }
+    Ok(count)
+}
+
+/// A chunk run from `stsc`: the first chunk it applies to and the samples in each of its chunks.
+type ChunkRun = (usize, usize);
+
+/// Reads the sample sizes, chunk offsets and chunk runs. `None` if a table is missing or truncated.
+fn parse_chunks(stbl: &[u8], fixed_size: u32, count: usize, c: &mut Cursor<'_>) -> Option<(Vec<u64>, Vec<u64>, Vec<ChunkRun>)> {
+    let sizes: Vec<u64> = if fixed_size != 0 {
+        vec![fixed_size as u64; count]
+    } else {
+        (0..count).map(|_| c.u32().map(u64::from)).collect::<Option<_>>()?
+    };
+
+    let offsets: Vec<u64> = if let Some(stco) = find(stbl, b"stco") {
+        let mut c = Cursor::new(stco);
+        c.skip(4)?;
+        let n = c.u32()?;
+        (0..n).map(|_| c.u32().map(u64::from)).collect::<Option<_>>()?
+    } else {
+        let mut c = Cursor::new(find(stbl, b"co64")?);
+        c.skip(4)?;
+        let n = c.u32()?;
+        (0..n).map(|_| c.u64()).collect::<Option<_>>()?
+    };
+
+    let mut c = Cursor::new(find(stbl, b"stsc")?);
+    c.skip(4)?;
+    let n = c.u32()?;
+    let mut runs = Vec::new();
+    for _ in 0..n {
+        let first_chunk = c.u32()? as usize;
+        let per_chunk = c.u32()? as usize;
+        c.skip(4)?;
+        runs.push((first_chunk.max(1), per_chunk));
+    }
+
+    Some((sizes, offsets, runs))
+}
+
+/// Places each sample in its chunk. Offsets come from the file, so one that overflows rejects
+/// the whole table.
+fn layout_samples(sizes: Vec<u64>, offsets: &[u64], runs: &[ChunkRun]) -> io::Result<Vec<SampleRef>> {
+    let mut samples = Vec::with_capacity(sizes.len());
+    let mut sizes = sizes.into_iter();
+    for (index, run) in runs.iter().enumerate() {
+        let last_chunk = runs.get(index + 1).map(|r| r.0 - 1).unwrap_or(offsets.len());
+        for chunk in run.0..=last_chunk {
+            let Some(mut offset) = offsets.get(chunk - 1).copied() else { break };
+            for _ in 0..run.1 {
+                let Some(size) = sizes.next() else { break };
+                samples.push(SampleRef { offset, size });
+                offset = offset.checked_add(size).ok_or_else(|| invalid("Sample offset overflows"))?;
+            }
+        }
+    }
+
+    samples.sort_by_key(|s| s.offset);
+    Ok(samples)
+}
+
+/// Reads a fragment's samples for `track_id`. `pending` samples are still queued from earlier
+/// fragments and count towards the track's limit.
+fn parse_moof(moof: &[u8], moof_start: u64, track_id: u32, defaults: TrackDefaults, pending: usize) -> io::Result<Vec<SampleRef>> {
+    let mut samples = Vec::new();
+
+    for (kind, traf) in children(moof) {
+        if &kind != b"traf" {
+            continue;
+        }
+        parse_traf(traf, moof_start, track_id, defaults, pending, &mut samples)?;
+    }
+
+    samples.sort_by_key(|s| s.offset);
+    Ok(samples)
+}
+
+fn parse_traf(traf: &[u8], moof_start: u64, track_id: u32, defaults: TrackDefaults, pending: usize, samples: &mut Vec<SampleRef>) -> io::Result<()> {
+    let Some((base, default_size)) = parse_tfhd(traf, moof_start, track_id, defaults) else { return Ok(()) };
+
+    let mut next_offset = base;
+    for (kind, trun) in children(traf) {
+        if &kind != b"trun" {
+            continue;
+        }
+        if !parse_trun(trun, base, default_size, pending, &mut next_offset, samples)? {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_tfhd(traf: &[u8], moof_start: u64, track_id: u32, defaults: TrackDefaults) -> Option<(u64, u32)> {
+    let mut c = Cursor::new(find(traf, b"tfhd")?);
+    let flags = c.u32()? & 0x00FF_FFFF;
+    if c.u32()? != track_id {
+        return None;
+    }
+
+    let base = if flags & 0x01 != 0 { c.u64()? } else { moof_start };
+    if flags & 0x02 != 0 {
+        c.skip(4)?;
+    }
+    if flags & 0x08 != 0 {
+        c.skip(4)?;
+    }
+    let default_size = if flags & 0x10 != 0 { c.u32()? } else { defaults.sample_size };
+    Some((base, default_size))
+}
+
+/// Appends a trun box's samples. Returns false if the box is truncated, which ends the fragment.
+fn parse_trun(trun: &[u8], base: u64, default_size: u32, pending: usize, next_offset: &mut u64, samples: &mut Vec<SampleRef>) -> io::Result<bool> {
+    let mut c = Cursor::new(trun);
+    let (Some(flags), Some(count)) = (c.u32(), c.u32()) else { return Ok(false) };
+    let flags = flags & 0x00FF_FFFF;
+    if flags & 0x01 != 0 {
+        let Some(offset) = c.u32().and_then(|o| base.checked_add_signed(o as i32 as i64)) else { return Ok(false) };
+        *next_offset = offset;
+    }
+    if flags & 0x04 != 0 && c.skip(4).is_none() {
+        return Ok(false);
+    }
+
+    let entry_len = [0x100, 0x200, 0x400, 0x800].iter().filter(|&&f| flags & f != 0).count() * 4;
+    let count = sample_count(count, entry_len, c.rest().len(), pending + samples.len())?;
+
+    for _ in 0..count {
+        if flags & 0x100 != 0 {
+            c.skip(4);
+        }
+        let size = if flags & 0x200 != 0 { c.u32().unwrap_or_default() } else { default_size } as u64;
+        if flags & 0x400 != 0 {
+            c.skip(4);
+        }
+        if flags & 0x800 != 0 {
+            c.skip(4);
+        }
+
+        samples.push(SampleRef { offset: *next_offset, size });
+        let Some(offset) = next_offset.checked_add(size) else { return Ok(false) };
+        *next_offset = offset;
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACK_ID: u32 = 1;
+
+    fn mp4_box(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut data = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(body);
+        data
+    }
+
+    fn full_box(kind: &[u8; 4], flags: u32, fields: &[&[u8]]) -> Vec<u8> {
+        mp4_box(kind, &[&flags.to_be_bytes()[..], &fields.concat()].concat())
+    }
+
+    fn u32s(values: &[u32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_be_bytes()).collect()
+    }
+
+    fn stsz(fixed: u32, sizes: &[u32]) -> Vec<u8> {
+        let entries = if fixed != 0 { Vec::new() } else { u32s(sizes) };
+        full_box(b"stsz", 0, &[&fixed.to_be_bytes(), &(sizes.len() as u32).to_be_bytes(), &entries])
+    }
+
+    fn stsc(runs: &[(u32, u32)]) -> Vec<u8> {
+        let entries: Vec<u32> = runs.iter().flat_map(|&(first, per_chunk)| [first, per_chunk, 1]).collect();
+        full_box(b"stsc", 0, &[&(runs.len() as u32).to_be_bytes(), &u32s(&entries)])
+    }
+
+    fn stco(offsets: &[u64]) -> Vec<u8> {
+        let offsets: Vec<u32> = offsets.iter().map(|&o| o as u32).collect();
+        full_box(b"stco", 0, &[&(offsets.len() as u32).to_be_bytes(), &u32s(&offsets)])
+    }
+
+    fn co64(offsets: &[u64]) -> Vec<u8> {
+        let entries: Vec<u8> = offsets.iter().flat_map(|o| o.to_be_bytes()).collect();
+        full_box(b"co64", 0, &[&(offsets.len() as u32).to_be_bytes(), &entries])
+    }
+
+    /// A moov with one Opus track whose sample table holds `tables`, plus `extra` boxes.
+    fn moov(tables: &[Vec<u8>], extra: &[Vec<u8>]) -> Vec<u8> {
+        let opus = mp4_box(b"Opus", &[&[0u8; 8][..], &[0, 0], &[0; 6], &2u16.to_be_bytes(), &[0; 6], &(48_000u32 << 16).to_be_bytes()].concat());
+        let stsd = full_box(b"stsd", 0, &[&1u32.to_be_bytes(), &opus]);
+        let stbl = mp4_box(b"stbl", &[stsd, tables.concat()].concat());
+        let mdia = mp4_box(b"mdia", &[
+            full_box(b"mdhd", 0, &[&[0; 8], &48_000u32.to_be_bytes(), &[0; 8]]),
+            full_box(b"hdlr", 0, &[&[0; 4], b"soun", &[0; 12]]),
+            mp4_box(b"minf", &stbl),
+        ].concat());
+        let trak = mp4_box(b"trak", &[full_box(b"tkhd", 0, &[&[0; 8], &TRACK_ID.to_be_bytes(), &[0; 4]]), mdia].concat());
+        mp4_box(b"moov", &[trak, extra.concat()].concat())
+    }
+
+    fn ftyp() -> Vec<u8> {
+        mp4_box(b"ftyp", b"isom\0\0\0\0isom")
+    }
+
+    /// Builds a file with the moov before or after the mdat. `tables` gets the offset of the
+    /// mdat body, which depends on the layout.
+    fn file(moov_first: bool, tables: impl Fn(u64) -> Vec<Vec<u8>>, mdat: &[u8]) -> Vec<u8> {
+        let ftyp = ftyp();
+        let mdat_box = mp4_box(b"mdat", mdat);
+        if moov_first {
+            let moov_len = moov(&tables(0), &[]).len();
+            let start = (ftyp.len() + moov_len + 8) as u64;
+            [ftyp, moov(&tables(start), &[]), mdat_box].concat()
+        } else {
+            let start = (ftyp.len() + 8) as u64;
+            [ftyp, mdat_box, moov(&tables(start), &[])].concat()
+        }
+    }
+
+    fn reader(data: Vec<u8>, seekable: bool) -> MediaReader {
+        let cursor = std::io::Cursor::new(data);
+        if seekable {
+            MediaReader::Seekable(Box::new(cursor))
+        } else {
+            MediaReader::Sequential(Box::new(cursor))
+        }
+    }
+
+    async fn samples(data: Vec<u8>, seekable: bool) -> io::Result<Vec<Vec<u8>>> {
+        let mut demuxer = Mp4Demuxer::open(reader(data, seekable)).await?;
+        assert_eq!(demuxer.track().codec, AudioCodec::Opus);
+        assert_eq!(demuxer.track().id, TRACK_ID);
+
+        let mut samples = Vec::new();
+        while let Some(sample) = demuxer.next_sample().await {
+            samples.push(sample?.to_vec());
+        }
+        Ok(samples)
+    }
+
+    fn error(result: io::Result<Vec<Vec<u8>>>) -> (io::ErrorKind, String) {
+        let e = result.expect_err("expected the file to be rejected");
+        (e.kind(), e.to_string())
+    }
+
+    #[tokio::test]
+    async fn reads_samples_with_moov_before_and_after_mdat() {
+        let mdat = b"aaabbbbbcc".to_vec();
+        let tables = |start: u64| vec![stsz(0, &[3, 5, 2]), stsc(&[(1, 3)]), stco(&[start])];
+        let expected = vec![b"aaa".to_vec(), b"bbbbb".to_vec(), b"cc".to_vec()];
+
+        for (moov_first, seekable) in [(true, true), (true, false), (false, true)] {
+            let data = file(moov_first, tables, &mdat);
+            assert_eq!(samples(data, seekable).await.unwrap(), expected, "moov first: {}, seekable: {}", moov_first, seekable);
+        }
+
+        let (kind, message) = error(samples(file(false, tables, &mdat), false).await);
+        assert_eq!(kind, io::ErrorKind::InvalidData);
+        assert!(message.contains("unseekable"), "{}", message);
+    }
+
+    #[tokio::test]
+    async fn lays_out_fixed_sizes_across_chunk_runs() {
+        // Three chunks: two samples each in chunks 1-2, one in chunk 3, with padding between.
+        let mdat = b"AAAABBBB--CCCCDDDD----EEEE".to_vec();
+        let tables = |start: u64| vec![stsz(4, &[4; 5]), stsc(&[(1, 2), (3, 1)]), stco(&[start, start + 10, start + 22])];
+        let expected: Vec<Vec<u8>> = ["AAAA", "BBBB", "CCCC", "DDDD", "EEEE"].iter().map(|s| s.as_bytes().to_vec()).collect();
+
+        for moov_first in [true, false] {
+            assert_eq!(samples(file(moov_first, tables, &mdat), true).await.unwrap(), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn reads_64_bit_chunk_offsets() {
+        let mdat = b"xxoneyytwo".to_vec();
+        let tables = |start: u64| vec![stsz(0, &[3, 3]), stsc(&[(1, 1)]), co64(&[start + 2, start + 7])];
+        assert_eq!(samples(file(true, tables, &mdat), true).await.unwrap(), vec![b"one".to_vec(), b"two".to_vec()]);
+    }
+
+    #[tokio::test]
+    async fn rejects_chunk_offsets_that_overflow() {
+        let tables = |_| vec![stsz(0, &[3, 3]), stsc(&[(1, 2)]), co64(&[u64::MAX - 1])];
+        let (kind, message) = error(samples(file(true, tables, b"aaabbb"), true).await);
+        assert_eq!((kind, message.as_str()), (io::ErrorKind::InvalidData, "Sample offset overflows"));
+    }
+
+    /// A moov with an empty sample table and a trex giving `default_size`.
+    fn fragmented_moov(default_size: u32) -> Vec<u8> {
+        let trex = full_box(b"trex", 0, &[&TRACK_ID.to_be_bytes(), &u32s(&[1, 0, default_size, 0])]);
+        moov(&[stsz(0, &[]), stsc(&[]), stco(&[])], &[mp4_box(b"mvex", &trex)])
+    }
+
+    /// A fragment whose traf has the given tfhd fields and trun, followed by its mdat.
+    fn fragment(tfhd: (u32, &[u8]), trun: impl Fn(u32) -> Vec<u8>, mdat: &[u8]) -> Vec<u8> {
+        let build = |data_offset: u32| {
+            let tfhd = full_box(b"tfhd", tfhd.0, &[&TRACK_ID.to_be_bytes(), tfhd.1]);
+            let other = mp4_box(b"traf", &full_box(b"tfhd", 0, &[&9u32.to_be_bytes()]));
+            let traf = mp4_box(b"traf", &[tfhd, trun(data_offset)].concat());
+            mp4_box(b"moof", &[full_box(b"mfhd", 0, &[&1u32.to_be_bytes()]), other, traf].concat())
+        };
+        let moof_len = build(0).len();
+        [build(moof_len as u32 + 8), mp4_box(b"mdat", mdat)].concat()
+    }
+
+    #[tokio::test]
+    async fn reads_fragments() {
+        let mut data = [ftyp(), fragmented_moov(2)].concat();
+
+        // Offsets relative to the moof, with per-sample sizes.
+        data.extend(fragment((0, &[]), |offset| full_box(b"trun", 0x201, &[&2u32.to_be_bytes(), &offset.to_be_bytes(), &u32s(&[1, 3])]), b"abbb"));
+
+        // An explicit base offset and a default size from the tfhd.
+        let trun = |_| full_box(b"trun", 0, &[&2u32.to_be_bytes()]);
+        let moof_len = fragment((0x11, &[0; 12]), trun, b"").len() - 8;
+        let tfhd = [&(data.len() as u64 + moof_len as u64 + 8).to_be_bytes()[..], &4u32.to_be_bytes()].concat();
+        data.extend(fragment((0x11, &tfhd), trun, b"ccccdddd"));
+
+        // The trex default size, with sample flags and durations to skip over.
+        data.extend(fragment((0, &[]), |offset| full_box(b"trun", 0x505, &[&3u32.to_be_bytes(), &offset.to_be_bytes(), &[0; 4], &[0; 24]]), b"eeffgg"));
+
+        let expected: Vec<Vec<u8>> = ["a", "bbb", "cccc", "dddd", "ee", "ff", "gg"].iter().map(|s| s.as_bytes().to_vec()).collect();
+        assert_eq!(samples(data.clone(), false).await.unwrap(), expected);
+        assert_eq!(samples(data, true).await.unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_boxes() {
+        let moov = moov(&[stsz(0, &[1]), stsc(&[(1, 1)]), stco(&[0])], &[]);
+        let oversized = [&((MAX_BOX_SIZE + 9) as u32).to_be_bytes()[..], b"moov"].concat();
+        let huge = [&1u32.to_be_bytes()[..], b"free", &u64::MAX.to_be_bytes()].concat();
+
+        let cases: Vec<(&str, Vec<u8>, io::ErrorKind, &str)> = vec![
+            ("truncated moov", [ftyp(), moov[..moov.len() - 10].to_vec()].concat(), io::ErrorKind::UnexpectedEof, "early eof"),
+            ("oversized moov", [ftyp(), oversized].concat(), io::ErrorKind::InvalidData, "Metadata box is too large"),
+            ("size-0 box before moov", [ftyp(), [&0u32.to_be_bytes()[..], b"free"].concat(), moov.clone()].concat(), io::ErrorKind::InvalidData, "Unbounded box before moov"),
+            ("box smaller than its header", [ftyp(), [&4u32.to_be_bytes()[..], b"free"].concat(), moov.clone()].concat(), io::ErrorKind::InvalidData, "Invalid box size"),
+            ("box past the end of u64", [ftyp(), huge, moov.clone()].concat(), io::ErrorKind::InvalidData, "Invalid box size"),
+            ("no moov", [ftyp(), mp4_box(b"free", b"")].concat(), io::ErrorKind::InvalidData, "No moov box found"),
+        ];
+
+        for (name, data, kind, message) in cases {
+            let e = Mp4Demuxer::open(reader(data, true)).await.err().unwrap_or_else(|| panic!("{} was accepted", name));
+            assert_eq!((e.kind(), e.to_string().as_str()), (kind, message), "{}", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_sample_counts_larger_than_their_box() {
+        let count = |count: u32| vec![full_box(b"stsz", 0, &[&0u32.to_be_bytes(), &count.to_be_bytes(), &u32s(&[1, 1])]), stsc(&[(1, 1)]), stco(&[0])];
+        let (kind, message) = error(samples(file(true, |_| count(u32::MAX), b"ab"), true).await);
+        assert_eq!((kind, message.as_str()), (io::ErrorKind::InvalidData, "Sample count exceeds what the box can hold"));
+        assert!(samples(file(true, |_| count(2), b"ab"), true).await.is_ok());
+
+        let mut data = [ftyp(), fragmented_moov(1)].concat();
+        data.extend(fragment((0, &[]), |offset| full_box(b"trun", 0x201, &[&u32::MAX.to_be_bytes(), &offset.to_be_bytes(), &u32s(&[1])]), b"a"));
+        let (kind, message) = error(samples(data, false).await);
+        assert_eq!((kind, message.as_str()), (io::ErrorKind::InvalidData, "Sample count exceeds what the box can hold"));
+    }
+
+    #[test]
+    fn limits_samples_per_track() {
+        let max = MAX_TRACK_SAMPLES as u32;
+        let cases = [
+            ((3, 4, 12, 0), Some(3)),
+            ((4, 4, 12, 0), None),
+            ((u32::MAX, 8, usize::MAX, 0), None),
+            ((max, 0, 0, 0), Some(MAX_TRACK_SAMPLES)),
+            ((max + 1, 0, 0, 0), None),
+            ((1, 0, 0, MAX_TRACK_SAMPLES - 1), Some(1)),
+            ((2, 0, 0, MAX_TRACK_SAMPLES - 1), None),
+        ];
+
+        for ((count, entry_len, remaining, existing), expected) in cases {
+            assert_eq!(sample_count(count, entry_len, remaining, existing).ok(), expected, "{} samples", count);
+        }
+    }
+}
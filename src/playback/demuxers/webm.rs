@@ -43,8 +43,8 @@ impl WebmOpusDemuxer {
             (first_byte & ((1 << (8 - width)) - 1)) as u64
         };
 
-        for i in 1..width {
-            val = (val << 8) | (buf[i] as u64);
+        for &byte in &buf[1..width] {
+            val = (val << 8) | (byte as u64);
         }
 
         Some((val, width))
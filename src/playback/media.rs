@@ -0,0 +1,121 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, ReadBuf};
+use tokio::sync::mpsc;
+use crate::playback::codecs::{AudioCodec, AudioContainer};
+
+pub trait SeekableRead: AsyncRead + AsyncSeek + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncSeek + Unpin + Send> SeekableRead for T {}
+
+pub enum MediaReader {
+    Seekable(Box<dyn SeekableRead>),
+    Sequential(Box<dyn AsyncRead + Unpin + Send>),
+}
+
+impl MediaReader {
+    pub fn is_seekable(&self) -> bool {
+        matches!(self, MediaReader::Seekable(_))
+    }
+
+    pub async fn skip(&mut self, len: u64) -> io::Result<()> {
+        if let MediaReader::Seekable(reader) = self {
+            let len = i64::try_from(len).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "skip is too long"))?;
+            reader.seek(io::SeekFrom::Current(len)).await?;
+            return Ok(());
+        }
+
+        let skipped = tokio::io::copy(&mut self.take(len), &mut tokio::io::sink()).await?;
+        if skipped < len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+        }
+        Ok(())
+    }
+
+    pub async fn seek_to(&mut self, position: u64) -> io::Result<()> {
+        match self {
+            MediaReader::Seekable(reader) => reader.seek(io::SeekFrom::Start(position)).await.map(|_| ()),
+            MediaReader::Sequential(_) => Err(io::Error::new(io::ErrorKind::Unsupported, "stream is not seekable")),
+        }
+    }
+}
+
+impl AsyncRead for MediaReader {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            MediaReader::Seekable(reader) => Pin::new(reader).poll_read(cx, buf),
+            MediaReader::Sequential(reader) => Pin::new(reader).poll_read(cx, buf),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MediaInfo {
+    pub container: AudioContainer,
+    pub codec: AudioCodec,
+    pub sample_rate: Option<u32>,
+    pub duration: Option<u64>,
+    pub seekable: bool,
+}
+
+impl MediaInfo {
+    pub fn new(container: AudioContainer, codec: AudioCodec) -> Self {
+        Self {
+            container,
+            codec,
+            sample_rate: None,
+            duration: None,
+            seekable: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StreamMetadata {
+    pub title: String,
+    pub url: Option<String>,
+}
+
+pub struct MediaStream {
+    pub reader: MediaReader,
+    pub info: MediaInfo,
+    pub metadata: Option<mpsc::UnboundedReceiver<StreamMetadata>>,
+    pub continuation: Option<mpsc::Receiver<MediaStream>>,
+}
+
+impl MediaStream {
+    pub fn seekable<R: SeekableRead + 'static>(reader: R, mut info: MediaInfo) -> Self {
+        info.seekable = true;
+        Self {
+            reader: MediaReader::Seekable(Box::new(reader)),
+            info,
+            metadata: None,
+            continuation: None,
+        }
+    }
+
+    pub fn sequential<R: AsyncRead + Unpin + Send + 'static>(reader: R, mut info: MediaInfo) -> Self {
+        info.seekable = false;
+        Self {
+            reader: MediaReader::Sequential(Box::new(reader)),
+            info,
+            metadata: None,
+            continuation: None,
+        }
+    }
+
+    pub fn with_metadata(mut self, metadata: mpsc::UnboundedReceiver<StreamMetadata>) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    pub fn with_continuation(mut self, continuation: mpsc::Receiver<MediaStream>) -> Self {
+        self.continuation = Some(continuation);
+        self
+    }
+
+    pub fn is_opus_passthrough(&self) -> bool {
+        self.info.container == AudioContainer::Webm && self.info.codec == AudioCodec::Opus
+    }
+}
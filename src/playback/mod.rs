@@ -1,5 +1,11 @@
 pub mod codecs;
+pub mod control;
 pub mod voice;
 pub mod decoder;
 pub mod demuxers;
-pub mod processor;
\ No newline at end of file
+pub mod processor;
+pub mod opus;
+pub mod readahead;
+pub mod resample;
+pub mod media;
+pub mod workers;
\ No newline at end of file
@@ -0,0 +1,79 @@
+use audiopus::coder::Encoder as OpusEncoder;
+use audiopus::Bitrate;
+use serde::Serialize;
+use crate::config::OpusConfig;
+
+/// `OPUS_SET_DTX_REQUEST` from opus_defines.h; audiopus has no setter for it.
+const OPUS_SET_DTX_REQUEST: i32 = 4016;
+const MIN_BITRATE: u32 = 6_000;
+const MAX_BITRATE: u32 = 510_000;
+const MAX_COMPLEXITY: u8 = 10;
+const MAX_PACKET_LOSS: u8 = 100;
+
+/// Parameters for the encoders that produce the frames sent to Discord.
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct OpusSettings {
+    /// Bits per second. `None` follows the voice channel bitrate when the client reported one,
+    /// and lets the encoder pick otherwise.
+    pub bitrate: Option<u32>,
+    pub complexity: u8,
+    pub vbr: bool,
+    pub fec: bool,
+    /// Expected packet loss in percent, used to size the forward error correction data.
+    pub packet_loss: u8,
+    pub dtx: bool,
+}
+
+impl Default for OpusSettings {
+    fn default() -> Self {
+        Self {
+            bitrate: None,
+            complexity: MAX_COMPLEXITY,
+            vbr: true,
+            fec: false,
+            packet_loss: 0,
+            dtx: false,
+        }
+    }
+}
+
+impl OpusSettings {
+    pub fn from_config(config: OpusConfig) -> Self {
+        Self::default().merge(&config)
+    }
+
+    /// Returns these settings with every field set in `overrides` replaced.
+    pub fn merge(self, overrides: &OpusConfig) -> Self {
+        Self {
+            bitrate: overrides.bitrate.map(|b| b.clamp(MIN_BITRATE, MAX_BITRATE)).or(self.bitrate),
+            complexity: overrides.complexity.map(|c| c.min(MAX_COMPLEXITY)).unwrap_or(self.complexity),
+            vbr: overrides.vbr.unwrap_or(self.vbr),
+            fec: overrides.fec.unwrap_or(self.fec),
+            packet_loss: overrides.packet_loss.map(|p| p.min(MAX_PACKET_LOSS)).unwrap_or(self.packet_loss),
+            dtx: overrides.dtx.unwrap_or(self.dtx),
+        }
+    }
+
+    /// Fills in an automatic bitrate from the voice channel's bitrate, so the encoder doesn't
+    /// spend bits the channel won't carry.
+    pub fn for_channel(self, channel_bitrate: Option<u32>) -> Self {
+        Self {
+            bitrate: self.bitrate.or(channel_bitrate.map(|b| b.clamp(MIN_BITRATE, MAX_BITRATE))),
+            ..self
+        }
+    }
+
+    pub fn apply(&self, encoder: &mut OpusEncoder) -> audiopus::Result<()> {
+        let bitrate = match self.bitrate {
+            Some(bps) => Bitrate::BitsPerSecond(bps as i32),
+            None => Bitrate::Auto,
+        };
+        encoder.set_bitrate(bitrate)?;
+        encoder.set_complexity(self.complexity)?;
+        encoder.set_vbr(self.vbr)?;
+        encoder.set_inband_fec(self.fec)?;
+        encoder.set_packet_loss_perc(self.packet_loss)?;
+        encoder.set_encoder_ctl_request(OPUS_SET_DTX_REQUEST, self.dtx as i32)
+    }
+}
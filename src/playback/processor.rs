@@ -1,120 +1,431 @@
-use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::runtime::Handle;
+use tokio::sync::{mpsc, oneshot};
 use tokio_util::codec::FramedRead;
 use futures_util::StreamExt;
+use crate::playback::codecs::{AudioCodec, AudioContainer};
+use crate::playback::demuxers::mp4::Mp4Demuxer;
 use crate::playback::demuxers::webm::WebmOpusDemuxer;
-use crate::playback::decoder::symphonia::AudioDecoder;
-use audiopus::{coder::Encoder as OpusEncoder, Application, SampleRate, Channels};
-use symphonia::core::audio::Signal;
-use std::io::Cursor;
+use crate::playback::decoder::symphonia::{AudioDecoder, PacketDecoder};
+use crate::playback::media::{MediaReader, MediaStream};
+use crate::playback::readahead::{ReadAhead, ReadAheadReader};
+use crate::playback::resample::{StereoResampler, OUTPUT_RATE};
+use crate::utils::{log, Level};
+use crate::playback::control::PlaybackControl;
+use crate::playback::voice::stream::FRAME_DURATION;
+use audiopus::{coder::Decoder as OpusDecoder, coder::Encoder as OpusEncoder, Application, SampleRate, Channels};
+use symphonia::core::audio::{AudioBufferRef, SampleBuffer};
+use symphonia::core::probe::Hint;
+use std::io::Read;
+use std::sync::Arc;
 
-pub enum AudioPipeline<R: AsyncRead + Unpin + Send> {
-    WebmOpus(FramedRead<R, WebmOpusDemuxer>),
-    Pcm(PcmToOpusStream),
+const OPUS_FRAME_SAMPLES: usize = 1920;
+const OPUS_MAX_PACKET_SAMPLES: usize = 5760 * 2;
+/// Packets a decoder thread may encode ahead of the audio worker.
+const DECODER_THREAD_PACKETS: usize = 4;
+
+pub enum AudioPipeline {
+    WebmOpus(FramedRead<MediaReader, WebmOpusDemuxer>),
+    Mp4Opus(Mp4Demuxer),
+    Mp4Pcm(Mp4ToOpusStream),
+    Pcm(ReadAheadPcm),
+    Streaming(DecoderThread),
 }
 
-pub struct PcmToOpusStream {
-    decoder: AudioDecoder,
+pub struct PcmEncoder {
     encoder: OpusEncoder,
     pcm_buffer: Vec<f32>,
+    resampler: Option<StereoResampler>,
+    control: Arc<PlaybackControl>,
+    position: u64,
+    opus_version: u64,
 }
 
-impl PcmToOpusStream {
-    pub fn new(data: Vec<u8>, mime: Option<&str>) -> Option<Self> {
-        let cursor = Cursor::new(data);
-        let decoder = AudioDecoder::new(cursor, mime).ok()?;
+/// Applies the player's encoder settings when they changed since the encoder last saw them.
+fn refresh_settings(encoder: &mut OpusEncoder, control: &PlaybackControl, seen: &mut u64) {
+    if let Some(settings) = control.opus_changed(seen)
+        && let Err(e) = settings.apply(encoder) {
+        log(Level::Warn, "AudioProcessor", format!("Failed to apply Opus settings {:?}: {:?}", settings, e));
+    }
+}
+
+impl PcmEncoder {
+    pub fn new(control: Arc<PlaybackControl>) -> Option<Self> {
         let encoder = OpusEncoder::new(SampleRate::Hz48000, Channels::Stereo, Application::Audio).ok()?;
-        
         Some(Self {
-            decoder,
             encoder,
             pcm_buffer: Vec::new(),
+            resampler: None,
+            position: control.position(),
+            control,
+            opus_version: 0,
+        })
+    }
+
+    /// Queues decoded audio as 48 kHz stereo, resampling sources at other rates.
+    pub fn push(&mut self, audio_buf: AudioBufferRef<'_>) -> Result<(), std::io::Error> {
+        let spec = *audio_buf.spec();
+        let channels = spec.channels.count();
+        if channels == 0 || audio_buf.frames() == 0 {
+            return Ok(());
+        }
+
+        if spec.rate == OUTPUT_RATE {
+            self.resampler = None;
+        } else if self.resampler.as_ref().is_none_or(|r| r.rate() != spec.rate) {
+            log(Level::Debug, "AudioProcessor", format!("Resampling from {} Hz", spec.rate));
+            self.resampler = Some(StereoResampler::new(spec.rate)?);
+        }
+
+        let mut samples = SampleBuffer::<f32>::new(audio_buf.capacity() as u64, spec);
+        samples.copy_interleaved_ref(audio_buf);
+
+        for frame in samples.samples().chunks_exact(channels) {
+            let (left, right) = (frame[0], frame[1 % channels]);
+            match &mut self.resampler {
+                Some(resampler) => resampler.push(left, right, &mut self.pcm_buffer)?,
+                None => self.pcm_buffer.extend([left, right]),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn next_frame(&mut self) -> Option<Result<Vec<u8>, std::io::Error>> {
+        if self.pcm_buffer.len() < OPUS_FRAME_SAMPLES {
+            return None;
+        }
+
+        let position = self.position;
+        self.position += FRAME_DURATION;
+
+        // Frames before a pending seek target are dropped by the audio loop, so skip encoding them.
+        if position < self.control.seek_target() {
+            self.pcm_buffer.drain(0..OPUS_FRAME_SAMPLES);
+            return Some(Ok(Vec::new()));
+        }
+
+        refresh_settings(&mut self.encoder, &self.control, &mut self.opus_version);
+        let mut frame: Vec<f32> = self.pcm_buffer.drain(0..OPUS_FRAME_SAMPLES).collect();
+        self.control.filter(&mut frame);
+        let gain = self.control.gain();
+        frame.iter_mut().for_each(|s| *s *= gain);
+        let mut output = vec![0u8; 4000];
+        match self.encoder.encode_float(&frame, &mut output) {
+            Ok(len) => {
+                output.truncate(len);
+                Some(Ok(output))
+            },
+            Err(e) => Some(Err(std::io::Error::other(format!("Opus error: {:?}", e)))),
+        }
+    }
+}
+
+/// Re-encodes passthrough Opus packets when the gain is not unity or plugin filters are set. The decoder sees every
+/// packet once created so its state stays continuous when the volume changes mid-track.
+struct OpusVolume {
+    decoder: OpusDecoder,
+    encoder: OpusEncoder,
+    pcm: Vec<f32>,
+    opus_version: u64,
+}
+
+impl OpusVolume {
+    fn new() -> Option<Self> {
+        Some(Self {
+            decoder: OpusDecoder::new(SampleRate::Hz48000, Channels::Stereo).ok()?,
+            encoder: OpusEncoder::new(SampleRate::Hz48000, Channels::Stereo, Application::Audio).ok()?,
+            pcm: vec![0.0; OPUS_MAX_PACKET_SAMPLES],
+            opus_version: 0,
+        })
+    }
+
+    fn apply(&mut self, packet: Vec<u8>, gain: f32, control: &PlaybackControl) -> Result<Vec<u8>, std::io::Error> {
+        let samples = self.decoder.decode_float(Some(&packet[..]), &mut self.pcm[..], false)
+            .map_err(|e| std::io::Error::other(format!("Opus error: {:?}", e)))?;
+        if (gain - 1.0).abs() < f32::EPSILON && !control.filtered() {
+            return Ok(packet);
+        }
+
+        refresh_settings(&mut self.encoder, control, &mut self.opus_version);
+        let frame = &mut self.pcm[..samples * 2];
+        control.filter(frame);
+        frame.iter_mut().for_each(|s| *s *= gain);
+
+        let mut output = vec![0u8; 4000];
+        let len = self.encoder.encode_float(frame, &mut output)
+            .map_err(|e| std::io::Error::other(format!("Opus error: {:?}", e)))?;
+        output.truncate(len);
+        Ok(output)
+    }
+}
+
+pub struct PcmToOpusStream {
+    decoder: AudioDecoder,
+    encoder: PcmEncoder,
+}
+
+impl PcmToOpusStream {
+    pub fn from_reader<R: Read + Send + Sync + 'static>(reader: R, hint: Hint, control: Arc<PlaybackControl>) -> Option<Self> {
+        let decoder = AudioDecoder::new(reader, hint).ok()?;
+        let encoder = PcmEncoder::new(control)?;
+
+        Some(Self {
+            decoder,
+            encoder,
         })
     }
 
     pub fn next_packet(&mut self) -> Option<Result<Vec<u8>, std::io::Error>> {
         loop {
-            match self.decoder.next_packet() {
-                Ok(audio_buf) => {
-                    use symphonia::core::audio::AudioBufferRef;
-                    
-                    let mut samples = Vec::new();
-                    match audio_buf {
-                        AudioBufferRef::F32(buf) => {
-                            for i in 0..buf.frames() {
-                                samples.push(buf.chan(0)[i]);
-                                samples.push(buf.chan(1 % buf.spec().channels.count())[i]);
-                            }
-                        },
-                        AudioBufferRef::S16(buf) => {
-                            for i in 0..buf.frames() {
-                                samples.push(buf.chan(0)[i] as f32 / 32768.0);
-                                samples.push(buf.chan(1 % buf.spec().channels.count())[i] as f32 / 32768.0);
-                            }
-                        },
-                        _ => {
-                            continue;
-                        }
+            if let Some(frame) = self.encoder.next_frame() {
+                return Some(frame);
+            }
+            if let Err(e) = self.decode()? {
+                return Some(Err(e));
+            }
+        }
+    }
+
+    /// Decodes one packet into the encoder. Returns `None` at the end of the source.
+    fn decode(&mut self) -> Option<Result<(), std::io::Error>> {
+        match self.decoder.next_packet() {
+            Ok(audio_buf) => Some(self.encoder.push(audio_buf)),
+            Err(symphonia::core::errors::Error::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => None,
+            Err(e) => Some(Err(std::io::Error::other(format!("Symphonia error: {}", e)))),
+        }
+    }
+}
+
+/// Decodes a seekable source (a local file or memory) on the audio worker, waiting for the
+/// read-ahead before each packet so the synchronous decoder rarely has to block on disk reads.
+pub struct ReadAheadPcm {
+    input: ReadAhead,
+    stream: PcmToOpusStream,
+}
+
+impl ReadAheadPcm {
+    pub async fn next_packet(&mut self) -> Option<Result<Vec<u8>, std::io::Error>> {
+        loop {
+            if let Some(frame) = self.stream.encoder.next_frame() {
+                return Some(frame);
+            }
+            self.input.ready().await;
+            if let Err(e) = self.stream.decode()? {
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+/// Decodes a non-seekable source on a thread of its own. Its reads wait on the network, and
+/// blocking the audio worker there would stall every other track it plays.
+pub struct DecoderThread {
+    packets: mpsc::Receiver<Result<Vec<u8>, std::io::Error>>,
+    _input: ReadAhead,
+}
+
+impl DecoderThread {
+    /// Probes the source on the new thread and returns once it is known to be decodable.
+    async fn spawn(input: ReadAhead, reader: ReadAheadReader, hint: Hint, control: Arc<PlaybackControl>) -> Option<Self> {
+        let (opened_tx, opened_rx) = oneshot::channel();
+        let (packets_tx, packets) = mpsc::channel(DECODER_THREAD_PACKETS);
+
+        let spawned = std::thread::Builder::new()
+            .name("stream-decoder".to_string())
+            .spawn(move || {
+                let Some(mut stream) = PcmToOpusStream::from_reader(reader, hint, control) else {
+                    let _ = opened_tx.send(false);
+                    return;
+                };
+                let _ = opened_tx.send(true);
+
+                while let Some(packet) = stream.next_packet() {
+                    let failed = packet.is_err();
+                    if packets_tx.blocking_send(packet).is_err() || failed {
+                        return;
                     }
+                }
+            });
+        if let Err(e) = spawned {
+            log(Level::Error, "AudioProcessor", format!("Failed to spawn decoder thread: {}", e));
+            return None;
+        }
+
+        if !opened_rx.await.unwrap_or(false) {
+            return None;
+        }
+        Some(Self { packets, _input: input })
+    }
+}
+
+pub struct Mp4ToOpusStream {
+    demuxer: Mp4Demuxer,
+    decoder: PacketDecoder,
+    encoder: PcmEncoder,
+}
 
-                    self.pcm_buffer.extend(samples);
-
-                    if self.pcm_buffer.len() >= 1920 {
-                        let frame: Vec<f32> = self.pcm_buffer.drain(0..1920).collect();
-                        let mut output = vec![0u8; 4000];
-                        match self.encoder.encode_float(&frame, &mut output) {
-                            Ok(len) => {
-                                output.truncate(len);
-                                return Some(Ok(output));
-                            },
-                            Err(e) => return Some(Err(std::io::Error::new(std::io::ErrorKind::Other, format!("Opus error: {:?}", e)))),
-                        }
+impl Mp4ToOpusStream {
+    pub fn new(demuxer: Mp4Demuxer, control: Arc<PlaybackControl>) -> Option<Self> {
+        let track = demuxer.track();
+        let decoder = match PacketDecoder::new(track.codec, track.sample_rate, track.channels, &track.config) {
+            Ok(d) => d,
+            Err(e) => {
+                log(Level::Error, "AudioProcessor", format!("Failed to create {:?} decoder: {}", track.codec, e));
+                return None;
+            }
+        };
+
+        Some(Self {
+            demuxer,
+            decoder,
+            encoder: PcmEncoder::new(control)?,
+        })
+    }
+
+    pub async fn next_packet(&mut self) -> Option<Result<Vec<u8>, std::io::Error>> {
+        loop {
+            if let Some(frame) = self.encoder.next_frame() {
+                return Some(frame);
+            }
+
+            let sample = match self.demuxer.next_sample().await? {
+                Ok(s) => s,
+                Err(e) => return Some(Err(e)),
+            };
+
+            match self.decoder.decode(&sample) {
+                Ok(audio_buf) => {
+                    if let Err(e) = self.encoder.push(audio_buf) {
+                        return Some(Err(e));
                     }
                 },
-                Err(symphonia::core::errors::Error::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
-                    return None;
+                Err(symphonia::core::errors::Error::DecodeError(e)) => {
+                    log(Level::Debug, "AudioProcessor", format!("Skipping undecodable MP4 sample: {}", e));
                 },
-                Err(e) => return Some(Err(std::io::Error::new(std::io::ErrorKind::Other, format!("Symphonia error: {}", e)))),
+                Err(e) => return Some(Err(std::io::Error::other(format!("Symphonia error: {}", e)))),
             }
         }
     }
 }
 
-pub struct AudioProcessor<R: AsyncRead + Unpin + Send> {
-    pipeline: AudioPipeline<R>,
+pub struct AudioProcessor {
+    pipeline: AudioPipeline,
+    continuation: Option<mpsc::Receiver<MediaStream>>,
+    control: Arc<PlaybackControl>,
+    volume: Option<OpusVolume>,
+    io: Handle,
 }
 
-impl<R: AsyncRead + Unpin + Send + 'static> AudioProcessor<R> {
-    pub async fn new(mut source: R, format: &str) -> Self {
-        if format == "webm/opus" {
-            let stream = FramedRead::new(source, WebmOpusDemuxer::new());
-            return Self {
-                pipeline: AudioPipeline::WebmOpus(stream),
-            };
+impl AudioProcessor {
+    /// Opens the decoding pipeline for `stream`. Meant to run on an audio worker; sources are
+    /// read on the `io` runtime, and non-seekable ones are decoded on a thread of their own.
+    pub async fn new(mut stream: MediaStream, control: Arc<PlaybackControl>, io: Handle) -> Option<Self> {
+        let continuation = stream.continuation.take();
+        let pipeline = Self::pipeline(stream, control.clone(), &io).await?;
+
+        Some(Self {
+            pipeline,
+            continuation,
+            control,
+            volume: None,
+            io,
+        })
+    }
+
+    async fn pipeline(stream: MediaStream, control: Arc<PlaybackControl>, io: &Handle) -> Option<AudioPipeline> {
+        if stream.is_opus_passthrough() {
+            let framed = FramedRead::new(stream.reader, WebmOpusDemuxer::new());
+            return Some(AudioPipeline::WebmOpus(framed));
         }
 
-        let mut buffer = Vec::new();
-        let _ = source.read_to_end(&mut buffer).await;
-        
-        if let Some(pcm_stream) = PcmToOpusStream::new(buffer, Some(format)) {
-            Self {
-                pipeline: AudioPipeline::Pcm(pcm_stream),
+        if stream.info.container == AudioContainer::Mp4 {
+            let demuxer = match Mp4Demuxer::open(stream.reader).await {
+                Ok(d) => d,
+                Err(e) => {
+                    log(Level::Error, "AudioProcessor", format!("Failed to open MP4 stream: {}", e));
+                    return None;
+                }
+            };
+
+            if demuxer.track().codec == AudioCodec::Opus {
+                return Some(AudioPipeline::Mp4Opus(demuxer));
             }
-        } else {
-            let stream = FramedRead::new(source, WebmOpusDemuxer::new());
-            return Self {
-                pipeline: AudioPipeline::WebmOpus(stream),
+            return Mp4ToOpusStream::new(demuxer, control).map(AudioPipeline::Mp4Pcm);
+        }
+
+        Self::decoded(stream, control, io).await
+    }
+
+    /// Decodes any other source as it is read, holding at most the read-ahead in memory.
+    async fn decoded(stream: MediaStream, control: Arc<PlaybackControl>, io: &Handle) -> Option<AudioPipeline> {
+        let hint = stream.info.container.hint();
+        let seekable = stream.reader.is_seekable();
+        let (input, reader) = ReadAhead::spawn(stream.reader, io);
+
+        if !seekable {
+            let Some(thread) = DecoderThread::spawn(input, reader, hint, control).await else {
+                log(Level::Error, "AudioProcessor", "Failed to probe media stream");
+                return None;
             };
+            return Some(AudioPipeline::Streaming(thread));
         }
+
+        input.ready().await;
+        let Some(pcm_stream) = PcmToOpusStream::from_reader(reader, hint, control) else {
+            log(Level::Error, "AudioProcessor", "Failed to probe media stream");
+            return None;
+        };
+        Some(AudioPipeline::Pcm(ReadAheadPcm { input, stream: pcm_stream }))
     }
 
     pub async fn next_packet(&mut self) -> Option<Result<Vec<u8>, std::io::Error>> {
-        match &mut self.pipeline {
-            AudioPipeline::WebmOpus(stream) => {
-                stream.next().await.map(|res| res.map(|b| b.to_vec()))
-            },
-            AudioPipeline::Pcm(stream) => {
-                stream.next_packet()
+        loop {
+            let packet = match &mut self.pipeline {
+                AudioPipeline::WebmOpus(stream) => {
+                    let packet = stream.next().await.map(|res| res.map(|b| b.to_vec()));
+                    self.passthrough(packet)
+                },
+                AudioPipeline::Mp4Opus(demuxer) => {
+                    let packet = demuxer.next_sample().await.map(|res| res.map(|b| b.to_vec()));
+                    self.passthrough(packet)
+                },
+                AudioPipeline::Mp4Pcm(stream) => {
+                    stream.next_packet().await
+                },
+                AudioPipeline::Pcm(stream) => {
+                    stream.next_packet().await
+                },
+                AudioPipeline::Streaming(thread) => {
+                    thread.packets.recv().await
+                },
+            };
+
+            if packet.is_some() {
+                return packet;
+            }
+
+            let next = self.continuation.as_mut()?.recv().await?;
+            if let Some(pipeline) = Self::pipeline(next, self.control.clone(), &self.io).await {
+                self.pipeline = pipeline;
             }
         }
     }
-}
\ No newline at end of file
+
+    /// Applies the gain and plugin filters to passthrough Opus packets, which skip the PCM encoder.
+    fn passthrough(&mut self, packet: Option<Result<Vec<u8>, std::io::Error>>) -> Option<Result<Vec<u8>, std::io::Error>> {
+        let Some(Ok(packet)) = packet else { return packet };
+        if self.control.seeking() {
+            return Some(Ok(packet));
+        }
+
+        let gain = self.control.gain();
+        if self.volume.is_none() && ((gain - 1.0).abs() >= f32::EPSILON || self.control.filtered()) {
+            self.volume = OpusVolume::new();
+        }
+
+        match &mut self.volume {
+            Some(volume) => Some(volume.apply(packet, gain, &self.control)),
+            None => Some(Ok(packet)),
+        }
+    }
+}
+
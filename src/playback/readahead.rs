@@ -0,0 +1,139 @@
+use std::collections::VecDeque;
+use std::io::{self, Read};
+use std::sync::{Arc, Condvar, Mutex};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::runtime::Handle;
+use tokio::sync::Notify;
+
+/// Bytes buffered before each decode, so a decode rarely has to wait for the network.
+const DECODE_AHEAD: usize = 16 * 1024;
+const MAX_BUFFERED: usize = 1024 * 1024;
+const READ_CHUNK: usize = 16 * 1024;
+
+#[derive(Default)]
+struct Buffer {
+    data: VecDeque<u8>,
+    finished: bool,
+    error: Option<io::Error>,
+}
+
+#[derive(Default)]
+struct Shared {
+    buffer: Mutex<Buffer>,
+    readable: Condvar,
+    data: Notify,
+    space: Notify,
+    closed: Notify,
+}
+
+/// Feeds a source to a synchronous decoder. The source is read on the I/O runtime; a decoder on
+/// an audio worker waits asynchronously until enough is buffered and only blocks if a single
+/// decode needs more than that. Dropping it stops reading the source and ends the decoder's reads.
+pub struct ReadAhead {
+    shared: Arc<Shared>,
+}
+
+/// The decoder's end of a [`ReadAhead`]. Dropping it stops reading the source.
+pub struct ReadAheadReader {
+    shared: Arc<Shared>,
+}
+
+impl ReadAhead {
+    pub fn spawn<R: AsyncRead + Unpin + Send + 'static>(mut source: R, io: &Handle) -> (Self, ReadAheadReader) {
+        let shared = Arc::new(Shared::default());
+
+        let pump = shared.clone();
+        io.spawn(async move {
+            let mut chunk = vec![0; READ_CHUNK];
+            loop {
+                loop {
+                    let space = pump.space.notified();
+                    if pump.buffer.lock().unwrap().data.len() < MAX_BUFFERED {
+                        break;
+                    }
+                    tokio::select! {
+                        _ = space => {},
+                        _ = pump.closed.notified() => return,
+                    }
+                }
+
+                let result = tokio::select! {
+                    result = source.read(&mut chunk) => result,
+                    _ = pump.closed.notified() => return,
+                };
+                let finished = {
+                    let mut buffer = pump.buffer.lock().unwrap();
+                    match result {
+                        Ok(0) => buffer.finished = true,
+                        Ok(read) => buffer.data.extend(&chunk[..read]),
+                        Err(e) => {
+                            buffer.error = Some(e);
+                            buffer.finished = true;
+                        },
+                    }
+                    buffer.finished
+                };
+                pump.readable.notify_all();
+                pump.data.notify_waiters();
+                if finished {
+                    return;
+                }
+            }
+        });
+
+        (Self { shared: shared.clone() }, ReadAheadReader { shared })
+    }
+
+    /// Waits until a decode can run without blocking: enough bytes are buffered or the source ended.
+    pub async fn ready(&self) {
+        loop {
+            let data = self.shared.data.notified();
+            {
+                let buffer = self.shared.buffer.lock().unwrap();
+                if buffer.finished || buffer.data.len() >= DECODE_AHEAD {
+                    return;
+                }
+            }
+            data.await;
+        }
+    }
+}
+
+impl Read for ReadAheadReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut buffer = self.shared.buffer.lock().unwrap();
+        loop {
+            if !buffer.data.is_empty() {
+                let read = buffer.data.read(buf)?;
+                drop(buffer);
+                self.shared.space.notify_waiters();
+                return Ok(read);
+            }
+            if let Some(e) = buffer.error.take() {
+                return Err(e);
+            }
+            if buffer.finished {
+                return Ok(0);
+            }
+            buffer = self.shared.readable.wait(buffer).unwrap();
+        }
+    }
+}
+
+impl Drop for ReadAhead {
+    fn drop(&mut self) {
+        {
+            let mut buffer = self.shared.buffer.lock().unwrap();
+            buffer.data.clear();
+            buffer.finished = true;
+        }
+        self.shared.readable.notify_all();
+        self.shared.closed.notify_one();
+    }
+}
+
+impl Drop for ReadAheadReader {
+    fn drop(&mut self) {
+        self.shared.closed.notify_one();
+    }
+}
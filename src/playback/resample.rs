@@ -0,0 +1,54 @@
+use rubato::{FftFixedIn, Resampler};
+
+/// The sample rate Discord expects and the Opus encoders run at.
+pub const OUTPUT_RATE: u32 = 48_000;
+const CHUNK_FRAMES: usize = 1024;
+const SUB_CHUNKS: usize = 2;
+
+/// Converts stereo PCM from a source's sample rate to 48 kHz. Input is gathered into fixed
+/// chunks, so output trails the input by up to a chunk plus the filter delay.
+pub struct StereoResampler {
+    rate: u32,
+    resampler: FftFixedIn<f32>,
+    input: [Vec<f32>; 2],
+    output: Vec<Vec<f32>>,
+}
+
+impl StereoResampler {
+    pub fn new(rate: u32) -> Result<Self, std::io::Error> {
+        let resampler = FftFixedIn::new(rate as usize, OUTPUT_RATE as usize, CHUNK_FRAMES, SUB_CHUNKS, 2)
+            .map_err(|e| std::io::Error::other(format!("Cannot resample from {} Hz: {}", rate, e)))?;
+        let output = resampler.output_buffer_allocate(true);
+
+        Ok(Self {
+            rate,
+            resampler,
+            input: [Vec::with_capacity(CHUNK_FRAMES), Vec::with_capacity(CHUNK_FRAMES)],
+            output,
+        })
+    }
+
+    pub fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    /// Takes one stereo frame and appends interleaved 48 kHz samples to `out` whenever a chunk
+    /// is complete.
+    pub fn push(&mut self, left: f32, right: f32, out: &mut Vec<f32>) -> Result<(), std::io::Error> {
+        self.input[0].push(left);
+        self.input[1].push(right);
+        if self.input[0].len() < self.resampler.input_frames_next() {
+            return Ok(());
+        }
+
+        let (_, written) = self.resampler.process_into_buffer(&self.input, &mut self.output, None)
+            .map_err(|e| std::io::Error::other(format!("Resampler error: {}", e)))?;
+        self.input.iter_mut().for_each(Vec::clear);
+
+        for (l, r) in self.output[0][..written].iter().zip(&self.output[1][..written]) {
+            out.push(*l);
+            out.push(*r);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,139 @@
+use std::time::Duration;
+use tokio::time::{sleep_until, Instant};
+use crate::playback::voice::stream::FRAME_DURATION;
+
+/// Frames sent late but within this many slots of their deadline are sent right away to catch
+/// up. Falling further behind skips the missed slots instead of bursting them out.
+const MAX_CATCH_UP_FRAMES: u64 = 3;
+
+/// Schedules frames on 20ms boundaries measured from a fixed monotonic origin, so slow frames
+/// don't accumulate into drift the way a restarted timer would.
+pub struct FrameClock {
+    origin: Instant,
+    frames: u64,
+}
+
+impl FrameClock {
+    pub fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+            frames: 0,
+        }
+    }
+
+    fn deadline(&self, frame: u64) -> Instant {
+        self.origin + Duration::from_millis(frame * FRAME_DURATION)
+    }
+
+    fn elapsed_frames(&self) -> u64 {
+        (self.origin.elapsed().as_millis() / FRAME_DURATION as u128) as u64
+    }
+
+    /// Waits for the next frame's slot. Returns how many slots were skipped because the caller
+    /// fell too far behind; the RTP timestamp should advance by as many frames.
+    pub async fn tick(&mut self) -> u32 {
+        self.frames += 1;
+        let deadline = self.deadline(self.frames);
+        if deadline > Instant::now() {
+            sleep_until(deadline).await;
+            return 0;
+        }
+
+        let behind = self.elapsed_frames().saturating_sub(self.frames);
+        if behind < MAX_CATCH_UP_FRAMES {
+            return 0;
+        }
+        self.frames += behind;
+        behind as u32
+    }
+
+    /// Skips the slots that passed while the caller wasn't ticking, such as while buffering or
+    /// seeking, and returns how many there were.
+    pub fn resync(&mut self) -> u32 {
+        let missed = self.elapsed_frames().saturating_sub(self.frames);
+        self.frames += missed;
+        missed as u32
+    }
+}
+
+impl Default for FrameClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time::advance;
+
+    fn ms(start: Instant) -> u64 {
+        start.elapsed().as_millis() as u64
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ticks_on_frame_boundaries() {
+        let start = Instant::now();
+        let mut clock = FrameClock::new();
+        for frame in 1..=5 {
+            assert_eq!(clock.tick().await, 0);
+            assert_eq!(ms(start), frame * FRAME_DURATION);
+        }
+
+        // Time spent between ticks comes out of the wait instead of adding to it.
+        advance(Duration::from_millis(15)).await;
+        assert_eq!(clock.tick().await, 0);
+        assert_eq!(ms(start), 6 * FRAME_DURATION);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn catches_up_on_late_frames() {
+        let start = Instant::now();
+        let mut clock = FrameClock::new();
+        advance(Duration::from_millis(50)).await;
+
+        assert_eq!(clock.tick().await, 0);
+        assert_eq!(clock.tick().await, 0);
+        assert_eq!(ms(start), 50, "frames behind their slots are sent right away");
+
+        assert_eq!(clock.tick().await, 0);
+        assert_eq!(ms(start), 3 * FRAME_DURATION);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn skips_slots_after_a_stall() {
+        // The stall, the slots the first tick skips, and when the tick after it returns.
+        let cases = [
+            (79, 0, 79),
+            (80, 3, 100),
+            (200, 9, 220),
+            (10_000, 499, 10_020),
+        ];
+        for (stall, skipped, next) in cases {
+            let start = Instant::now();
+            let mut clock = FrameClock::new();
+            advance(Duration::from_millis(stall)).await;
+
+            assert_eq!(clock.tick().await, skipped, "{} ms", stall);
+            assert_eq!(ms(start), stall, "{} ms", stall);
+            assert_eq!(clock.tick().await, 0);
+            assert_eq!(ms(start), next, "{} ms", stall);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn resyncs_after_buffering() {
+        let start = Instant::now();
+        let mut clock = FrameClock::new();
+        assert_eq!(clock.resync(), 0);
+        clock.tick().await;
+        clock.tick().await;
+
+        advance(Duration::from_millis(510)).await;
+        assert_eq!(clock.resync(), 25);
+        assert_eq!(clock.resync(), 0);
+
+        assert_eq!(clock.tick().await, 0);
+        assert_eq!(ms(start), 28 * FRAME_DURATION);
+    }
+}
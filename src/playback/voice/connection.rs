@@ -9,7 +9,7 @@ use tokio::time::{interval, Duration};
 use tokio_tungstenite::connect_async;
 use tokio_tungstenite::tungstenite::Message;
 
-const OPUS_SILENCE_FRAME: [u8; 3] = [0xF8, 0xFF, 0xFE];
+pub const OPUS_SILENCE_FRAME: [u8; 3] = [0xF8, 0xFF, 0xFE];
 
 pub struct VoiceConnection {
     pub guild_id: String,
@@ -69,14 +69,18 @@ impl VoiceConnection {
             *s = speaking;
         }
 
-        let _ = self.sender.send(Message::Text(payload.to_string().into()));
+        let _ = self.sender.send(Message::Text(payload.to_string()));
     }
 
-    pub async fn send_silence(&self) {
-        let udp_lock = self.udp.lock().await;
-        let crypto_lock = self.crypto.lock().await;
+    /// A copy of the UDP session and cipher, once the voice server has sent both.
+    pub async fn transport(&self) -> Option<(VoiceUdp, VoiceCrypto)> {
+        let udp = self.udp.lock().await.clone()?;
+        let crypto = self.crypto.lock().await.clone()?;
+        Some((udp, crypto))
+    }
 
-        if let (Some(mut udp), Some(crypto)) = (udp_lock.clone(), crypto_lock.clone()) {
+    pub async fn send_silence(&self) {
+        if let Some((mut udp, crypto)) = self.transport().await {
             for _ in 0..5 {
                 udp.send_opus(&OPUS_SILENCE_FRAME, &crypto).await;
                 tokio::time::sleep(Duration::from_millis(20)).await;
@@ -115,11 +119,11 @@ impl VoiceConnection {
                         "op": 3,
                         "d": std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as u64
                     });
-                    let _ = ws_write.send(Message::Text(payload.to_string().into())).await;
+                    let _ = ws_write.send(Message::Text(payload.to_string())).await;
                 }
                 Some(msg) = rx.recv() => {
                     let text = msg.to_text().unwrap_or("").to_string();
-                    let _ = ws_write.send(tokio_tungstenite::tungstenite::Message::Text(text.into())).await;
+                    let _ = ws_write.send(tokio_tungstenite::tungstenite::Message::Text(text)).await;
                 }
                 Some(msg_res) = ws_read.next() => {
                     match msg_res {
@@ -2,4 +2,5 @@ pub mod connection;
 pub mod websocket;
 pub mod udp;
 pub mod crypto;
+pub mod clock;
 pub mod stream;
\ No newline at end of file
@@ -1,53 +1,254 @@
-use std::time::Duration;
-use tokio::time::{interval, MissedTickBehavior};
+use std::future::Future;
+use std::time::{Duration, Instant};
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use rtrb::{Consumer, PushError, RingBuffer};
+use tokio::sync::{watch, Notify};
+use tokio::task::JoinHandle;
 use futures_util::StreamExt;
+use crate::playback::control::PlaybackControl;
+use crate::playback::workers::AudioWorkers;
+use crate::playback::voice::clock::FrameClock;
+use crate::playback::voice::connection::{VoiceConnection, OPUS_SILENCE_FRAME};
 use crate::playback::voice::udp::VoiceUdp;
 use crate::playback::voice::crypto::VoiceCrypto;
 use crate::utils::{log, Level};
 
-const FRAME_DURATION: u64 = 20;
+pub const FRAME_DURATION: u64 = 20;
+const PAUSE_SILENCE_FRAMES: u32 = 5;
+const TRANSPORT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Frames decoded ahead of the clock. Playback (re)starts once this many are ready.
+const PREBUFFER_FRAMES: usize = 10;
+const PREBUFFER_TIMEOUT: Duration = Duration::from_secs(1);
+
+type Frame = Result<Vec<u8>, std::io::Error>;
+
+/// Wakes the side of a frame ring that is waiting. Only used when the ring is empty or full;
+/// frames themselves pass through the lock-free ring.
+#[derive(Default)]
+struct RingSignals {
+    data: Notify,
+    space: Notify,
+}
+
+enum Popped {
+    Frame(Frame),
+    Empty,
+    Finished,
+}
+
+/// Frames produced ahead of the clock by an audio worker. The producer stops when this is dropped.
+pub struct Prefetch {
+    frames: Consumer<Frame>,
+    signals: Arc<RingSignals>,
+    task: JoinHandle<()>,
+}
+
+impl Prefetch {
+    /// Opens the source on the least busy audio worker and starts producing frames there.
+    /// If `open` yields no source, the prefetch finishes without frames.
+    pub fn spawn<F, S>(open: F, workers: &AudioWorkers) -> Self
+    where
+        F: Future<Output = Option<S>> + Send + 'static,
+        S: StreamExt<Item = Frame> + Unpin + Send,
+    {
+        let (mut producer, frames) = RingBuffer::new(PREBUFFER_FRAMES);
+        let signals = Arc::new(RingSignals::default());
+
+        let producer_signals = signals.clone();
+        let task = workers.spawn(async move {
+            let mut source = open.await;
+            while let Some(mut frame) = match &mut source {
+                Some(source) => source.next().await,
+                None => None,
+            } {
+                let failed = frame.is_err();
+                loop {
+                    match producer.push(frame) {
+                        Ok(()) => break,
+                        Err(PushError::Full(rejected)) => {
+                            frame = rejected;
+                            producer_signals.space.notified().await;
+                        },
+                    }
+                }
+                producer_signals.data.notify_one();
+                if failed {
+                    break;
+                }
+            }
+            drop(producer);
+            producer_signals.data.notify_one();
+        });
+
+        Self { frames, signals, task }
+    }
+
+    fn pop(&mut self) -> Popped {
+        // Checked before popping so a frame pushed just before the producer quit isn't lost.
+        let finished = self.frames.is_abandoned();
+        match self.frames.pop() {
+            Ok(frame) => {
+                self.signals.space.notify_one();
+                Popped::Frame(frame)
+            },
+            Err(_) if finished => Popped::Finished,
+            Err(_) => Popped::Empty,
+        }
+    }
+
+    async fn next(&mut self) -> Option<Frame> {
+        loop {
+            match self.pop() {
+                Popped::Frame(frame) => return Some(frame),
+                Popped::Finished => return None,
+                Popped::Empty => self.signals.data.notified().await,
+            }
+        }
+    }
+
+    /// Waits until the buffer is full or the source is done, giving up after a while so a slow
+    /// live source still plays what it has.
+    async fn fill(&mut self) {
+        let started = Instant::now();
+        while self.frames.slots() < PREBUFFER_FRAMES && !self.frames.is_abandoned() {
+            let Some(remaining) = PREBUFFER_TIMEOUT.checked_sub(started.elapsed()) else { break };
+            let _ = tokio::time::timeout(remaining, self.signals.data.notified()).await;
+        }
+    }
+}
+
+impl Drop for Prefetch {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
 
 pub struct AudioStream {
-    udp: Arc<Mutex<VoiceUdp>>,
-    crypto: Arc<VoiceCrypto>,
+    connection: watch::Receiver<Option<Arc<VoiceConnection>>>,
 }
 
 impl AudioStream {
-    pub fn new(udp: Arc<Mutex<VoiceUdp>>, crypto: Arc<VoiceCrypto>) -> Self {
-        Self { udp, crypto }
+    /// Plays on whichever connection `connection` currently holds, so the player can move
+    /// to a new voice server without restarting the track.
+    pub fn new(connection: watch::Receiver<Option<Arc<VoiceConnection>>>) -> Self {
+        Self { connection }
     }
 
-    pub async fn play<S>(&self, mut source: S)
-    where
-        S: StreamExt<Item = Result<Vec<u8>, std::io::Error>> + Unpin + Send + 'static,
-    {
-        let mut ticker = interval(Duration::from_millis(FRAME_DURATION));
-        ticker.set_missed_tick_behavior(MissedTickBehavior::Burst);
+    pub fn connection(&self) -> Option<Arc<VoiceConnection>> {
+        self.connection.borrow().clone()
+    }
+
+    /// Sends frames from `prefetch` on 20ms boundaries, decoding a few frames ahead. While paused
+    /// a few silence frames are sent; while seeking frames are consumed as fast as the source
+    /// allows. While the voice connection is being (re)established no frames are taken, so
+    /// playback resumes where it stopped. Slots without a frame advance the RTP timestamp.
+    /// Fails if no connection becomes ready in time.
+    pub async fn play(&mut self, mut prefetch: Prefetch, control: &PlaybackControl) -> Result<(), std::io::Error> {
+        let mut clock = FrameClock::new();
         let mut count = 0;
+        let mut underruns = 0;
+        let mut silence_sent = 0;
+        let mut buffering = true;
+        let mut transport: Option<(VoiceUdp, VoiceCrypto)> = None;
+        let mut waiting_since = Instant::now();
 
         loop {
-            ticker.tick().await;
+            if self.connection.has_changed().unwrap_or(false) {
+                self.connection.borrow_and_update();
+                if transport.take().is_some() {
+                    log(Level::Info, "AudioStream", format!("Voice server changed, holding playback at {}ms", control.position()));
+                }
+                waiting_since = Instant::now();
+            }
 
-            match source.next().await {
-                Some(Ok(frame)) => {
-                    let mut udp = self.udp.lock().await;
-                    udp.send_opus(&frame, &self.crypto).await;
+            if control.seeking() {
+                match prefetch.next().await {
+                    Some(Ok(_)) => control.advance(),
+                    Some(Err(e)) => {
+                        log(Level::Error, "AudioStream", format!("Error reading frame: {}", e));
+                        return Ok(());
+                    },
+                    None => return Ok(()),
+                }
+                buffering = true;
+                continue;
+            }
+
+            if buffering {
+                prefetch.fill().await;
+                buffering = false;
+                let missed = clock.resync();
+                if let Some((udp, _)) = &mut transport {
+                    udp.skip(missed);
+                }
+            }
+
+            let missed = clock.tick().await;
+            if missed > 0 {
+                log(Level::Debug, "AudioStream", format!("Fell {} frames behind, skipping ahead", missed));
+                if let Some((udp, _)) = &mut transport {
+                    udp.skip(missed);
+                }
+            }
+
+            if transport.is_none() {
+                let ready = match self.connection() {
+                    Some(connection) => connection.transport().await.map(|t| (connection, t)),
+                    None => None,
+                };
+                match ready {
+                    Some((connection, ready)) => {
+                        connection.set_speaking(!control.paused()).await;
+                        transport = Some(ready);
+                    },
+                    None if waiting_since.elapsed() > TRANSPORT_TIMEOUT => {
+                        return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "No voice UDP session was established"));
+                    },
+                    None => continue,
+                }
+            }
+            let Some((udp, crypto)) = &mut transport else { continue };
+
+            if control.paused() {
+                if silence_sent < PAUSE_SILENCE_FRAMES {
+                    udp.send_opus(&OPUS_SILENCE_FRAME, crypto).await;
+                    silence_sent += 1;
+                } else {
+                    udp.skip(1);
+                }
+                continue;
+            }
+            silence_sent = 0;
+
+            match prefetch.pop() {
+                Popped::Frame(Ok(frame)) => {
+                    control.advance();
+                    if frame.is_empty() {
+                        udp.skip(1);
+                        continue;
+                    }
+
+                    udp.send_opus(&frame, crypto).await;
                     count += 1;
                     if count % 500 == 0 {
-                        log(Level::Debug, "AudioStream", format!("Sent {} frames", count));
+                        log(Level::Debug, "AudioStream", format!("Sent {} frames ({} underruns)", count, underruns));
                     }
-                }
-                Some(Err(e)) => {
+                },
+                Popped::Frame(Err(e)) => {
                     log(Level::Error, "AudioStream", format!("Error reading frame: {}", e));
-                    break;
+                    return Ok(());
                 },
-                None => {
+                Popped::Empty => {
+                    // The source couldn't keep up; leave a gap and refill before continuing.
+                    udp.skip(1);
+                    underruns += 1;
+                    buffering = true;
+                },
+                Popped::Finished => {
                     log(Level::Debug, "AudioStream", "Source reached EOF");
-                    break;
+                    return Ok(());
                 },
             }
         }
     }
-}
\ No newline at end of file
+}
@@ -3,6 +3,9 @@ use std::net::SocketAddr;
 use std::sync::Arc;
 use crate::playback::voice::crypto::VoiceCrypto;
 
+/// RTP timestamp increment for one 20ms frame at 48kHz.
+const SAMPLES_PER_FRAME: u32 = 960;
+
 #[derive(Clone)]
 pub struct VoiceUdp {
     pub socket: Arc<UdpSocket>,
@@ -63,7 +66,13 @@ impl VoiceUdp {
         let _ = self.socket.send_to(&packet, self.destination).await;
 
         self.sequence = self.sequence.wrapping_add(1);
-        self.timestamp = self.timestamp.wrapping_add(960);
+        self.timestamp = self.timestamp.wrapping_add(SAMPLES_PER_FRAME);
         self.nonce = self.nonce.wrapping_add(1);
     }
+
+    /// Advances the RTP timestamp over frame slots that were not sent, so the receiver plays the
+    /// next packet after a matching gap instead of squeezing it against the previous one.
+    pub fn skip(&mut self, frames: u32) {
+        self.timestamp = self.timestamp.wrapping_add(SAMPLES_PER_FRAME.wrapping_mul(frames));
+    }
 }
@@ -17,7 +17,7 @@ impl VoiceWebsocket {
                 "token": token,
             }
         });
-        let _ = write.send(Message::Text(identify.to_string().into())).await;
+        let _ = write.send(Message::Text(identify.to_string())).await;
     }
 
     pub async fn select_protocol<S>(write: &mut S, ip: &str, port: u16)
@@ -34,6 +34,6 @@ impl VoiceWebsocket {
                 }
             }
         });
-        let _ = write.send(Message::Text(select.to_string().into())).await;
+        let _ = write.send(Message::Text(select.to_string())).await;
     }
 }
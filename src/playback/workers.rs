@@ -0,0 +1,71 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::runtime::{Builder, Handle};
+use tokio::task::JoinHandle;
+use crate::utils::{log, Level};
+
+struct Worker {
+    handle: Handle,
+    tracks: Arc<AtomicUsize>,
+}
+
+/// Decrements a worker's track count when the track's task finishes or is aborted.
+struct TrackGuard(Arc<AtomicUsize>);
+
+impl Drop for TrackGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Threads that decode and encode audio away from the runtime serving REST and websockets.
+/// Each thread drives its own single-threaded runtime, and every track is placed on the thread
+/// running the fewest tracks.
+pub struct AudioWorkers {
+    workers: Vec<Worker>,
+}
+
+impl AudioWorkers {
+    pub fn new(count: usize) -> Self {
+        let workers = (0..count.max(1))
+            .map(|index| {
+                let runtime = Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .expect("Failed to build audio worker runtime");
+                let handle = runtime.handle().clone();
+
+                std::thread::Builder::new()
+                    .name(format!("audio-worker-{}", index))
+                    .spawn(move || runtime.block_on(std::future::pending::<()>()))
+                    .expect("Failed to spawn audio worker thread");
+
+                Worker {
+                    handle,
+                    tracks: Arc::new(AtomicUsize::new(0)),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        log(Level::Info, "AudioWorkers", format!("Started {} audio worker threads", workers.len()));
+        Self { workers }
+    }
+
+    /// Runs a track's frame producer on the least busy worker.
+    pub fn spawn<F>(&self, future: F) -> JoinHandle<()>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let worker = self.workers.iter()
+            .min_by_key(|w| w.tracks.load(Ordering::Relaxed))
+            .expect("At least one audio worker");
+        worker.tracks.fetch_add(1, Ordering::Relaxed);
+
+        let guard = TrackGuard(worker.tracks.clone());
+        worker.handle.spawn(async move {
+            let _guard = guard;
+            future.await;
+        })
+    }
+}
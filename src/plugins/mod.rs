@@ -0,0 +1,11 @@
+#[cfg(feature = "tone")]
+pub mod tone;
+
+use crate::managers::plugins::PluginFactory;
+
+pub fn available() -> Vec<(&'static str, PluginFactory)> {
+    vec![
+        #[cfg(feature = "tone")]
+        ("tone", tone::TonePlugin::load),
+    ]
+}
@@ -0,0 +1,215 @@
+use std::f64::consts::PI;
+use std::io::Cursor;
+use async_trait::async_trait;
+use bytes::{BufMut, BytesMut};
+use serde::Deserialize;
+use warp::http::Method;
+use crate::managers::plugins::{Plugin, PluginRequest, PluginResponse, PluginRoute};
+use crate::managers::sources::Source;
+use crate::models::load_tracks::{ErrorData, Severity, LoadTracksResponse, LoadType, LoadResultData};
+use crate::playback::codecs::{AudioCodec, AudioContainer};
+use crate::playback::media::{MediaInfo, MediaStream};
+use crate::utils::encoding::{DecodedTrack, DecodedInfo, encode_track};
+
+const SAMPLE_RATE: u32 = 48000;
+const CHANNELS: u16 = 2;
+const WAVEFORMS: [&str; 4] = ["sine", "square", "sawtooth", "triangle"];
+
+#[derive(Deserialize, Clone)]
+struct ToneConfig {
+    default_duration: Option<u64>,
+    max_duration: Option<u64>,
+    volume: Option<f64>,
+}
+
+pub struct TonePlugin {
+    config: ToneConfig,
+}
+
+struct ToneSource {
+    default_duration: u64,
+    max_duration: u64,
+    volume: f64,
+}
+
+struct ToneSpec {
+    waveform: &'static str,
+    frequency: f64,
+    seconds: u64,
+}
+
+impl TonePlugin {
+    pub fn load(config: &toml::Value) -> Result<Box<dyn Plugin>, String> {
+        let config: ToneConfig = config.clone().try_into().map_err(|e| format!("invalid config: {}", e))?;
+        Ok(Box::new(Self { config }))
+    }
+}
+
+#[async_trait]
+impl Plugin for TonePlugin {
+    fn name(&self) -> &'static str {
+        "tone"
+    }
+
+    fn version(&self) -> &'static str {
+        "1.0.0"
+    }
+
+    fn sources(&self) -> Vec<Box<dyn Source>> {
+        vec![Box::new(ToneSource {
+            default_duration: self.config.default_duration.unwrap_or(10),
+            max_duration: self.config.max_duration.unwrap_or(600),
+            volume: self.config.volume.unwrap_or(0.3).clamp(0.0, 1.0),
+        })]
+    }
+
+    fn routes(&self) -> Vec<PluginRoute> {
+        vec![PluginRoute { method: Method::GET, path: "/v4/tone/waveforms" }]
+    }
+
+    async fn handle(&self, _route: &PluginRoute, _request: PluginRequest) -> PluginResponse {
+        PluginResponse::json(200, serde_json::json!(WAVEFORMS))
+    }
+
+    fn track_info(&self, track: &DecodedTrack) -> Option<serde_json::Value> {
+        let spec = ToneSpec::parse(&track.info.identifier, 0)?;
+        Some(serde_json::json!({
+            "waveform": spec.waveform,
+            "frequency": spec.frequency,
+        }))
+    }
+}
+
+impl ToneSpec {
+    fn parse(identifier: &str, default_duration: u64) -> Option<Self> {
+        let mut parts = identifier.strip_prefix("tone:")?.split(':').peekable();
+
+        let waveform = match parts.peek() {
+            Some(p) => match WAVEFORMS.iter().find(|w| w.eq_ignore_ascii_case(p)) {
+                Some(w) => {
+                    parts.next();
+                    *w
+                },
+                None => "sine",
+            },
+            None => return None,
+        };
+
+        let frequency: f64 = parts.next()?.trim().parse().ok()?;
+        if !(20.0..=20000.0).contains(&frequency) {
+            return None;
+        }
+
+        let seconds = match parts.next() {
+            Some(s) => s.trim().parse().ok()?,
+            None => default_duration,
+        };
+
+        Some(Self { waveform, frequency, seconds })
+    }
+
+    fn sample(&self, t: f64) -> f64 {
+        let phase = (t * self.frequency).fract();
+        match self.waveform {
+            "square" => if phase < 0.5 { 1.0 } else { -1.0 },
+            "sawtooth" => 2.0 * phase - 1.0,
+            "triangle" => 1.0 - 4.0 * (phase - 0.5).abs(),
+            _ => (2.0 * PI * phase).sin(),
+        }
+    }
+
+    fn render(&self, volume: f64) -> Vec<u8> {
+        let frames = SAMPLE_RATE as u64 * self.seconds;
+        let data_len = (frames * CHANNELS as u64 * 2) as u32;
+
+        let mut buf = BytesMut::with_capacity(44 + data_len as usize);
+        buf.put_slice(b"RIFF");
+        buf.put_u32_le(36 + data_len);
+        buf.put_slice(b"WAVEfmt ");
+        buf.put_u32_le(16);
+        buf.put_u16_le(1);
+        buf.put_u16_le(CHANNELS);
+        buf.put_u32_le(SAMPLE_RATE);
+        buf.put_u32_le(SAMPLE_RATE * CHANNELS as u32 * 2);
+        buf.put_u16_le(CHANNELS * 2);
+        buf.put_u16_le(16);
+        buf.put_slice(b"data");
+        buf.put_u32_le(data_len);
+
+        for i in 0..frames {
+            let value = (self.sample(i as f64 / SAMPLE_RATE as f64) * volume * i16::MAX as f64) as i16;
+            for _ in 0..CHANNELS {
+                buf.put_i16_le(value);
+            }
+        }
+
+        buf.to_vec()
+    }
+}
+
+impl ToneSource {
+    fn empty() -> LoadTracksResponse {
+        LoadTracksResponse {
+            load_type: LoadType::Empty,
+            data: LoadResultData::Empty(serde_json::json!({})),
+        }
+    }
+}
+
+#[async_trait]
+impl Source for ToneSource {
+    fn name(&self) -> &'static str {
+        "tone"
+    }
+
+    fn patterns(&self) -> Vec<&'static str> {
+        vec![r"^tone:"]
+    }
+
+    async fn search(&self, _query: &str, _search_type: &str) -> LoadTracksResponse {
+        Self::empty()
+    }
+
+    async fn resolve(&self, url: &str) -> LoadTracksResponse {
+        let Some(spec) = ToneSpec::parse(url, self.default_duration) else { return Self::empty() };
+        if spec.seconds == 0 || spec.seconds > self.max_duration {
+            return Self::empty();
+        }
+
+        let info = DecodedInfo {
+            title: format!("{} Hz {} tone", spec.frequency, spec.waveform),
+            author: "tone".to_string(),
+            length: spec.seconds * 1000,
+            identifier: format!("tone:{}:{}:{}", spec.waveform, spec.frequency, spec.seconds),
+            is_stream: false,
+            uri: None,
+            artwork_url: None,
+            isrc: None,
+            source_name: "tone".to_string(),
+            position: 0,
+            source_data: Vec::new(),
+        };
+
+        LoadTracksResponse {
+            load_type: LoadType::Track,
+            data: LoadResultData::Track(Box::new(DecodedTrack {
+                encoded: encode_track(&info),
+                info,
+                plugin_info: serde_json::json!({}),
+                user_data: serde_json::json!({}),
+            })),
+        }
+    }
+
+    async fn load_stream(&self, track: &DecodedInfo) -> Result<MediaStream, ErrorData> {
+        let spec = ToneSpec::parse(&track.identifier, self.default_duration)
+            .filter(|spec| spec.seconds <= self.max_duration)
+            .ok_or_else(|| ErrorData::new("Invalid tone", Severity::Common, format!("{} is not a playable tone", track.identifier)))?;
+
+        let volume = self.volume;
+        let data = tokio::task::spawn_blocking(move || spec.render(volume))
+            .await
+            .map_err(|e| ErrorData::from_error("Failed to generate the tone", Severity::Fault, &e))?;
+        Ok(MediaStream::seekable(Cursor::new(data), MediaInfo::new(AudioContainer::Wav, AudioCodec::Pcm)))
+    }
+}
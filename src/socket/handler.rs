@@ -9,7 +9,7 @@ pub async fn handle_socket(ws: WebSocket, client_name: String, user_id: String,
     let mut resumed = false;
 
     let session_id = {
-        let mut manager = aelira.sessions.lock().unwrap();
+        let manager = &aelira.sessions;
         let existing_session = if let Some(id) = session_id_header {
             manager.resume(&id, tx.clone())
         } else {
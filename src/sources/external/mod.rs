@@ -0,0 +1,302 @@
+pub mod process;
+
+use std::io::Cursor;
+use std::time::Duration;
+use async_trait::async_trait;
+use base64::{Engine as _, engine::general_purpose};
+use serde::Deserialize;
+use crate::config::ExternalSourceConfig;
+use crate::managers::sources::Source;
+use crate::models::load_tracks::{ErrorData, Severity, LoadTracksResponse, LoadType, LoadResultData, PlaylistData, PlaylistInfo};
+use crate::playback::codecs::{AudioCodec, AudioContainer};
+use crate::playback::media::{MediaInfo, MediaStream};
+use crate::sources::hls::HlsSource;
+use crate::sources::http::HttpSource;
+use crate::utils::encoding::{DecodedTrack, DecodedInfo, encode_track};
+use crate::utils::{log, Level};
+use process::{ExternalProcess, RpcError};
+
+const DEFAULT_TIMEOUT_MS: u64 = 10_000;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExternalTrack {
+    title: String,
+    author: String,
+    length: u64,
+    identifier: String,
+    #[serde(default)]
+    is_stream: bool,
+    uri: Option<String>,
+    artwork_url: Option<String>,
+    isrc: Option<String>,
+    plugin_info: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExternalPlaylist {
+    name: String,
+    selected_track: Option<i32>,
+    plugin_info: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize, Default)]
+struct LoadResult {
+    #[serde(default)]
+    tracks: Vec<ExternalTrack>,
+    playlist: Option<ExternalPlaylist>,
+}
+
+#[derive(Deserialize)]
+struct StreamResult {
+    url: Option<String>,
+    data: Option<String>,
+    format: Option<String>,
+}
+
+pub struct ExternalSource {
+    name: &'static str,
+    priority: u32,
+    search_terms: Vec<&'static str>,
+    patterns: Vec<&'static str>,
+    metadata_only: bool,
+    process: ExternalProcess,
+}
+
+impl ExternalSource {
+    pub fn new(config: ExternalSourceConfig) -> Self {
+        let leak = |s: String| -> &'static str { Box::leak(s.into_boxed_str()) };
+        let timeout = Duration::from_millis(config.timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS));
+
+        Self {
+            name: leak(config.name.clone()),
+            priority: config.priority.unwrap_or(10),
+            search_terms: config.search_terms.unwrap_or_default().into_iter().map(leak).collect(),
+            patterns: config.patterns.unwrap_or_default().into_iter().map(leak).collect(),
+            metadata_only: config.metadata_only.unwrap_or(false),
+            process: ExternalProcess::new(config.name, config.command, config.args.unwrap_or_default(), timeout),
+        }
+    }
+
+    fn empty() -> LoadTracksResponse {
+        LoadTracksResponse {
+            load_type: LoadType::Empty,
+            data: LoadResultData::Empty(serde_json::json!({})),
+        }
+    }
+
+    fn failed(&self, error: RpcError) -> ErrorData {
+        let severity = match error {
+            RpcError::Remote(_) => Severity::Common,
+            RpcError::Timeout | RpcError::Unavailable(_) => Severity::Fault,
+        };
+
+        ErrorData::new(
+            format!("The {} source failed to load the request", self.name),
+            severity,
+            error.to_string(),
+        )
+    }
+
+    fn track(&self, track: ExternalTrack) -> DecodedTrack {
+        let info = DecodedInfo {
+            title: track.title,
+            author: track.author,
+            length: track.length,
+            identifier: track.identifier,
+            is_stream: track.is_stream,
+            uri: track.uri,
+            artwork_url: track.artwork_url,
+            isrc: track.isrc,
+            source_name: self.name.to_string(),
+            position: 0,
+            source_data: Vec::new(),
+        };
+
+        DecodedTrack {
+            encoded: encode_track(&info),
+            info,
+            plugin_info: track.plugin_info.unwrap_or_else(|| serde_json::json!({})),
+            user_data: serde_json::json!({}),
+        }
+    }
+
+    async fn load(&self, method: &str, params: serde_json::Value) -> Result<LoadResult, LoadTracksResponse> {
+        let value = self.process.request(method, params).await.map_err(|e| LoadTracksResponse::error(self.failed(e)))?;
+        if value.is_null() {
+            return Ok(LoadResult::default());
+        }
+
+        serde_json::from_value(value)
+            .map_err(|e| LoadTracksResponse::error(self.failed(RpcError::Remote(format!("invalid {} result: {}", method, e)))))
+    }
+}
+
+#[async_trait]
+impl Source for ExternalSource {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn priority(&self) -> u32 {
+        self.priority
+    }
+
+    fn search_terms(&self) -> Vec<&'static str> {
+        self.search_terms.clone()
+    }
+
+    fn patterns(&self) -> Vec<&'static str> {
+        self.patterns.clone()
+    }
+
+    fn can_stream(&self) -> bool {
+        !self.metadata_only
+    }
+
+    async fn search(&self, query: &str, search_type: &str) -> LoadTracksResponse {
+        let result = match self.load("search", serde_json::json!({ "query": query, "type": search_type })).await {
+            Ok(r) => r,
+            Err(res) => return res,
+        };
+
+        if result.tracks.is_empty() {
+            return Self::empty();
+        }
+
+        LoadTracksResponse {
+            load_type: LoadType::Search,
+            data: LoadResultData::Search(result.tracks.into_iter().map(|t| self.track(t)).collect()),
+        }
+    }
+
+    async fn resolve(&self, url: &str) -> LoadTracksResponse {
+        let result = match self.load("resolve", serde_json::json!({ "identifier": url })).await {
+            Ok(r) => r,
+            Err(res) => return res,
+        };
+
+        let mut tracks: Vec<DecodedTrack> = result.tracks.into_iter().map(|t| self.track(t)).collect();
+        match (result.playlist, tracks.len()) {
+            (_, 0) => Self::empty(),
+            (Some(playlist), _) => LoadTracksResponse {
+                load_type: LoadType::Playlist,
+                data: LoadResultData::Playlist(PlaylistData {
+                    info: PlaylistInfo {
+                        name: playlist.name,
+                        selected_track: playlist.selected_track.unwrap_or(-1),
+                    },
+                    plugin_info: playlist.plugin_info.unwrap_or_else(|| serde_json::json!({})),
+                    tracks,
+                }),
+            },
+            (None, 1) => LoadTracksResponse {
+                load_type: LoadType::Track,
+                data: LoadResultData::Track(Box::new(tracks.remove(0))),
+            },
+            (None, _) => LoadTracksResponse {
+                load_type: LoadType::Search,
+                data: LoadResultData::Search(tracks),
+            },
+        }
+    }
+
+    async fn load_stream(&self, track: &DecodedInfo) -> Result<MediaStream, ErrorData> {
+        let value = match self.process.request("stream", serde_json::json!({ "track": track })).await {
+            Ok(v) => v,
+            Err(e) => {
+                log(Level::Error, "ExternalSource", format!("{}: stream for {} failed: {}", self.name, track.identifier, e));
+                return Err(self.failed(e));
+            }
+        };
+
+        if value.is_null() {
+            return Err(ErrorData::new("No stream available", Severity::Common, format!("{} has no audio for this track", self.name)));
+        }
+
+        let result: StreamResult = serde_json::from_value(value).map_err(|e| {
+            log(Level::Error, "ExternalSource", format!("{}: invalid stream result: {}", self.name, e));
+            ErrorData::from_error(format!("The {} source returned an invalid stream", self.name), Severity::Fault, &e)
+        })?;
+
+        if let Some(url) = result.url {
+            let target = DecodedInfo { identifier: url.clone(), ..track.clone() };
+            let is_hls = url.split(['?', '#']).next().is_some_and(|p| p.ends_with(".m3u8"));
+            return if is_hls {
+                HlsSource::new().load_stream(&target).await
+            } else {
+                HttpSource::new().load_stream(&target).await
+            };
+        }
+
+        let invalid = |cause: String| ErrorData::new(format!("The {} source returned an invalid stream", self.name), Severity::Fault, cause);
+        let data = result.data.ok_or_else(|| invalid("The stream result has neither url nor data".to_string()))?;
+        let data = general_purpose::STANDARD.decode(data).map_err(|e| invalid(e.to_string()))?;
+        let format = result.format.unwrap_or_default();
+        let container = match AudioContainer::from_mime(&format) {
+            AudioContainer::Unknown => AudioContainer::from_extension(&format),
+            container => container,
+        };
+
+        let codec = match container {
+            AudioContainer::Webm => AudioCodec::Opus,
+            AudioContainer::Mp3 => AudioCodec::Mp3,
+            AudioContainer::Aac => AudioCodec::Aac,
+            AudioContainer::Flac => AudioCodec::Flac,
+            AudioContainer::Wav => AudioCodec::Pcm,
+            _ => AudioCodec::Unknown,
+        };
+
+        Ok(MediaStream::seekable(Cursor::new(data), MediaInfo::new(container, codec)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fails every search and never answers resolve requests.
+    const SCRIPT: &str = r#"
+while IFS= read -r line; do
+  id=$(printf '%s' "$line" | sed 's/.*"id":\([0-9]*\).*/\1/')
+  case "$line" in
+    *'"method":"search"'*) printf '{"jsonrpc":"2.0","id":%s,"error":{"code":-32000,"message":"rate limited"}}\n' "$id" ;;
+  esac
+done
+"#;
+
+    fn source() -> ExternalSource {
+        ExternalSource::new(ExternalSourceConfig {
+            name: "stand-in".to_string(),
+            command: "sh".to_string(),
+            args: Some(vec!["-c".to_string(), SCRIPT.to_string()]),
+            search_terms: None,
+            patterns: None,
+            priority: None,
+            timeout_ms: Some(300),
+            metadata_only: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn reports_remote_errors_as_common_and_timeouts_as_faults() {
+        let source = source();
+
+        let failed = serde_json::to_value(source.search("query", "search").await).unwrap();
+        assert_eq!(failed, serde_json::json!({
+            "loadType": "error",
+            "data": {
+                "message": "The stand-in source failed to load the request",
+                "severity": "common",
+                "cause": "rate limited",
+                "causeStackTrace": "rate limited",
+            },
+        }));
+
+        let timed_out = serde_json::to_value(source.resolve("stand-in:1").await).unwrap();
+        assert_eq!(timed_out["loadType"], "error");
+        assert_eq!(timed_out["data"]["severity"], "fault");
+        assert_eq!(timed_out["data"]["cause"], "request timed out");
+    }
+}
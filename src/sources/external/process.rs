@@ -0,0 +1,453 @@
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::oneshot;
+use crate::utils::{log, Level};
+
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(30);
+const STABLE_UPTIME: Duration = Duration::from_secs(30);
+const MAX_CONSECUTIVE_TIMEOUTS: u32 = 3;
+/// Longest line read from a child. A longer response is a protocol error; longer stderr
+/// output is logged in pieces.
+const MAX_LINE_BYTES: u64 = 8 * 1024 * 1024;
+
+pub enum RpcError {
+    Remote(String),
+    Timeout,
+    Unavailable(String),
+}
+
+#[derive(Deserialize)]
+struct RpcResponse {
+    id: Option<u64>,
+    result: Option<Value>,
+    error: Option<RemoteError>,
+}
+
+#[derive(Deserialize)]
+struct RemoteError {
+    message: String,
+}
+
+type Pending = Arc<Mutex<HashMap<u64, oneshot::Sender<Result<Value, RpcError>>>>>;
+
+struct ProcessState {
+    stdin: Option<ChildStdin>,
+    /// Requests waiting on the running child. Each child gets its own map, so a child that
+    /// exits late can't fail requests sent to its replacement.
+    pending: Pending,
+    kill: Option<oneshot::Sender<()>>,
+    alive: Arc<AtomicBool>,
+    spawned_at: Option<Instant>,
+    crashes: u32,
+}
+
+pub struct ExternalProcess {
+    name: String,
+    command: String,
+    args: Vec<String>,
+    timeout: Duration,
+    next_id: AtomicU64,
+    timeouts: AtomicU32,
+    state: tokio::sync::Mutex<ProcessState>,
+}
+
+impl std::fmt::Display for RpcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RpcError::Remote(message) => write!(f, "{}", message),
+            RpcError::Timeout => write!(f, "request timed out"),
+            RpcError::Unavailable(reason) => write!(f, "process unavailable: {}", reason),
+        }
+    }
+}
+
+impl ExternalProcess {
+    pub fn new(name: String, command: String, args: Vec<String>, timeout: Duration) -> Self {
+        Self {
+            name,
+            command,
+            args,
+            timeout,
+            next_id: AtomicU64::new(1),
+            timeouts: AtomicU32::new(0),
+            state: tokio::sync::Mutex::new(ProcessState {
+                stdin: None,
+                pending: Arc::new(Mutex::new(HashMap::new())),
+                kill: None,
+                alive: Arc::new(AtomicBool::new(false)),
+                spawned_at: None,
+                crashes: 0,
+            }),
+        }
+    }
+
+    pub async fn request(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = oneshot::channel();
+
+        let pending = {
+            let mut state = self.state.lock().await;
+            if !state.alive.load(Ordering::Acquire) {
+                self.spawn(&mut state)?;
+            }
+
+            let pending = state.pending.clone();
+            pending.lock().unwrap().insert(id, tx);
+            let line = serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "method": method,
+                "params": params,
+            }).to_string() + "\n";
+
+            // The state stays locked while writing, so a child that stops reading must not
+            // hold up every other request and `kill` forever.
+            let stdin = state.stdin.as_mut().expect("alive process has stdin");
+            let written = tokio::time::timeout(self.timeout, async {
+                stdin.write_all(line.as_bytes()).await?;
+                stdin.flush().await
+            }).await;
+
+            match written {
+                Ok(Ok(())) => {},
+                Ok(Err(e)) => {
+                    pending.lock().unwrap().remove(&id);
+                    stop(&mut state);
+                    return Err(RpcError::Unavailable(e.to_string()));
+                },
+                Err(_) => {
+                    pending.lock().unwrap().remove(&id);
+                    log(Level::Warn, "ExternalSource", format!("{} stopped reading requests, killing it", self.name));
+                    stop(&mut state);
+                    self.timeouts.store(0, Ordering::Relaxed);
+                    return Err(RpcError::Timeout);
+                },
+            }
+            pending
+        };
+
+        match tokio::time::timeout(self.timeout, rx).await {
+            Ok(Ok(result)) => {
+                self.timeouts.store(0, Ordering::Relaxed);
+                result
+            },
+            Ok(Err(_)) => Err(RpcError::Unavailable("process exited".to_string())),
+            Err(_) => {
+                pending.lock().unwrap().remove(&id);
+                log(Level::Warn, "ExternalSource", format!("{}: {} timed out after {:?}", self.name, method, self.timeout));
+
+                if self.timeouts.fetch_add(1, Ordering::Relaxed) + 1 >= MAX_CONSECUTIVE_TIMEOUTS {
+                    self.kill().await;
+                }
+                Err(RpcError::Timeout)
+            }
+        }
+    }
+
+    async fn kill(&self) {
+        let mut state = self.state.lock().await;
+        if let Some(kill) = state.kill.take() {
+            log(Level::Warn, "ExternalSource", format!("{} stopped responding, killing it", self.name));
+            let _ = kill.send(());
+        }
+        self.timeouts.store(0, Ordering::Relaxed);
+    }
+
+    fn spawn(&self, state: &mut ProcessState) -> Result<(), RpcError> {
+        if let Some(spawned_at) = state.spawned_at {
+            let crashes = if spawned_at.elapsed() >= STABLE_UPTIME { 0 } else { state.crashes + 1 };
+            let delay = Duration::from_secs(1 << crashes.min(5)).min(MAX_RESTART_DELAY);
+            if spawned_at.elapsed() < delay {
+                return Err(RpcError::Unavailable(format!("restarting in {:?}", delay - spawned_at.elapsed())));
+            }
+            state.crashes = crashes;
+            log(Level::Info, "ExternalSource", format!("Restarting {}", self.name));
+        }
+
+        state.spawned_at = Some(Instant::now());
+        let mut child = Command::new(&self.command)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| {
+                log(Level::Error, "ExternalSource", format!("Failed to start {} ({}): {}", self.name, self.command, e));
+                RpcError::Unavailable(e.to_string())
+            })?;
+
+        let stdin = child.stdin.take();
+        let stdout = child.stdout.take();
+        let (Some(stdin), Some(stdout)) = (stdin, stdout) else {
+            return Err(RpcError::Unavailable("missing stdio pipes".to_string()));
+        };
+
+        if let Some(stderr) = child.stderr.take() {
+            let name = self.name.clone();
+            tokio::spawn(async move {
+                let mut stderr = BufReader::new(stderr);
+                let mut line = Vec::new();
+                loop {
+                    line.clear();
+                    match (&mut stderr).take(MAX_LINE_BYTES).read_until(b'\n', &mut line).await {
+                        Ok(0) | Err(_) => break,
+                        Ok(_) => log(Level::Debug, "ExternalSource", format!("{}: {}", name, String::from_utf8_lossy(&line).trim_end())),
+                    }
+                }
+            });
+        }
+
+        let alive = Arc::new(AtomicBool::new(true));
+        let pending: Pending = Arc::new(Mutex::new(HashMap::new()));
+        let (kill_tx, kill_rx) = oneshot::channel();
+        tokio::spawn(read_responses(self.name.clone(), child, stdout, kill_rx, pending.clone(), alive.clone()));
+
+        state.stdin = Some(stdin);
+        state.pending = pending;
+        state.kill = Some(kill_tx);
+        state.alive = alive;
+        Ok(())
+    }
+}
+
+/// Kills the child and marks it dead, so the next request starts a new one.
+fn stop(state: &mut ProcessState) {
+    if let Some(kill) = state.kill.take() {
+        let _ = kill.send(());
+    }
+    state.stdin = None;
+    state.alive.store(false, Ordering::Release);
+}
+
+async fn read_responses(name: String, mut child: Child, stdout: ChildStdout, mut kill: oneshot::Receiver<()>, pending: Pending, alive: Arc<AtomicBool>) {
+    let mut stdout = BufReader::new(stdout);
+    let mut buf = Vec::new();
+    loop {
+        let line = tokio::select! {
+            line = read_line(&mut stdout, &mut buf) => match line {
+                Ok(Some(line)) => line,
+                Ok(None) => break,
+                Err(e) => {
+                    log(Level::Warn, "ExternalSource", format!("{}: {}, stopping it", name, e));
+                    let _ = child.start_kill();
+                    break;
+                },
+            },
+            _ = &mut kill => {
+                let _ = child.start_kill();
+                break;
+            }
+        };
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response: RpcResponse = match serde_json::from_str(&line) {
+            Ok(r) => r,
+            Err(e) => {
+                log(Level::Warn, "ExternalSource", format!("{}: ignoring malformed response: {}", name, e));
+                continue;
+            }
+        };
+
+        let Some(id) = response.id else { continue };
+        let Some(sender) = pending.lock().unwrap().remove(&id) else { continue };
+        let result = match response.error {
+            Some(error) => Err(RpcError::Remote(error.message)),
+            None => Ok(response.result.unwrap_or(Value::Null)),
+        };
+        let _ = sender.send(result);
+    }
+
+    for (_, sender) in pending.lock().unwrap().drain() {
+        let _ = sender.send(Err(RpcError::Unavailable("process exited".to_string())));
+    }
+    alive.store(false, Ordering::Release);
+
+    let status = child.wait().await.map(|s| s.to_string()).unwrap_or_else(|e| e.to_string());
+    log(Level::Warn, "ExternalSource", format!("{} exited ({}), it will be restarted on the next request", name, status));
+}
+
+/// Reads a line of at most `MAX_LINE_BYTES`, without the line ending. Returns `None` at EOF.
+async fn read_line<R: AsyncBufRead + Unpin>(reader: &mut R, buf: &mut Vec<u8>) -> std::io::Result<Option<String>> {
+    buf.clear();
+    if reader.take(MAX_LINE_BYTES + 1).read_until(b'\n', buf).await? == 0 {
+        return Ok(None);
+    }
+    if buf.len() as u64 > MAX_LINE_BYTES && buf.last() != Some(&b'\n') {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("response line exceeds {} bytes", MAX_LINE_BYTES)));
+    }
+
+    let line = String::from_utf8(std::mem::take(buf))
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Answers `echo` and `fail`, exits on `exit`, stops reading on `stall`, writes a line
+    /// over the limit on `huge` and never answers anything else.
+    const SCRIPT: &str = r#"
+while IFS= read -r line; do
+  id=$(printf '%s' "$line" | sed 's/.*"id":\([0-9]*\).*/\1/')
+  case "$line" in
+    *'"method":"echo"'*) printf '{"jsonrpc":"2.0","id":%s,"result":{"pid":%s}}\n' "$id" "$$" ;;
+    *'"method":"fail"'*) printf '{"jsonrpc":"2.0","id":%s,"error":{"code":-32000,"message":"track not found"}}\n' "$id" ;;
+    *'"method":"exit"'*) exit 0 ;;
+    *'"method":"stall"'*) exec sleep 30 ;;
+    *'"method":"huge"'*) head -c 9000000 /dev/zero | tr '\0' a; echo ;;
+  esac
+done
+"#;
+
+    /// A stand-in resolver script, removed when dropped.
+    struct Script(PathBuf);
+
+    impl Script {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("aelira-external-{}-{}.sh", name, std::process::id()));
+            std::fs::write(&path, SCRIPT).unwrap();
+            Self(path)
+        }
+
+        fn process(&self, timeout: Duration) -> ExternalProcess {
+            ExternalProcess::new("test".to_string(), "sh".to_string(), vec![self.0.to_string_lossy().to_string()], timeout)
+        }
+    }
+
+    impl Drop for Script {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn outcome(result: &Result<Value, RpcError>) -> String {
+        match result {
+            Ok(_) => "ok".to_string(),
+            Err(RpcError::Remote(message)) => format!("remote: {}", message),
+            Err(RpcError::Timeout) => "timeout".to_string(),
+            Err(RpcError::Unavailable(_)) => "unavailable".to_string(),
+        }
+    }
+
+    /// Waits for the reader to notice that the child is gone.
+    async fn wait_until_dead(process: &ExternalProcess) {
+        for _ in 0..100 {
+            if !process.state.lock().await.alive.load(Ordering::Acquire) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("the child was not stopped");
+    }
+
+    #[tokio::test]
+    async fn answers_requests_and_reports_remote_errors() {
+        let script = Script::new("answers");
+        let process = script.process(Duration::from_secs(5));
+
+        let first = process.request("echo", serde_json::json!({})).await;
+        let second = process.request("echo", serde_json::json!({})).await;
+        assert_eq!(outcome(&first), "ok");
+        assert_eq!(first.ok(), second.ok(), "requests share one child");
+        assert_eq!(outcome(&process.request("fail", serde_json::json!({})).await), "remote: track not found");
+    }
+
+    #[tokio::test]
+    async fn kills_the_child_after_consecutive_timeouts() {
+        let script = Script::new("timeouts");
+        let process = script.process(Duration::from_millis(200));
+
+        for attempt in 0..MAX_CONSECUTIVE_TIMEOUTS {
+            assert_eq!(outcome(&process.request("hang", serde_json::json!({})).await), "timeout", "attempt {}", attempt);
+        }
+        wait_until_dead(&process).await;
+        assert_eq!(outcome(&process.request("echo", serde_json::json!({})).await), "unavailable");
+    }
+
+    #[tokio::test]
+    async fn answered_requests_reset_the_timeout_count() {
+        let script = Script::new("reset");
+        let process = script.process(Duration::from_millis(200));
+
+        for _ in 0..MAX_CONSECUTIVE_TIMEOUTS {
+            assert_eq!(outcome(&process.request("hang", serde_json::json!({})).await), "timeout");
+            assert_eq!(outcome(&process.request("echo", serde_json::json!({})).await), "ok");
+        }
+    }
+
+    #[tokio::test]
+    async fn kills_a_child_that_stops_reading() {
+        let script = Script::new("stall");
+        let process = script.process(Duration::from_millis(200));
+
+        assert_eq!(outcome(&process.request("stall", serde_json::json!({})).await), "timeout");
+        let large = serde_json::json!({ "query": "a".repeat(1024 * 1024) });
+        let started = Instant::now();
+        assert_eq!(outcome(&process.request("echo", large).await), "timeout");
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        assert!(!process.state.lock().await.alive.load(Ordering::Acquire));
+        assert_eq!(outcome(&process.request("echo", serde_json::json!({})).await), "unavailable");
+    }
+
+    #[tokio::test]
+    async fn restarts_after_the_child_exits() {
+        let script = Script::new("restart");
+        let process = script.process(Duration::from_secs(5));
+
+        let Ok(first) = process.request("echo", serde_json::json!({})).await else { panic!("first request failed") };
+        assert_eq!(outcome(&process.request("exit", serde_json::json!({})).await), "unavailable");
+        wait_until_dead(&process).await;
+        assert_eq!(outcome(&process.request("echo", serde_json::json!({})).await), "unavailable", "restarts are delayed");
+
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        let Ok(second) = process.request("echo", serde_json::json!({})).await else { panic!("restart failed") };
+        assert_ne!(first, second, "a new child answers");
+    }
+
+    #[tokio::test]
+    async fn stops_children_writing_lines_over_the_limit() {
+        let script = Script::new("huge");
+        let process = script.process(Duration::from_secs(5));
+
+        assert_eq!(outcome(&process.request("huge", serde_json::json!({})).await), "unavailable");
+        wait_until_dead(&process).await;
+    }
+
+    #[tokio::test]
+    async fn reads_lines_up_to_the_limit() {
+        let exact = vec![b'a'; MAX_LINE_BYTES as usize];
+        let cases = [
+            ([b"{}\r\n".as_slice(), b"\n"].concat(), vec![Some("{}".to_string()), Some(String::new()), None]),
+            ([exact.as_slice(), b"\n"].concat(), vec![Some("a".repeat(MAX_LINE_BYTES as usize)), None]),
+            (exact.clone(), vec![Some("a".repeat(MAX_LINE_BYTES as usize)), None]),
+        ];
+
+        for (input, expected) in cases {
+            let mut reader = BufReader::new(&input[..]);
+            let mut buf = Vec::new();
+            let mut lines = Vec::new();
+            for _ in 0..expected.len() {
+                lines.push(read_line(&mut reader, &mut buf).await.unwrap());
+            }
+            assert_eq!(lines, expected);
+        }
+
+        let over = vec![b'a'; MAX_LINE_BYTES as usize + 1];
+        let mut reader = BufReader::new(&over[..]);
+        assert!(read_line(&mut reader, &mut Vec::new()).await.is_err());
+    }
+}
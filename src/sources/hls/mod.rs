@@ -0,0 +1,103 @@
+pub mod playlist;
+pub mod stream;
+pub mod ts;
+
+use async_trait::async_trait;
+use tokio::sync::oneshot;
+use crate::managers::sources::Source;
+use crate::models::load_tracks::{ErrorData, Severity, LoadTracksResponse, LoadType, LoadResultData};
+use crate::playback::media::MediaStream;
+use crate::sources::http::STREAM_LENGTH;
+use crate::utils::encoding::{DecodedTrack, DecodedInfo, encode_track};
+use stream::HlsLoader;
+
+pub struct HlsSource;
+
+impl HlsSource {
+    pub fn new() -> Self {
+        Self
+    }
+
+    fn empty() -> LoadTracksResponse {
+        LoadTracksResponse {
+            load_type: LoadType::Empty,
+            data: LoadResultData::Empty(serde_json::json!({})),
+        }
+    }
+
+    fn unavailable(url: &str) -> ErrorData {
+        ErrorData::new("Failed to load the HLS playlist", Severity::Suspicious, format!("{} could not be fetched or is not a valid playlist", url))
+    }
+
+    fn default_title(url: &str) -> String {
+        url::Url::parse(url).ok()
+            .and_then(|u| u.host_str().map(str::to_string))
+            .unwrap_or_else(|| url.to_string())
+    }
+}
+
+impl Default for HlsSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Source for HlsSource {
+    fn name(&self) -> &'static str {
+        "hls"
+    }
+
+    fn priority(&self) -> u32 {
+        5
+    }
+
+    fn patterns(&self) -> Vec<&'static str> {
+        vec![r"^https?://[^?#]+\.m3u8([?#].*)?$"]
+    }
+
+    async fn search(&self, _query: &str, _search_type: &str) -> LoadTracksResponse {
+        Self::empty()
+    }
+
+    async fn resolve(&self, url: &str) -> LoadTracksResponse {
+        let Some((_, media)) = stream::resolve_media_url(url).await else {
+            return LoadTracksResponse::error(Self::unavailable(url));
+        };
+        if media.segments.is_empty() {
+            return Self::empty();
+        }
+
+        let info = DecodedInfo {
+            title: Self::default_title(url),
+            author: "unknown".to_string(),
+            length: if media.ended { media.duration_ms() } else { STREAM_LENGTH },
+            identifier: url.to_string(),
+            is_stream: !media.ended,
+            uri: Some(url.to_string()),
+            artwork_url: None,
+            isrc: None,
+            source_name: "hls".to_string(),
+            position: 0,
+            source_data: Vec::new(),
+        };
+
+        LoadTracksResponse {
+            load_type: LoadType::Track,
+            data: LoadResultData::Track(Box::new(DecodedTrack {
+                encoded: encode_track(&info),
+                info,
+                plugin_info: serde_json::json!({}),
+                user_data: serde_json::json!({}),
+            })),
+        }
+    }
+
+    async fn load_stream(&self, track: &DecodedInfo) -> Result<MediaStream, ErrorData> {
+        let loader = HlsLoader::open(&track.identifier).await.ok_or_else(|| Self::unavailable(&track.identifier))?;
+        let (tx, rx) = oneshot::channel();
+        tokio::spawn(loader.run(tx));
+        rx.await.map_err(|_| ErrorData::new("The HLS stream ended before any audio was received", Severity::Suspicious, "No playable segments were found"))
+    }
+}
+
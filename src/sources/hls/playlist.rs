@@ -0,0 +1,401 @@
+use std::collections::HashMap;
+
+const VIDEO_CODECS: [&str; 6] = ["avc1", "avc3", "hvc1", "hev1", "vp09", "av01"];
+
+pub struct Variant {
+    pub uri: String,
+    pub bandwidth: u64,
+    pub codecs: Option<String>,
+    pub audio_group: Option<String>,
+}
+
+pub struct Rendition {
+    pub group_id: String,
+    pub uri: String,
+    pub default: bool,
+    pub autoselect: bool,
+}
+
+pub struct MasterPlaylist {
+    pub variants: Vec<Variant>,
+    pub renditions: Vec<Rendition>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ByteRange {
+    pub offset: u64,
+    pub length: u64,
+}
+
+impl ByteRange {
+    /// The offset just past the range, or `None` if it doesn't fit in a `u64`.
+    pub fn end(&self) -> Option<u64> {
+        self.offset.checked_add(self.length)
+    }
+
+    /// The inclusive last byte, as used in a `Range` header. `None` for empty ranges.
+    pub fn last_byte(&self) -> Option<u64> {
+        if self.length == 0 {
+            return None;
+        }
+        self.end().map(|end| end - 1)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InitSection {
+    pub uri: String,
+    pub range: Option<ByteRange>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Segment {
+    pub uri: String,
+    pub sequence: u64,
+    pub duration: f64,
+    pub range: Option<ByteRange>,
+    pub discontinuity: bool,
+    pub init: Option<InitSection>,
+    pub encrypted: bool,
+}
+
+pub struct MediaPlaylist {
+    pub target_duration: f64,
+    pub segments: Vec<Segment>,
+    pub ended: bool,
+}
+
+pub enum HlsPlaylist {
+    Master(MasterPlaylist),
+    Media(MediaPlaylist),
+}
+
+impl Variant {
+    pub fn is_audio_only(&self) -> bool {
+        self.codecs.as_deref().is_some_and(|codecs| {
+            !codecs.split(',').any(|c| VIDEO_CODECS.iter().any(|v| c.trim().starts_with(v)))
+        })
+    }
+}
+
+impl MasterPlaylist {
+    pub fn select_audio(&self) -> Option<&str> {
+        let groups: Vec<&str> = self.variants.iter().filter_map(|v| v.audio_group.as_deref()).collect();
+        let renditions: Vec<&Rendition> = self.renditions.iter()
+            .filter(|r| groups.is_empty() || groups.contains(&r.group_id.as_str()))
+            .collect();
+
+        let rendition = renditions.iter().find(|r| r.default)
+            .or_else(|| renditions.iter().find(|r| r.autoselect))
+            .or_else(|| renditions.first());
+        if let Some(rendition) = rendition {
+            return Some(&rendition.uri);
+        }
+
+        let audio_only = self.variants.iter()
+            .filter(|v| v.is_audio_only())
+            .max_by_key(|v| v.bandwidth);
+
+        audio_only
+            .or_else(|| self.variants.iter().min_by_key(|v| v.bandwidth))
+            .map(|v| v.uri.as_str())
+    }
+}
+
+impl MediaPlaylist {
+    pub fn duration_ms(&self) -> u64 {
+        (self.segments.iter().map(|s| s.duration).sum::<f64>() * 1000.0) as u64
+    }
+}
+
+pub fn parse(base: &str, content: &str) -> Option<HlsPlaylist> {
+    let content = content.trim_start_matches('\u{feff}');
+    if !content.trim_start().starts_with("#EXTM3U") {
+        return None;
+    }
+
+    if content.lines().any(|l| l.trim().starts_with("#EXT-X-STREAM-INF")) {
+        return Some(HlsPlaylist::Master(parse_master(base, content)));
+    }
+    Some(HlsPlaylist::Media(parse_media(base, content)))
+}
+
+fn parse_master(base: &str, content: &str) -> MasterPlaylist {
+    let mut variants = Vec::new();
+    let mut renditions = Vec::new();
+    let mut pending: Option<HashMap<String, String>> = None;
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(value) = line.strip_prefix("#EXT-X-STREAM-INF:") {
+            pending = Some(parse_attributes(value));
+        } else if let Some(value) = line.strip_prefix("#EXT-X-MEDIA:") {
+            let attrs = parse_attributes(value);
+            if attrs.get("TYPE").map(String::as_str) != Some("AUDIO") {
+                continue;
+            }
+            let Some(uri) = attrs.get("URI") else { continue };
+
+            renditions.push(Rendition {
+                group_id: attrs.get("GROUP-ID").cloned().unwrap_or_default(),
+                uri: resolve(base, uri),
+                default: attrs.get("DEFAULT").is_some_and(|v| v == "YES"),
+                autoselect: attrs.get("AUTOSELECT").is_some_and(|v| v == "YES"),
+            });
+        } else if !line.starts_with('#')
+            && let Some(attrs) = pending.take() {
+            variants.push(Variant {
+                uri: resolve(base, line),
+                bandwidth: attrs.get("BANDWIDTH").and_then(|b| b.parse().ok()).unwrap_or(0),
+                codecs: attrs.get("CODECS").cloned(),
+                audio_group: attrs.get("AUDIO").cloned(),
+            });
+        }
+    }
+
+    MasterPlaylist { variants, renditions }
+}
+
+fn parse_media(base: &str, content: &str) -> MediaPlaylist {
+    let mut target_duration = 6.0;
+    let mut sequence = 0;
+    let mut segments = Vec::new();
+    let mut ended = false;
+
+    let mut duration = 0.0;
+    let mut discontinuity = false;
+    let mut range: Option<ByteRange> = None;
+    let mut bad_range = false;
+    let mut next_offset = 0;
+    let mut init: Option<InitSection> = None;
+    let mut encrypted = false;
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(value) = line.strip_prefix("#EXT-X-TARGETDURATION:") {
+            target_duration = value.trim().parse().unwrap_or(target_duration);
+        } else if let Some(value) = line.strip_prefix("#EXT-X-MEDIA-SEQUENCE:") {
+            sequence = value.trim().parse().unwrap_or(0);
+        } else if let Some(value) = line.strip_prefix("#EXTINF:") {
+            duration = value.split(',').next().and_then(|d| d.trim().parse().ok()).unwrap_or(0.0);
+        } else if line == "#EXT-X-DISCONTINUITY" {
+            discontinuity = true;
+        } else if let Some(value) = line.strip_prefix("#EXT-X-BYTERANGE:") {
+            range = parse_byte_range(value, next_offset);
+            bad_range = range.is_none();
+        } else if let Some(value) = line.strip_prefix("#EXT-X-MAP:") {
+            let attrs = parse_attributes(value);
+            init = attrs.get("URI").map(|uri| InitSection {
+                uri: resolve(base, uri),
+                range: attrs.get("BYTERANGE").and_then(|r| parse_byte_range(r, 0)),
+            });
+        } else if let Some(value) = line.strip_prefix("#EXT-X-KEY:") {
+            let attrs = parse_attributes(value);
+            encrypted = attrs.get("METHOD").is_some_and(|m| m != "NONE");
+        } else if line == "#EXT-X-ENDLIST" {
+            ended = true;
+        } else if !line.starts_with('#') && std::mem::take(&mut bad_range) {
+            // Fetching the whole resource instead would play the wrong audio, so drop the segment.
+            sequence += 1;
+            duration = 0.0;
+        } else if !line.starts_with('#') {
+            if let Some(end) = range.as_ref().and_then(ByteRange::end) {
+                next_offset = end;
+            }
+
+            segments.push(Segment {
+                uri: resolve(base, line),
+                sequence,
+                duration,
+                range: range.take(),
+                discontinuity: std::mem::take(&mut discontinuity),
+                init: init.clone(),
+                encrypted,
+            });
+            sequence += 1;
+            duration = 0.0;
+        }
+    }
+
+    MediaPlaylist { target_duration, segments, ended }
+}
+
+fn parse_byte_range(value: &str, next_offset: u64) -> Option<ByteRange> {
+    let (length, offset) = match value.trim().split_once('@') {
+        Some((length, offset)) => (length.parse().ok()?, offset.parse().ok()?),
+        None => (value.trim().parse().ok()?, next_offset),
+    };
+    let range = ByteRange { offset, length };
+    range.last_byte().map(|_| range)
+}
+
+fn parse_attributes(value: &str) -> HashMap<String, String> {
+    let mut attrs = HashMap::new();
+    let mut rest = value.trim();
+
+    while !rest.is_empty() {
+        let Some((key, after)) = rest.split_once('=') else { break };
+
+        let (value, remaining) = match after.strip_prefix('"') {
+            Some(quoted) => match quoted.split_once('"') {
+                Some((v, r)) => (v, r),
+                None => (quoted, ""),
+            },
+            None => after.split_once(',').unwrap_or((after, "")),
+        };
+
+        attrs.insert(key.trim().to_ascii_uppercase(), value.to_string());
+        rest = remaining.trim_start_matches(',').trim_start();
+    }
+
+    attrs
+}
+
+fn resolve(base: &str, uri: &str) -> String {
+    url::Url::parse(base)
+        .and_then(|b| b.join(uri))
+        .map(|u| u.to_string())
+        .unwrap_or_else(|_| uri.to_string())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = "https://cdn.example/live/index.m3u8";
+
+    type Expected<'a> = (&'a str, u64, Option<(u64, u64)>, bool, Option<&'a str>, bool);
+
+    fn media(content: &str) -> MediaPlaylist {
+        match parse(BASE, content) {
+            Some(HlsPlaylist::Media(media)) => media,
+            Some(HlsPlaylist::Master(_)) => panic!("parsed a master playlist from {:?}", content),
+            None => panic!("failed to parse {:?}", content),
+        }
+    }
+
+    fn segments(media: &MediaPlaylist) -> Vec<Expected<'_>> {
+        media.segments.iter()
+            .map(|s| (
+                s.uri.as_str(),
+                s.sequence,
+                s.range.as_ref().map(|r| (r.offset, r.length)),
+                s.discontinuity,
+                s.init.as_ref().map(|i| i.uri.as_str()),
+                s.encrypted,
+            ))
+            .collect()
+    }
+
+    #[test]
+    fn selects_audio_from_master_playlists() {
+        let cases = [
+            (
+                "#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=800000,CODECS=\"avc1.4d401f,mp4a.40.2\"\nvideo/low.m3u8\n#EXT-X-STREAM-INF:BANDWIDTH=128000,CODECS=\"mp4a.40.2\"\naudio/high.m3u8\n#EXT-X-STREAM-INF:BANDWIDTH=64000,CODECS=\"mp4a.40.5\"\naudio/low.m3u8\n",
+                "https://cdn.example/live/audio/high.m3u8",
+            ),
+            (
+                "#EXTM3U\n#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",NAME=\"en\",AUTOSELECT=YES,URI=\"en.m3u8\"\n#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",NAME=\"main\",DEFAULT=YES,URI=\"main.m3u8\"\n#EXT-X-MEDIA:TYPE=SUBTITLES,GROUP-ID=\"subs\",URI=\"subs.m3u8\"\n#EXT-X-STREAM-INF:BANDWIDTH=900000,AUDIO=\"aud\"\nvideo.m3u8\n",
+                "https://cdn.example/live/main.m3u8",
+            ),
+            (
+                "#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=2000000,CODECS=\"avc1.640028,mp4a.40.2\"\nhd.m3u8\n#EXT-X-STREAM-INF:BANDWIDTH=500000,CODECS=\"avc1.42e01e,mp4a.40.2\"\n/sd.m3u8\n",
+                "https://cdn.example/sd.m3u8",
+            ),
+        ];
+
+        for (content, expected) in cases {
+            let Some(HlsPlaylist::Master(master)) = parse(BASE, content) else { panic!("not a master playlist: {:?}", content) };
+            assert_eq!(master.select_audio(), Some(expected), "{:?}", content);
+        }
+    }
+
+    #[test]
+    fn parses_media_playlists() {
+        let cases: &[(&str, f64, bool, &[Expected<'_>])] = &[
+            (
+                "\u{feff}#EXTM3U\r\n#EXT-X-TARGETDURATION:4\r\n#EXT-X-MEDIA-SEQUENCE:120\r\n#EXTINF:4.0,\r\nseg120.ts\r\n#EXT-X-DISCONTINUITY\r\n#EXTINF:3.5,\r\nhttps://other.example/seg121.aac\r\n",
+                4.0,
+                false,
+                &[
+                    ("https://cdn.example/live/seg120.ts", 120, None, false, None, false),
+                    ("https://other.example/seg121.aac", 121, None, true, None, false),
+                ],
+            ),
+            (
+                "#EXTM3U\n#EXT-X-TARGETDURATION:6\n#EXTINF:6,\n#EXT-X-BYTERANGE:1000@0\nall.ts\n#EXTINF:6,\n#EXT-X-BYTERANGE:500\nall.ts\n#EXTINF:6,\n#EXT-X-BYTERANGE:200@5000\nall.ts\n#EXTINF:6,\n#EXT-X-BYTERANGE:300\nall.ts\n#EXT-X-ENDLIST\n",
+                6.0,
+                true,
+                &[
+                    ("https://cdn.example/live/all.ts", 0, Some((0, 1000)), false, None, false),
+                    ("https://cdn.example/live/all.ts", 1, Some((1000, 500)), false, None, false),
+                    ("https://cdn.example/live/all.ts", 2, Some((5000, 200)), false, None, false),
+                    ("https://cdn.example/live/all.ts", 3, Some((5200, 300)), false, None, false),
+                ],
+            ),
+            (
+                "#EXTM3U\n#EXT-X-MAP:URI=\"init.mp4\",BYTERANGE=\"720@0\"\n#EXTINF:2,\nseg0.m4s\n#EXT-X-MAP:URI=\"init2.mp4\"\n#EXTINF:2,\nseg1.m4s\n#EXT-X-ENDLIST\n",
+                6.0,
+                true,
+                &[
+                    ("https://cdn.example/live/seg0.m4s", 0, None, false, Some("https://cdn.example/live/init.mp4"), false),
+                    ("https://cdn.example/live/seg1.m4s", 1, None, false, Some("https://cdn.example/live/init2.mp4"), false),
+                ],
+            ),
+            (
+                "#EXTM3U\n#EXT-X-KEY:METHOD=AES-128,URI=\"key.bin\"\n#EXTINF:6,\nlocked.ts\n#EXT-X-KEY:METHOD=NONE\n#EXTINF:6,\nopen.ts\n",
+                6.0,
+                false,
+                &[
+                    ("https://cdn.example/live/locked.ts", 0, None, false, None, true),
+                    ("https://cdn.example/live/open.ts", 1, None, false, None, false),
+                ],
+            ),
+            (
+                "#EXTM3U\n#EXT-X-MEDIA-SEQUENCE:7\n#EXTINF:6,\n#EXT-X-BYTERANGE:0@10\nempty.ts\n#EXTINF:6,\n#EXT-X-BYTERANGE:10@18446744073709551615\nhuge.ts\n#EXTINF:6,\n#EXT-X-BYTERANGE:abc\nbad.ts\n#EXTINF:6,\nafter.ts\n",
+                6.0,
+                false,
+                &[("https://cdn.example/live/after.ts", 10, None, false, None, false)],
+            ),
+        ];
+
+        for (content, target_duration, ended, expected) in cases {
+            let media = media(content);
+            assert_eq!(media.target_duration, *target_duration, "{:?}", content);
+            assert_eq!(media.ended, *ended, "{:?}", content);
+            assert_eq!(segments(&media), *expected, "{:?}", content);
+        }
+    }
+
+    #[test]
+    fn reads_init_section_ranges() {
+        let media = media("#EXTM3U\n#EXT-X-MAP:URI=\"init.mp4\",BYTERANGE=\"720@16\"\n#EXTINF:2,\nseg0.m4s\n");
+        let init = media.segments[0].init.as_ref().unwrap();
+        assert_eq!(init.range, Some(ByteRange { offset: 16, length: 720 }));
+        assert_eq!(media.duration_ms(), 2000);
+    }
+
+    #[test]
+    fn rejects_non_playlists() {
+        for content in ["", "seg0.ts\n", "<html></html>", "#EXTINF:6,\nseg0.ts\n#EXTM3U\n"] {
+            assert!(parse(BASE, content).is_none(), "{:?}", content);
+        }
+    }
+
+    #[test]
+    fn parses_quoted_attributes() {
+        let attrs = parse_attributes("TYPE=AUDIO,NAME=\"a, b\",uri=\"x.m3u8\",DEFAULT=YES");
+        assert_eq!(attrs.get("NAME").map(String::as_str), Some("a, b"));
+        assert_eq!(attrs.get("URI").map(String::as_str), Some("x.m3u8"));
+        assert_eq!(attrs.get("DEFAULT").map(String::as_str), Some("YES"));
+    }
+}
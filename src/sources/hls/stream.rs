@@ -0,0 +1,390 @@
+use std::collections::VecDeque;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use bytes::{Buf, Bytes};
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio::sync::{mpsc, oneshot};
+use crate::playback::codecs::{AudioCodec, AudioContainer};
+use crate::playback::media::{MediaInfo, MediaStream};
+use crate::utils::http::client;
+use crate::utils::{log, Level};
+use super::playlist::{self, ByteRange, HlsPlaylist, InitSection, MediaPlaylist, Segment};
+use super::ts::TsDemuxer;
+
+const SEGMENT_BUFFER: usize = 4;
+const LIVE_EDGE_SEGMENTS: usize = 3;
+const MAX_RELOAD_FAILURES: u32 = 5;
+
+pub struct SegmentReader {
+    rx: mpsc::Receiver<Bytes>,
+    chunk: Bytes,
+}
+
+impl AsyncRead for SegmentReader {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        while self.chunk.is_empty() {
+            match futures_util::ready!(self.rx.poll_recv(cx)) {
+                Some(chunk) => self.chunk = chunk,
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+
+        let len = self.chunk.len().min(buf.remaining());
+        buf.put_slice(&self.chunk[..len]);
+        self.chunk.advance(len);
+        Poll::Ready(Ok(()))
+    }
+}
+
+enum SegmentFormat {
+    Transport(TsDemuxer),
+    Packed(AudioContainer),
+    Fragmented,
+}
+
+pub struct HlsLoader {
+    playlist_url: String,
+    target_duration: f64,
+    ended: bool,
+    last_sequence: Option<u64>,
+    queue: VecDeque<Segment>,
+    reload_failures: u32,
+}
+
+pub async fn fetch_text(url: &str) -> Option<String> {
+    let response = client().get(url).send().await.ok()?;
+    if !response.status().is_success() {
+        log(Level::Warn, "HlsSource", format!("{} responded with {}", url, response.status()));
+        return None;
+    }
+    response.text().await.ok()
+}
+
+async fn fetch_bytes(url: &str, range: Option<&ByteRange>) -> Option<Bytes> {
+    let mut request = client().get(url);
+    if let Some(range) = range {
+        let Some(last) = range.last_byte() else {
+            log(Level::Warn, "HlsSource", format!("Skipping segment {} with an invalid byte range", url));
+            return None;
+        };
+        request = request.header("Range", format!("bytes={}-{}", range.offset, last));
+    }
+
+    let response = match request.send().await {
+        Ok(r) if r.status().is_success() => r,
+        Ok(r) => {
+            log(Level::Warn, "HlsSource", format!("Segment {} responded with {}", url, r.status()));
+            return None;
+        },
+        Err(e) => {
+            log(Level::Warn, "HlsSource", format!("Failed to fetch segment {}: {}", url, e));
+            return None;
+        }
+    };
+    response.bytes().await.ok()
+}
+
+pub async fn resolve_media_url(url: &str) -> Option<(String, playlist::MediaPlaylist)> {
+    let content = fetch_text(url).await?;
+    match playlist::parse(url, &content)? {
+        HlsPlaylist::Media(media) => Some((url.to_string(), media)),
+        HlsPlaylist::Master(master) => {
+            let media_url = master.select_audio()?.to_string();
+            let content = fetch_text(&media_url).await?;
+            match playlist::parse(&media_url, &content)? {
+                HlsPlaylist::Media(media) => Some((media_url, media)),
+                HlsPlaylist::Master(_) => None,
+            }
+        }
+    }
+}
+
+impl HlsLoader {
+    pub async fn open(url: &str) -> Option<Self> {
+        let (playlist_url, media) = resolve_media_url(url).await?;
+        let mut loader = Self {
+            playlist_url,
+            target_duration: media.target_duration,
+            ended: media.ended,
+            last_sequence: None,
+            queue: VecDeque::new(),
+            reload_failures: 0,
+        };
+
+        loader.enqueue(live_edge(media));
+        Some(loader)
+    }
+
+    fn enqueue(&mut self, segments: impl Iterator<Item = Segment>) {
+        for segment in segments {
+            if self.last_sequence.is_some_and(|last| segment.sequence <= last) {
+                continue;
+            }
+            self.last_sequence = Some(segment.sequence);
+            self.queue.push_back(segment);
+        }
+    }
+
+    async fn reload(&mut self) -> bool {
+        let content = fetch_text(&self.playlist_url).await;
+        let Some(HlsPlaylist::Media(media)) = content.and_then(|c| playlist::parse(&self.playlist_url, &c)) else {
+            self.reload_failures += 1;
+            return self.reload_failures < MAX_RELOAD_FAILURES;
+        };
+
+        self.reload_failures = 0;
+        self.update(media);
+        true
+    }
+
+    /// Queues the segments of a reloaded playlist that follow the ones already queued.
+    fn update(&mut self, media: MediaPlaylist) {
+        self.target_duration = media.target_duration;
+        self.ended = media.ended;
+
+        let first = media.segments.first().map(|s| s.sequence);
+        let newest = media.segments.last().map(|s| s.sequence);
+        match (self.last_sequence, first, newest) {
+            // A restarted encoder resets EXT-X-MEDIA-SEQUENCE, and all of its segments would
+            // otherwise look like ones already played.
+            (Some(last), _, Some(newest)) if newest < last => {
+                log(Level::Warn, "HlsSource", format!("Media sequence went back from {} to {}, treating it as a discontinuity", last, newest));
+                self.last_sequence = None;
+                let mut segments = live_edge(media).peekable();
+                if let Some(first) = segments.peek_mut() {
+                    first.discontinuity = true;
+                }
+                self.enqueue(segments);
+                return;
+            },
+            (Some(last), Some(first), _) if first > last + 1 => {
+                log(Level::Warn, "HlsSource", format!("Fell behind the live edge, skipping {} segments", first - last - 1));
+            },
+            _ => {},
+        }
+
+        self.enqueue(media.segments.into_iter());
+    }
+
+    async fn next_segment(&mut self) -> Option<Segment> {
+        loop {
+            if let Some(segment) = self.queue.pop_front() {
+                return Some(segment);
+            }
+            if self.ended {
+                return None;
+            }
+
+            tokio::time::sleep(Duration::from_secs_f64((self.target_duration / 2.0).max(1.0))).await;
+            if !self.reload().await {
+                log(Level::Error, "HlsSource", format!("Giving up on {} after repeated playlist failures", self.playlist_url));
+                return None;
+            }
+        }
+    }
+
+    pub async fn run(mut self, first: oneshot::Sender<MediaStream>) {
+        let (next_tx, next_rx) = mpsc::channel(1);
+        let mut first = Some((first, next_rx));
+        let mut group: Option<(mpsc::Sender<Bytes>, SegmentFormat)> = None;
+        let mut init: Option<InitSection> = None;
+
+        while let Some(segment) = self.next_segment().await {
+            if segment.encrypted {
+                log(Level::Error, "HlsSource", "Encrypted HLS segments are not supported");
+                return;
+            }
+
+            let Some(data) = fetch_bytes(&segment.uri, segment.range.as_ref()).await else { continue };
+            let new_group = group.is_none() || segment.discontinuity || segment.init != init;
+
+            let (payload, stream) = if new_group {
+                let mut payload = Vec::new();
+                if let Some(section) = &segment.init {
+                    match fetch_bytes(&section.uri, section.range.as_ref()).await {
+                        Some(bytes) => payload.extend_from_slice(&bytes),
+                        None => continue,
+                    }
+                }
+                init = segment.init.clone();
+
+                let mut format = detect_format(&segment, &data);
+                payload.extend(process(&mut format, data));
+
+                let (container, codec) = match &format {
+                    SegmentFormat::Transport(ts) => (ts.container(), ts.codec()),
+                    SegmentFormat::Packed(container) => (*container, codec_for(*container)),
+                    SegmentFormat::Fragmented => (AudioContainer::Mp4, AudioCodec::Unknown),
+                };
+
+                let (tx, rx) = mpsc::channel(SEGMENT_BUFFER);
+                let reader = SegmentReader { rx, chunk: Bytes::new() };
+                group = Some((tx, format));
+                (Bytes::from(payload), Some(MediaStream::sequential(reader, MediaInfo::new(container, codec))))
+            } else {
+                let (_, format) = group.as_mut().unwrap();
+                (Bytes::from(process(format, data)), None)
+            };
+
+            if let Some(stream) = stream {
+                if segment.discontinuity {
+                    log(Level::Debug, "HlsSource", format!("Discontinuity at segment {}", segment.sequence));
+                }
+
+                match first.take() {
+                    Some((sender, next_rx)) => {
+                        if sender.send(stream.with_continuation(next_rx)).is_err() {
+                            return;
+                        }
+                    },
+                    None => {
+                        if next_tx.send(stream).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+            }
+
+            let (tx, _) = group.as_ref().unwrap();
+            if !payload.is_empty() && tx.send(payload).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// The segments to start with: all of them for a finished playlist, the last few for a live one.
+fn live_edge(media: MediaPlaylist) -> impl Iterator<Item = Segment> {
+    let skip = if media.ended { 0 } else { media.segments.len().saturating_sub(LIVE_EDGE_SEGMENTS) };
+    media.segments.into_iter().skip(skip)
+}
+
+fn detect_format(segment: &Segment, data: &[u8]) -> SegmentFormat {
+    if segment.init.is_some() {
+        return SegmentFormat::Fragmented;
+    }
+    if TsDemuxer::is_transport_stream(data) {
+        return SegmentFormat::Transport(TsDemuxer::new());
+    }
+
+    let audio = skip_id3(data);
+    if audio.len() >= 2 && audio[0] == 0xFF && audio[1] & 0xF6 == 0xF0 {
+        return SegmentFormat::Packed(AudioContainer::Aac);
+    }
+
+    let ext = segment.uri.split(['?', '#']).next()
+        .and_then(|p| p.rsplit('.').next())
+        .unwrap_or("");
+    match AudioContainer::from_extension(ext) {
+        AudioContainer::Mp4 => SegmentFormat::Fragmented,
+        AudioContainer::Unknown => SegmentFormat::Packed(AudioContainer::Mp3),
+        container => SegmentFormat::Packed(container),
+    }
+}
+
+fn process(format: &mut SegmentFormat, data: Bytes) -> Vec<u8> {
+    match format {
+        SegmentFormat::Transport(ts) => ts.push(&data),
+        SegmentFormat::Packed(_) => skip_id3(&data).to_vec(),
+        SegmentFormat::Fragmented => data.to_vec(),
+    }
+}
+
+fn codec_for(container: AudioContainer) -> AudioCodec {
+    match container {
+        AudioContainer::Aac => AudioCodec::Aac,
+        AudioContainer::Mp3 => AudioCodec::Mp3,
+        AudioContainer::Flac => AudioCodec::Flac,
+        _ => AudioCodec::Unknown,
+    }
+}
+
+fn skip_id3(data: &[u8]) -> &[u8] {
+    if data.len() < 10 || &data[0..3] != b"ID3" {
+        return data;
+    }
+
+    let size = data[6..10].iter().fold(0usize, |acc, b| (acc << 7) | (*b & 0x7F) as usize);
+    let footer = if data[5] & 0x10 != 0 { 10 } else { 0 };
+    data.get(10 + size + footer..).unwrap_or(&[])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "https://cdn.example/live/index.m3u8";
+
+    fn media(first: u64, count: u64) -> MediaPlaylist {
+        let mut content = format!("#EXTM3U\n#EXT-X-TARGETDURATION:4\n#EXT-X-MEDIA-SEQUENCE:{}\n", first);
+        for sequence in first..first + count {
+            content.push_str(&format!("#EXTINF:4,\nseg{}.ts\n", sequence));
+        }
+        match playlist::parse(URL, &content) {
+            Some(HlsPlaylist::Media(media)) => media,
+            _ => unreachable!(),
+        }
+    }
+
+    fn loader(first: MediaPlaylist) -> HlsLoader {
+        let mut loader = HlsLoader {
+            playlist_url: URL.to_string(),
+            target_duration: first.target_duration,
+            ended: first.ended,
+            last_sequence: None,
+            queue: VecDeque::new(),
+            reload_failures: 0,
+        };
+        loader.enqueue(live_edge(first));
+        loader
+    }
+
+    /// A queued segment's sequence and whether it starts a discontinuity.
+    type Queued = (u64, bool);
+
+    fn drain(loader: &mut HlsLoader) -> Vec<Queued> {
+        loader.queue.drain(..).map(|s| (s.sequence, s.discontinuity)).collect()
+    }
+
+    #[test]
+    fn queues_segments_after_the_live_edge() {
+        let cases: &[(u64, u64, &[Queued])] = &[
+            (100, 5, &[]),
+            (101, 5, &[(105, false)]),
+            (103, 6, &[(105, false), (106, false), (107, false), (108, false)]),
+            (110, 3, &[(110, false), (111, false), (112, false)]),
+        ];
+
+        for (first, count, expected) in cases {
+            let mut loader = loader(media(100, 5));
+            assert_eq!(drain(&mut loader), vec![(102, false), (103, false), (104, false)]);
+
+            loader.update(media(*first, *count));
+            assert_eq!(drain(&mut loader), *expected, "reload at {}", first);
+        }
+    }
+
+    #[test]
+    fn restarts_when_the_media_sequence_goes_back() {
+        let mut loader = loader(media(500, 4));
+        drain(&mut loader);
+
+        loader.update(media(0, 5));
+        assert_eq!(drain(&mut loader), vec![(2, true), (3, false), (4, false)]);
+
+        loader.update(media(1, 6));
+        assert_eq!(drain(&mut loader), vec![(5, false), (6, false)]);
+    }
+
+    #[test]
+    fn starts_finished_playlists_at_the_beginning() {
+        let mut finished = media(0, 5);
+        finished.ended = true;
+
+        let mut loader = loader(finished);
+        assert!(loader.ended);
+        assert_eq!(drain(&mut loader).len(), 5);
+    }
+}
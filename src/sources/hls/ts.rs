@@ -0,0 +1,325 @@
+use crate::playback::codecs::{AudioCodec, AudioContainer};
+
+const PACKET_SIZE: usize = 188;
+const SYNC_BYTE: u8 = 0x47;
+
+const STREAM_TYPE_MPEG1_AUDIO: u8 = 0x03;
+const STREAM_TYPE_MPEG2_AUDIO: u8 = 0x04;
+const STREAM_TYPE_ADTS_AAC: u8 = 0x0F;
+
+pub struct TsDemuxer {
+    pmt_pid: Option<u16>,
+    audio_pid: Option<u16>,
+    codec: AudioCodec,
+    pending: Vec<u8>,
+    in_pes_header: Vec<u8>,
+}
+
+impl Default for TsDemuxer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TsDemuxer {
+    pub fn new() -> Self {
+        Self {
+            pmt_pid: None,
+            audio_pid: None,
+            codec: AudioCodec::Unknown,
+            pending: Vec::new(),
+            in_pes_header: Vec::new(),
+        }
+    }
+
+    pub fn is_transport_stream(data: &[u8]) -> bool {
+        data.len() >= PACKET_SIZE && data[0] == SYNC_BYTE
+            && (data.len() < PACKET_SIZE * 2 || data[PACKET_SIZE] == SYNC_BYTE)
+    }
+
+    pub fn container(&self) -> AudioContainer {
+        match self.codec {
+            AudioCodec::Aac => AudioContainer::Aac,
+            AudioCodec::Mp3 => AudioContainer::Mp3,
+            _ => AudioContainer::Unknown,
+        }
+    }
+
+    pub fn codec(&self) -> AudioCodec {
+        self.codec
+    }
+
+    pub fn push(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut output = Vec::new();
+        self.pending.extend_from_slice(data);
+
+        let mut offset = 0;
+        while offset + PACKET_SIZE <= self.pending.len() {
+            if self.pending[offset] != SYNC_BYTE {
+                offset += 1;
+                continue;
+            }
+
+            let packet: [u8; PACKET_SIZE] = self.pending[offset..offset + PACKET_SIZE].try_into().unwrap();
+            self.read_packet(&packet, &mut output);
+            offset += PACKET_SIZE;
+        }
+
+        self.pending.drain(..offset);
+        output
+    }
+
+    fn read_packet(&mut self, packet: &[u8; PACKET_SIZE], output: &mut Vec<u8>) {
+        let unit_start = packet[1] & 0x40 != 0;
+        let pid = (u16::from(packet[1] & 0x1F) << 8) | u16::from(packet[2]);
+        let adaptation = (packet[3] >> 4) & 0x03;
+
+        let mut start = 4;
+        if adaptation & 0x02 != 0 {
+            start += 1 + packet[4] as usize;
+        }
+        if adaptation & 0x01 == 0 || start >= PACKET_SIZE {
+            return;
+        }
+        let payload = &packet[start..];
+
+        if pid == 0 {
+            self.read_pat(payload, unit_start);
+        } else if Some(pid) == self.pmt_pid {
+            self.read_pmt(payload, unit_start);
+        } else if Some(pid) == self.audio_pid {
+            self.read_pes(payload, unit_start, output);
+        }
+    }
+
+    fn section(payload: &[u8], unit_start: bool) -> Option<&[u8]> {
+        if !unit_start {
+            return None;
+        }
+        let pointer = *payload.first()? as usize;
+        let section = payload.get(1 + pointer..)?;
+        let length = ((usize::from(section.get(1)? & 0x0F)) << 8) | usize::from(*section.get(2)?);
+        section.get(..3 + length)
+    }
+
+    fn read_pat(&mut self, payload: &[u8], unit_start: bool) {
+        let Some(section) = Self::section(payload, unit_start) else { return };
+        let Some(entries) = section.get(8..section.len().saturating_sub(4)) else { return };
+
+        for entry in entries.chunks_exact(4) {
+            let program = u16::from_be_bytes([entry[0], entry[1]]);
+            if program != 0 {
+                self.pmt_pid = Some((u16::from(entry[2] & 0x1F) << 8) | u16::from(entry[3]));
+                return;
+            }
+        }
+    }
+
+    fn read_pmt(&mut self, payload: &[u8], unit_start: bool) {
+        let Some(section) = Self::section(payload, unit_start) else { return };
+        if section.len() < 16 {
+            return;
+        }
+
+        let info_length = (usize::from(section[10] & 0x0F) << 8) | usize::from(section[11]);
+        let end = section.len() - 4;
+        let mut offset = 12 + info_length;
+
+        while offset + 5 <= end {
+            let stream_type = section[offset];
+            let pid = (u16::from(section[offset + 1] & 0x1F) << 8) | u16::from(section[offset + 2]);
+            let es_length = (usize::from(section[offset + 3] & 0x0F) << 8) | usize::from(section[offset + 4]);
+
+            let codec = match stream_type {
+                STREAM_TYPE_ADTS_AAC => AudioCodec::Aac,
+                STREAM_TYPE_MPEG1_AUDIO | STREAM_TYPE_MPEG2_AUDIO => AudioCodec::Mp3,
+                _ => AudioCodec::Unknown,
+            };
+
+            if codec != AudioCodec::Unknown && self.audio_pid.is_none_or(|current| current == pid) {
+                self.audio_pid = Some(pid);
+                self.codec = codec;
+                return;
+            }
+            offset += 5 + es_length;
+        }
+    }
+
+    fn read_pes(&mut self, payload: &[u8], unit_start: bool, output: &mut Vec<u8>) {
+        if unit_start {
+            self.in_pes_header.clear();
+        }
+
+        if unit_start || !self.in_pes_header.is_empty() {
+            self.in_pes_header.extend_from_slice(payload);
+            let header = &self.in_pes_header;
+            if header.len() < 9 {
+                return;
+            }
+            if header[0..3] != [0, 0, 1] {
+                self.in_pes_header.clear();
+                return;
+            }
+
+            let header_length = 9 + header[8] as usize;
+            if header.len() < header_length {
+                return;
+            }
+
+            output.extend_from_slice(&header[header_length..]);
+            self.in_pes_header.clear();
+            return;
+        }
+
+        output.extend_from_slice(payload);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PMT_PID: u16 = 0x1000;
+    const AUDIO_PID: u16 = 0x101;
+    const VIDEO_PID: u16 = 0x100;
+
+    fn packet(pid: u16, unit_start: bool, payload: &[u8]) -> Vec<u8> {
+        assert!(payload.len() <= PACKET_SIZE - 4);
+        let mut packet = vec![SYNC_BYTE, (u8::from(unit_start) << 6) | (pid >> 8) as u8, pid as u8];
+        let stuffing = PACKET_SIZE - 4 - payload.len();
+        if stuffing == 0 {
+            packet.push(0x10);
+        } else {
+            packet.push(0x30);
+            packet.push(stuffing as u8 - 1);
+            if stuffing > 1 {
+                packet.push(0);
+                packet.extend(std::iter::repeat_n(0xFF, stuffing - 2));
+            }
+        }
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    fn section(table_id: u8, body: &[u8]) -> Vec<u8> {
+        let length = body.len() + 4;
+        let mut section = vec![0, table_id, 0xB0 | (length >> 8) as u8, length as u8];
+        section.extend_from_slice(body);
+        section.extend([0; 4]);
+        section
+    }
+
+    fn pat() -> Vec<u8> {
+        packet(0, true, &section(0x00, &[0, 1, 0xC1, 0, 0, 0, 0, 0xE0, 0x10, 0, 1, 0xE0 | (PMT_PID >> 8) as u8, PMT_PID as u8]))
+    }
+
+    fn pmt(streams: &[(u8, u16)]) -> Vec<u8> {
+        let mut body = vec![0, 1, 0xC1, 0, 0, 0xE1, 0x00, 0xF0, 0];
+        for (stream_type, pid) in streams {
+            body.extend([*stream_type, 0xE0 | (pid >> 8) as u8, *pid as u8, 0xF0, 0]);
+        }
+        packet(PMT_PID, true, &section(0x02, &body))
+    }
+
+    fn pes(header_data: usize, data: &[u8]) -> Vec<u8> {
+        let mut pes = vec![0, 0, 1, 0xC0, 0, 0, 0x80, 0x80, header_data as u8];
+        pes.extend(std::iter::repeat_n(0xAA, header_data));
+        pes.extend_from_slice(data);
+        pes
+    }
+
+    fn audio(len: usize, seed: u8) -> Vec<u8> {
+        (0..len).map(|i| (i as u8).wrapping_mul(31).wrapping_add(seed)).collect()
+    }
+
+    /// A PAT, a PMT with a video and an AAC stream, and two audio PES packets spread over
+    /// several TS packets with a video packet in between.
+    fn stream() -> (Vec<u8>, Vec<u8>) {
+        let first = audio(300, 1);
+        let second = audio(90, 2);
+        let first_pes = pes(5, &first);
+
+        let mut ts = pat();
+        ts.extend(pmt(&[(0x1B, VIDEO_PID), (STREAM_TYPE_ADTS_AAC, AUDIO_PID)]));
+        ts.extend(packet(AUDIO_PID, true, &first_pes[..184]));
+        ts.extend(packet(VIDEO_PID, true, &[0xEE; 184]));
+        ts.extend(packet(AUDIO_PID, false, &first_pes[184..]));
+        ts.extend(packet(AUDIO_PID, true, &pes(0, &second)));
+
+        (ts, [first, second].concat())
+    }
+
+    #[test]
+    fn extracts_audio_from_transport_streams() {
+        let (ts, expected) = stream();
+        let mut demuxer = TsDemuxer::new();
+        assert!(TsDemuxer::is_transport_stream(&ts));
+        assert_eq!(demuxer.push(&ts), expected);
+        assert_eq!(demuxer.codec(), AudioCodec::Aac);
+        assert_eq!(demuxer.container(), AudioContainer::Aac);
+    }
+
+    #[test]
+    fn reassembles_packets_split_across_pushes() {
+        let (ts, expected) = stream();
+        for chunk_size in [1, 7, 100, 187, 189, 400] {
+            let mut demuxer = TsDemuxer::new();
+            let output: Vec<u8> = ts.chunks(chunk_size).flat_map(|chunk| demuxer.push(chunk)).collect();
+            assert_eq!(output, expected, "chunks of {}", chunk_size);
+        }
+    }
+
+    #[test]
+    fn reads_pes_headers_split_across_packets() {
+        let data = audio(200, 3);
+        let pes = pes(20, &data);
+        let mut ts = pat();
+        ts.extend(pmt(&[(STREAM_TYPE_MPEG1_AUDIO, AUDIO_PID)]));
+        ts.extend(packet(AUDIO_PID, true, &pes[..6]));
+        ts.extend(packet(AUDIO_PID, false, &pes[6..25]));
+        ts.extend(packet(AUDIO_PID, false, &pes[25..125]));
+        ts.extend(packet(AUDIO_PID, false, &pes[125..]));
+
+        let mut demuxer = TsDemuxer::new();
+        assert_eq!(demuxer.push(&ts), data);
+        assert_eq!(demuxer.container(), AudioContainer::Mp3);
+    }
+
+    #[test]
+    fn resyncs_after_bad_sync_bytes() {
+        let (ts, expected) = stream();
+        let packets: Vec<&[u8]> = ts.chunks(PACKET_SIZE).collect();
+        let cases: [&[u8]; 3] = [&[0x00], &[0x12, 0x34, 0x56], &[0xFF; 40]];
+
+        for garbage in cases {
+            let mut corrupted = garbage.to_vec();
+            for packet in &packets {
+                corrupted.extend_from_slice(packet);
+                corrupted.extend_from_slice(garbage);
+            }
+
+            let mut demuxer = TsDemuxer::new();
+            assert_eq!(demuxer.push(&corrupted), expected, "{:?}", garbage);
+        }
+    }
+
+    #[test]
+    fn ignores_payloads_before_the_program_tables() {
+        let (ts, _) = stream();
+        let early = packet(AUDIO_PID, true, &pes(0, &[1, 2, 3]));
+
+        let mut demuxer = TsDemuxer::new();
+        assert!(demuxer.push(&early).is_empty());
+        assert_eq!(demuxer.codec(), AudioCodec::Unknown);
+        assert!(!demuxer.push(&ts).is_empty());
+    }
+
+    #[test]
+    fn recognizes_transport_streams() {
+        let (ts, _) = stream();
+        assert!(TsDemuxer::is_transport_stream(&ts[..PACKET_SIZE]));
+        assert!(!TsDemuxer::is_transport_stream(&ts[..PACKET_SIZE - 1]));
+        assert!(!TsDemuxer::is_transport_stream(&ts[1..]));
+        assert!(!TsDemuxer::is_transport_stream(b"ID3\x04\x00\x00\x00\x00\x00\x00"));
+    }
+}
@@ -0,0 +1,204 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio::sync::mpsc;
+use crate::playback::media::StreamMetadata;
+
+const SCRATCH_SIZE: usize = 16 * 1024;
+
+pub struct IcyReader<R> {
+    inner: R,
+    metaint: usize,
+    remaining: usize,
+    meta_len: Option<usize>,
+    meta_buf: Vec<u8>,
+    scratch: Vec<u8>,
+    last: Option<StreamMetadata>,
+    sender: mpsc::UnboundedSender<StreamMetadata>,
+}
+
+impl<R: AsyncRead + Unpin> IcyReader<R> {
+    pub fn new(inner: R, metaint: usize, sender: mpsc::UnboundedSender<StreamMetadata>) -> Self {
+        Self {
+            inner,
+            metaint,
+            remaining: metaint,
+            meta_len: None,
+            meta_buf: Vec::new(),
+            scratch: vec![0; SCRATCH_SIZE],
+            last: None,
+            sender,
+        }
+    }
+
+    fn poll_metadata(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<bool>> {
+        loop {
+            let target = match self.meta_len {
+                Some(len) => len,
+                None => {
+                    let mut byte = [0u8; 1];
+                    let mut buf = ReadBuf::new(&mut byte);
+                    futures_util::ready!(Pin::new(&mut self.inner).poll_read(cx, &mut buf))?;
+                    if buf.filled().is_empty() {
+                        return Poll::Ready(Ok(false));
+                    }
+
+                    let len = byte[0] as usize * 16;
+                    self.meta_len = Some(len);
+                    self.meta_buf.clear();
+                    len
+                }
+            };
+
+            if self.meta_buf.len() >= target {
+                self.meta_len = None;
+                self.remaining = self.metaint;
+                self.publish();
+                return Poll::Ready(Ok(true));
+            }
+
+            let want = (target - self.meta_buf.len()).min(self.scratch.len());
+            let mut buf = ReadBuf::new(&mut self.scratch[..want]);
+            futures_util::ready!(Pin::new(&mut self.inner).poll_read(cx, &mut buf))?;
+            let read = buf.filled().len();
+            if read == 0 {
+                return Poll::Ready(Ok(false));
+            }
+            self.meta_buf.extend_from_slice(&self.scratch[..read]);
+        }
+    }
+
+    fn publish(&mut self) {
+        if self.meta_buf.is_empty() {
+            return;
+        }
+
+        let raw = String::from_utf8_lossy(&self.meta_buf);
+        let raw = raw.trim_end_matches('\0');
+        let Some(title) = parse_field(raw, "StreamTitle") else { return };
+
+        let metadata = StreamMetadata {
+            title,
+            url: parse_field(raw, "StreamUrl").filter(|u| !u.is_empty()),
+        };
+
+        if self.last.as_ref() != Some(&metadata) {
+            let _ = self.sender.send(metadata.clone());
+            self.last = Some(metadata);
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for IcyReader<R> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        if this.remaining == 0 && !futures_util::ready!(this.poll_metadata(cx))? {
+            return Poll::Ready(Ok(()));
+        }
+
+        let want = this.remaining.min(buf.remaining()).min(this.scratch.len());
+        let mut chunk = ReadBuf::new(&mut this.scratch[..want]);
+        futures_util::ready!(Pin::new(&mut this.inner).poll_read(cx, &mut chunk))?;
+
+        let read = chunk.filled().len();
+        buf.put_slice(&this.scratch[..read]);
+        this.remaining -= read;
+        Poll::Ready(Ok(()))
+    }
+}
+
+fn parse_field(raw: &str, key: &str) -> Option<String> {
+    let start = raw.find(&format!("{}='", key))? + key.len() + 2;
+    let rest = &raw[start..];
+    let end = rest.find("';").or_else(|| rest.rfind('\'')).unwrap_or(rest.len());
+    Some(rest[..end].trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::TryStreamExt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio_util::io::StreamReader;
+
+    const METAINT: usize = 16;
+
+    fn metadata_block(text: &str) -> Vec<u8> {
+        let mut block = text.as_bytes().to_vec();
+        block.resize(text.len().div_ceil(16) * 16, 0);
+        let mut framed = vec![(block.len() / 16) as u8];
+        framed.extend(block);
+        framed
+    }
+
+    /// Serves `audio` as an ICY stream with a metadata block after every `METAINT` bytes,
+    /// writing a few bytes at a time so blocks are split across reads.
+    async fn serve(audio: Vec<u8>, blocks: Vec<Vec<u8>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = [0u8; 1024];
+            let _ = socket.read(&mut request).await.unwrap();
+
+            let mut body = Vec::new();
+            for (chunk, block) in audio.chunks(METAINT).zip(blocks) {
+                body.extend_from_slice(chunk);
+                body.extend(block);
+            }
+
+            let head = format!("HTTP/1.1 200 OK\r\nContent-Type: audio/mpeg\r\nicy-metaint: {}\r\nConnection: close\r\n\r\n", METAINT);
+            socket.write_all(head.as_bytes()).await.unwrap();
+            for piece in body.chunks(7) {
+                socket.write_all(piece).await.unwrap();
+                socket.flush().await.unwrap();
+                tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+            }
+        });
+
+        format!("http://{}/stream", address)
+    }
+
+    #[tokio::test]
+    async fn strips_metadata_split_across_chunks() {
+        let audio: Vec<u8> = (0..METAINT as u8 * 5).collect();
+        let blocks = vec![
+            metadata_block("StreamTitle='First Song';"),
+            vec![0],
+            metadata_block("StreamTitle='First Song';"),
+            metadata_block("StreamTitle='Artist - It's Second';StreamUrl='https://example.com/art.jpg';"),
+            vec![0],
+        ];
+        let url = serve(audio.clone(), blocks).await;
+
+        let response = reqwest::get(&url).await.unwrap();
+        assert_eq!(response.headers()["icy-metaint"], "16");
+        let body = StreamReader::new(response.bytes_stream().map_err(io::Error::other));
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut reader = IcyReader::new(body, METAINT, tx);
+        let mut received = Vec::new();
+        let mut buf = [0u8; 5];
+        loop {
+            let read = reader.read(&mut buf).await.unwrap();
+            if read == 0 {
+                break;
+            }
+            received.extend_from_slice(&buf[..read]);
+        }
+        assert_eq!(received, audio);
+
+        let mut events = Vec::new();
+        while let Ok(metadata) = rx.try_recv() {
+            events.push(metadata);
+        }
+        assert_eq!(events, vec![
+            StreamMetadata { title: "First Song".to_string(), url: None },
+            StreamMetadata { title: "Artist - It's Second".to_string(), url: Some("https://example.com/art.jpg".to_string()) },
+        ]);
+    }
+}
@@ -0,0 +1,179 @@
+pub mod icy;
+
+use async_trait::async_trait;
+use futures_util::TryStreamExt;
+use reqwest::header::{HeaderMap, CONTENT_LENGTH, CONTENT_TYPE};
+use tokio::sync::mpsc;
+use tokio_util::io::StreamReader;
+use crate::managers::sources::Source;
+use crate::models::load_tracks::{ErrorData, Severity, LoadTracksResponse, LoadType, LoadResultData};
+use crate::playback::codecs::{AudioCodec, AudioContainer};
+use crate::playback::media::{MediaInfo, MediaStream};
+use crate::utils::encoding::{DecodedTrack, DecodedInfo, encode_source_text, encode_track};
+use crate::utils::http::client;
+use crate::utils::{log, Level};
+use icy::IcyReader;
+
+pub const STREAM_LENGTH: u64 = i64::MAX as u64;
+
+pub struct HttpSource;
+
+struct StreamHeaders {
+    container: AudioContainer,
+    name: Option<String>,
+    metaint: Option<usize>,
+    is_stream: bool,
+}
+
+impl HttpSource {
+    pub fn new() -> Self {
+        Self
+    }
+
+    fn empty() -> LoadTracksResponse {
+        LoadTracksResponse {
+            load_type: LoadType::Empty,
+            data: LoadResultData::Empty(serde_json::json!({})),
+        }
+    }
+
+    async fn open(url: &str) -> Result<reqwest::Response, ErrorData> {
+        let response = client().get(url)
+            .header("Icy-MetaData", "1")
+            .send()
+            .await;
+
+        match response {
+            Ok(r) if r.status().is_success() => Ok(r),
+            Ok(r) => {
+                log(Level::Warn, "HttpSource", format!("{} responded with {}", url, r.status()));
+                Err(ErrorData::new("The server responded with an error", Severity::Common, format!("{} responded with {}", url, r.status())))
+            },
+            Err(e) => {
+                log(Level::Warn, "HttpSource", format!("Failed to open {}: {}", url, e));
+                Err(ErrorData::from_error("Connecting to the URL failed", Severity::Suspicious, &e))
+            }
+        }
+    }
+
+    fn read_headers(url: &str, headers: &HeaderMap) -> StreamHeaders {
+        let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).map(str::trim).filter(|v| !v.is_empty());
+
+        let mut container = header(CONTENT_TYPE.as_str())
+            .map(AudioContainer::from_mime)
+            .unwrap_or(AudioContainer::Unknown);
+
+        if container == AudioContainer::Unknown
+            && let Ok(parsed) = url::Url::parse(url)
+            && let Some(ext) = parsed.path().rsplit('.').next() {
+            container = AudioContainer::from_extension(ext);
+        }
+
+        let metaint = header("icy-metaint").and_then(|v| v.parse().ok()).filter(|v| *v > 0);
+        let is_icy = metaint.is_some() || header("icy-name").is_some() || header("icy-br").is_some();
+
+        StreamHeaders {
+            container,
+            name: header("icy-name").map(str::to_string),
+            metaint,
+            is_stream: is_icy || header(CONTENT_LENGTH.as_str()).is_none(),
+        }
+    }
+
+    fn default_title(url: &str) -> String {
+        url::Url::parse(url).ok()
+            .and_then(|u| {
+                u.path_segments()
+                    .and_then(|mut s| s.next_back().map(str::to_string))
+                    .filter(|s| !s.is_empty())
+                    .or_else(|| u.host_str().map(str::to_string))
+            })
+            .unwrap_or_else(|| url.to_string())
+    }
+}
+
+impl Default for HttpSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Source for HttpSource {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    fn priority(&self) -> u32 {
+        1
+    }
+
+    fn patterns(&self) -> Vec<&'static str> {
+        vec![r"^https?://"]
+    }
+
+    async fn search(&self, _query: &str, _search_type: &str) -> LoadTracksResponse {
+        Self::empty()
+    }
+
+    async fn resolve(&self, url: &str) -> LoadTracksResponse {
+        let response = match Self::open(url).await {
+            Ok(r) => r,
+            Err(e) => return LoadTracksResponse::error(e),
+        };
+        let headers = Self::read_headers(url, response.headers());
+        drop(response);
+
+        if headers.container == AudioContainer::Unknown && headers.metaint.is_none() {
+            return LoadTracksResponse::error(ErrorData::new("Unknown file format", Severity::Common, format!("{} is not a recognised audio format", url)));
+        }
+
+        let info = DecodedInfo {
+            title: headers.name.unwrap_or_else(|| Self::default_title(url)),
+            author: "unknown".to_string(),
+            length: if headers.is_stream { STREAM_LENGTH } else { 0 },
+            identifier: url.to_string(),
+            is_stream: headers.is_stream,
+            uri: Some(url.to_string()),
+            artwork_url: None,
+            isrc: None,
+            source_name: "http".to_string(),
+            position: 0,
+            source_data: encode_source_text(headers.container.probe_name().unwrap_or("")),
+        };
+
+        LoadTracksResponse {
+            load_type: LoadType::Track,
+            data: LoadResultData::Track(Box::new(DecodedTrack {
+                encoded: encode_track(&info),
+                info,
+                plugin_info: serde_json::json!({}),
+                user_data: serde_json::json!({}),
+            })),
+        }
+    }
+
+    async fn load_stream(&self, track: &DecodedInfo) -> Result<MediaStream, ErrorData> {
+        let response = Self::open(&track.identifier).await?;
+        let headers = Self::read_headers(&track.identifier, response.headers());
+
+        let codec = match headers.container {
+            AudioContainer::Mp3 => AudioCodec::Mp3,
+            AudioContainer::Aac => AudioCodec::Aac,
+            AudioContainer::Flac => AudioCodec::Flac,
+            _ => AudioCodec::Unknown,
+        };
+
+        let info = MediaInfo::new(headers.container, codec);
+        let body = StreamReader::new(response.bytes_stream().map_err(std::io::Error::other));
+
+        match headers.metaint {
+            Some(metaint) => {
+                let (tx, rx) = mpsc::unbounded_channel();
+                Ok(MediaStream::sequential(IcyReader::new(body, metaint, tx), info).with_metadata(rx))
+            },
+            None => Ok(MediaStream::sequential(body, info)),
+        }
+    }
+}
+
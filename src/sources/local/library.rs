@@ -0,0 +1,520 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::time::{Duration, UNIX_EPOCH};
+use notify::{EventKind, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use crate::playback::codecs::AudioContainer;
+use crate::utils::encoding::DecodedTrack;
+use crate::utils::text::{match_token, tokenize};
+use crate::utils::{log, Level};
+use super::LocalSource;
+
+const INDEX_VERSION: u32 = 1;
+const PERSIST_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Deserialize, Clone)]
+struct LibraryEntry {
+    path: PathBuf,
+    modified: u64,
+    track: DecodedTrack,
+    #[serde(skip)]
+    keywords: Keywords,
+}
+
+#[derive(Clone, Default)]
+struct Keywords {
+    title: Vec<String>,
+    author: Vec<String>,
+    album: Vec<String>,
+    file: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PersistedIndex {
+    version: u32,
+    entries: Vec<LibraryEntry>,
+}
+
+pub struct LocalLibrary {
+    roots: Vec<PathBuf>,
+    index_path: Option<PathBuf>,
+    artwork_base_url: String,
+    entries: RwLock<HashMap<PathBuf, LibraryEntry>>,
+    dirty: AtomicBool,
+}
+
+impl LibraryEntry {
+    fn new(path: PathBuf, modified: u64, track: DecodedTrack) -> Self {
+        let mut entry = Self {
+            path,
+            modified,
+            track,
+            keywords: Keywords::default(),
+        };
+        entry.index_keywords();
+        entry
+    }
+
+    fn index_keywords(&mut self) {
+        let album = self.track.plugin_info.get("album").and_then(|a| a.as_str()).unwrap_or("");
+        let file = self.path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+
+        self.keywords = Keywords {
+            title: tokenize(&self.track.info.title),
+            author: if self.track.info.author == "unknown" { Vec::new() } else { tokenize(&self.track.info.author) },
+            album: tokenize(album),
+            file: tokenize(&file),
+        };
+    }
+
+    fn score(&self, query: &[String]) -> Option<f32> {
+        let mut total = 0.0;
+        for token in query {
+            let best = [
+                (&self.keywords.title, 3.0),
+                (&self.keywords.author, 2.0),
+                (&self.keywords.album, 1.0),
+                (&self.keywords.file, 1.0),
+            ]
+                .iter()
+                .map(|(words, weight)| words.iter().map(|w| match_token(token, w)).fold(0.0, f32::max) * weight)
+                .fold(0.0, f32::max);
+
+            if best == 0.0 {
+                return None;
+            }
+            total += best;
+        }
+
+        let field_len = (self.keywords.title.len() + self.keywords.author.len()).max(1) as f32;
+        Some(total / query.len() as f32 + query.len() as f32 / field_len)
+    }
+}
+
+impl LocalLibrary {
+    pub fn new(roots: Vec<PathBuf>, index_path: Option<PathBuf>, artwork_base_url: String) -> Arc<Self> {
+        Arc::new(Self {
+            roots,
+            index_path,
+            artwork_base_url,
+            entries: RwLock::new(HashMap::new()),
+            dirty: AtomicBool::new(false),
+        })
+    }
+
+    pub fn start(self: &Arc<Self>, watch: bool) {
+        let library = self.clone();
+        std::thread::Builder::new()
+            .name("local-library".to_string())
+            .spawn(move || {
+                library.load_persisted();
+                library.scan();
+                library.persist();
+
+                if watch {
+                    library.watch();
+                }
+            })
+            .expect("Failed to spawn local library thread");
+    }
+
+    pub fn search(&self, query: &str, limit: usize) -> Vec<DecodedTrack> {
+        let tokens = tokenize(query);
+        if tokens.is_empty() {
+            return Vec::new();
+        }
+
+        let entries = self.entries.read().unwrap();
+        let isrc = query.trim();
+        let exact: Vec<DecodedTrack> = entries.values()
+            .filter(|entry| entry.track.info.isrc.as_deref().is_some_and(|i| i.eq_ignore_ascii_case(isrc)))
+            .take(limit)
+            .map(|entry| entry.track.clone())
+            .collect();
+        if !exact.is_empty() {
+            return exact;
+        }
+
+        let mut ranked: Vec<(f32, &LibraryEntry)> = entries.values()
+            .filter_map(|entry| entry.score(&tokens).map(|score| (score, entry)))
+            .collect();
+
+        ranked.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.path.cmp(&b.1.path)));
+        ranked.into_iter()
+            .take(limit)
+            .map(|(_, entry)| entry.track.clone())
+            .collect()
+    }
+
+    fn load_persisted(&self) {
+        let Some(path) = &self.index_path else { return };
+        let data = match std::fs::read(path) {
+            Ok(d) => d,
+            Err(_) => return,
+        };
+
+        match serde_json::from_slice::<PersistedIndex>(&data) {
+            Ok(index) if index.version == INDEX_VERSION => {
+                let mut entries = self.entries.write().unwrap();
+                for mut entry in index.entries {
+                    entry.index_keywords();
+                    entries.insert(entry.path.clone(), entry);
+                }
+                log(Level::Info, "LocalLibrary", format!("Loaded {} indexed tracks from {}", entries.len(), path.display()));
+            },
+            Ok(_) => log(Level::Warn, "LocalLibrary", "Ignoring library index with an outdated version"),
+            Err(e) => log(Level::Warn, "LocalLibrary", format!("Failed to read library index: {}", e)),
+        }
+    }
+
+    fn persist(&self) {
+        let Some(path) = &self.index_path else { return };
+        if !self.dirty.swap(false, Ordering::AcqRel) {
+            return;
+        }
+
+        let index = {
+            let entries = self.entries.read().unwrap();
+            PersistedIndex {
+                version: INDEX_VERSION,
+                entries: entries.values().cloned().collect(),
+            }
+        };
+
+        let tmp = path.with_extension("tmp");
+        let result = serde_json::to_vec(&index)
+            .map_err(std::io::Error::other)
+            .and_then(|data| std::fs::write(&tmp, data))
+            .and_then(|_| std::fs::rename(&tmp, path));
+
+        if let Err(e) = result {
+            log(Level::Warn, "LocalLibrary", format!("Failed to persist library index: {}", e));
+        }
+    }
+
+    fn scan(&self) {
+        let mut files = Vec::new();
+        for root in &self.roots {
+            collect_files(root, &mut files);
+        }
+
+        let mut seen = Vec::with_capacity(files.len());
+        let mut indexed = 0;
+        for file in files {
+            if self.index_file(&file) {
+                indexed += 1;
+            }
+            seen.push(file);
+        }
+
+        let mut entries = self.entries.write().unwrap();
+        let before = entries.len();
+        let seen: std::collections::HashSet<PathBuf> = seen.into_iter().collect();
+        entries.retain(|path, _| seen.contains(path));
+        if entries.len() != before {
+            self.dirty.store(true, Ordering::Release);
+        }
+
+        log(Level::Info, "LocalLibrary", format!("Library scan finished: {} tracks ({} updated)", entries.len(), indexed));
+    }
+
+    fn index_file(&self, path: &Path) -> bool {
+        let modified = modified_time(path);
+        if let Some(entry) = self.entries.read().unwrap().get(path)
+            && entry.modified == modified {
+            return false;
+        }
+
+        match LocalSource::load_file(path, &self.artwork_base_url) {
+            Ok(local) => {
+                let entry = LibraryEntry::new(path.to_path_buf(), modified, local.track);
+                self.entries.write().unwrap().insert(path.to_path_buf(), entry);
+                self.dirty.store(true, Ordering::Release);
+                true
+            },
+            Err(_) => {
+                self.remove_file(path);
+                false
+            }
+        }
+    }
+
+    fn remove_file(&self, path: &Path) {
+        let mut entries = self.entries.write().unwrap();
+        let before = entries.len();
+        entries.retain(|p, _| !p.starts_with(path));
+        if entries.len() != before {
+            self.dirty.store(true, Ordering::Release);
+        }
+    }
+
+    fn watch(&self) {
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = match notify::recommended_watcher(tx) {
+            Ok(w) => w,
+            Err(e) => {
+                log(Level::Warn, "LocalLibrary", format!("Filesystem watching unavailable: {}", e));
+                return;
+            }
+        };
+
+        for root in &self.roots {
+            if let Err(e) = watcher.watch(root, RecursiveMode::Recursive) {
+                log(Level::Warn, "LocalLibrary", format!("Failed to watch {}: {}", root.display(), e));
+            }
+        }
+
+        loop {
+            match rx.recv_timeout(PERSIST_INTERVAL) {
+                Ok(Ok(event)) => self.handle_event(event),
+                Ok(Err(e)) => log(Level::Debug, "LocalLibrary", format!("Watch error: {}", e)),
+                Err(mpsc::RecvTimeoutError::Timeout) => self.persist(),
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    }
+
+    fn handle_event(&self, event: notify::Event) {
+        match event.kind {
+            EventKind::Create(_) | EventKind::Modify(_) => {
+                for path in event.paths {
+                    if path.is_dir() {
+                        let mut files = Vec::new();
+                        collect_files(&path, &mut files);
+                        for file in files {
+                            self.index_file(&file);
+                        }
+                    } else if path.exists() {
+                        if is_audio_file(&path) {
+                            self.index_file(&path);
+                        }
+                    } else {
+                        self.remove_file(&path);
+                    }
+                }
+            },
+            EventKind::Remove(_) => {
+                for path in event.paths {
+                    self.remove_file(&path);
+                }
+            },
+            _ => {}
+        }
+    }
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(e) => e,
+        Err(_) => return,
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(file_type) = entry.file_type() else { continue };
+
+        if file_type.is_dir() {
+            collect_files(&path, files);
+        } else if file_type.is_file() && is_audio_file(&path) {
+            files.push(path);
+        }
+    }
+}
+
+fn is_audio_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| AudioContainer::from_extension(e) != AudioContainer::Unknown)
+}
+
+fn modified_time(path: &Path) -> u64 {
+    std::fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::encoding::{encode_track, DecodedInfo};
+
+    fn track(title: &str, author: &str, album: &str, isrc: Option<&str>) -> DecodedTrack {
+        let info = DecodedInfo {
+            title: title.to_string(),
+            author: author.to_string(),
+            length: 1000,
+            identifier: String::new(),
+            is_stream: false,
+            uri: None,
+            artwork_url: None,
+            isrc: isrc.map(str::to_string),
+            source_name: "local".to_string(),
+            position: 0,
+            source_data: Vec::new(),
+        };
+        DecodedTrack {
+            encoded: encode_track(&info),
+            info,
+            plugin_info: serde_json::json!({ "album": album }),
+            user_data: serde_json::json!({}),
+        }
+    }
+
+    fn library(index_path: Option<PathBuf>, tracks: Vec<(&str, DecodedTrack)>) -> Arc<LocalLibrary> {
+        let library = LocalLibrary::new(Vec::new(), index_path, String::new());
+        {
+            let mut entries = library.entries.write().unwrap();
+            for (path, mut track) in tracks {
+                track.info.identifier = path.to_string();
+                entries.insert(PathBuf::from(path), LibraryEntry::new(PathBuf::from(path), 0, track));
+            }
+        }
+        library
+    }
+
+    fn titles(tracks: Vec<DecodedTrack>) -> Vec<String> {
+        tracks.into_iter().map(|t| t.info.title).collect()
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("aelira-library-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn wav() -> Vec<u8> {
+        let samples = [0u8; 4800 * 4];
+        let mut data = Vec::new();
+        data.extend(b"RIFF");
+        data.extend((36 + samples.len() as u32).to_le_bytes());
+        data.extend(b"WAVEfmt ");
+        data.extend(16u32.to_le_bytes());
+        data.extend(1u16.to_le_bytes());
+        data.extend(2u16.to_le_bytes());
+        data.extend(48_000u32.to_le_bytes());
+        data.extend((48_000u32 * 4).to_le_bytes());
+        data.extend(4u16.to_le_bytes());
+        data.extend(16u16.to_le_bytes());
+        data.extend(b"data");
+        data.extend((samples.len() as u32).to_le_bytes());
+        data.extend(samples);
+        data
+    }
+
+    #[test]
+    fn weights_title_over_author_over_album() {
+        let library = library(None, vec![
+            ("/music/c.flac", track("Around", "Other", "Daft Album", None)),
+            ("/music/b.flac", track("Harder Better", "Daft Punk", "", None)),
+            ("/music/a.flac", track("Daft Song", "Someone", "", None)),
+        ]);
+
+        assert_eq!(titles(library.search("daft", 10)), vec!["Daft Song", "Harder Better", "Around"]);
+    }
+
+    #[test]
+    fn requires_every_query_token_to_match() {
+        let library = library(None, vec![
+            ("/music/a.flac", track("Daft Song", "Someone", "", None)),
+            ("/music/b.flac", track("Harder Better", "Daft Punk", "", None)),
+        ]);
+
+        assert_eq!(titles(library.search("daft punk", 10)), vec!["Harder Better"]);
+        assert!(library.search("daft zebra", 10).is_empty());
+        assert!(library.search("  ", 10).is_empty());
+    }
+
+    #[test]
+    fn scores_exact_matches_above_prefixes_and_typos() {
+        let library = library(None, vec![
+            ("/music/a.flac", track("Harderest", "", "", None)),
+            ("/music/b.flac", track("Hardr", "", "", None)),
+            ("/music/c.flac", track("Harder", "", "", None)),
+            ("/music/d.flac", track("Softer", "", "", None)),
+        ]);
+
+        assert_eq!(titles(library.search("harder", 10)), vec!["Harder", "Harderest", "Hardr"]);
+    }
+
+    #[test]
+    fn returns_isrc_matches_only() {
+        let library = library(None, vec![
+            ("/music/a.flac", track("USRC17607839 Remix", "", "", None)),
+            ("/music/b.flac", track("Original", "", "", Some("USRC17607839"))),
+        ]);
+
+        assert_eq!(titles(library.search(" usrc17607839 ", 10)), vec!["Original"]);
+    }
+
+    #[test]
+    fn breaks_ties_by_path_and_applies_limit() {
+        let library = library(None, vec![
+            ("/music/b/song.flac", track("Song", "Artist", "", None)),
+            ("/music/a/song.flac", track("Song", "Artist", "", None)),
+            ("/music/c/song.flac", track("Song", "Artist", "", None)),
+        ]);
+
+        let identifiers: Vec<String> = library.search("song", 2).into_iter().map(|t| t.info.identifier).collect();
+        assert_eq!(identifiers, vec!["/music/a/song.flac", "/music/b/song.flac"]);
+    }
+
+    #[test]
+    fn scan_indexes_audio_files_and_forgets_removed_ones() {
+        let dir = temp_dir("scan");
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+        std::fs::write(dir.join("first song.wav"), wav()).unwrap();
+        std::fs::write(dir.join("nested/second song.wav"), wav()).unwrap();
+        std::fs::write(dir.join("notes.txt"), b"not audio").unwrap();
+
+        let library = LocalLibrary::new(vec![dir.clone()], None, String::new());
+        library.scan();
+        assert_eq!(library.entries.read().unwrap().len(), 2);
+        assert_eq!(titles(library.search("second", 10)), vec!["second song.wav"]);
+        assert!(!library.index_file(&dir.join("first song.wav")));
+
+        std::fs::remove_file(dir.join("nested/second song.wav")).unwrap();
+        library.scan();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(library.entries.read().unwrap().len(), 1);
+        assert!(library.search("second", 10).is_empty());
+    }
+
+    #[test]
+    fn persisted_index_round_trips() {
+        let dir = temp_dir("index");
+        let index = dir.join("index.json");
+        let saved = library(Some(index.clone()), vec![
+            ("/music/a.flac", track("Daft Song", "Someone", "Discovery", Some("GBDUW0000059"))),
+            ("/music/b.flac", track("Harder Better", "Daft Punk", "", None)),
+        ]);
+        saved.persist();
+        assert!(!index.exists(), "an unchanged index is not written");
+
+        saved.dirty.store(true, Ordering::Release);
+        saved.persist();
+        let loaded = LocalLibrary::new(Vec::new(), Some(index.clone()), String::new());
+        loaded.load_persisted();
+
+        assert_eq!(loaded.entries.read().unwrap().len(), 2);
+        assert_eq!(titles(loaded.search("discovery", 10)), vec!["Daft Song"]);
+        assert_eq!(titles(loaded.search("gbduw0000059", 10)), vec!["Daft Song"]);
+        let track = loaded.search("harder", 10).remove(0);
+        assert_eq!(track.encoded, saved.search("harder", 10)[0].encoded);
+        assert_eq!(track.info.identifier, "/music/b.flac");
+
+        std::fs::write(&index, br#"{"version":0,"entries":[]}"#).unwrap();
+        let outdated = LocalLibrary::new(Vec::new(), Some(index), String::new());
+        outdated.load_persisted();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(outdated.entries.read().unwrap().is_empty());
+    }
+}
@@ -1,114 +1,520 @@
-use std::path::Path;
+pub mod library;
+pub mod sandbox;
+
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use tokio::fs::File;
-use tokio_util::codec::FramedRead;
+use symphonia::core::codecs::CODEC_TYPE_NULL;
+use symphonia::core::formats::FormatReader;
 use symphonia::core::io::MediaSourceStream;
-use symphonia::core::probe::Hint;
-use crate::playback::demuxers::webm::WebmOpusDemuxer;
-use crate::utils::encoding::{DecodedTrack, DecodedInfo, encode_track};
+use symphonia::core::meta::{StandardTagKey, StandardVisualKey, Tag, Visual};
+use symphonia::core::probe::{Hint, ProbeResult};
+use crate::config::LocalSourceConfig;
+use crate::playback::codecs::{AudioCodec, AudioContainer};
+use crate::playback::media::{MediaInfo, MediaStream};
+use crate::utils::encoding::{DecodedTrack, DecodedInfo, encode_source_text, encode_track};
 use crate::managers::sources::Source;
-use crate::models::load_tracks::{LoadTracksResponse, LoadType, LoadResultData};
+use crate::utils::{log, Level};
+use crate::models::load_tracks::{ErrorData, Severity, LoadTracksResponse, LoadType, LoadResultData, PlaylistData, PlaylistInfo};
 use async_trait::async_trait;
+use base64::{Engine as _, engine::general_purpose};
+use library::LocalLibrary;
+use sandbox::{LocalAccess, LocalSandbox};
 
-pub struct LocalSource;
+const MAX_DIRECTORY_TRACKS: usize = 1000;
+const DEFAULT_SEARCH_LIMIT: usize = 50;
 
-#[async_trait]
-impl Source for LocalSource {
-    fn name(&self) -> &'static str {
-        "local"
+pub struct LocalSource {
+    recursive: bool,
+    artwork_base_url: String,
+    library: Option<Arc<LocalLibrary>>,
+    search_limit: usize,
+    sandbox: Arc<LocalSandbox>,
+}
+
+struct LocalTrack {
+    track: DecodedTrack,
+    track_number: Option<u32>,
+}
+
+impl LocalSource {
+    pub fn new(config: LocalSourceConfig, public_url: &str, sandbox: Arc<LocalSandbox>) -> Self {
+        let artwork_base_url = format!("{}/v4/local/artwork", public_url.trim_end_matches('/'));
+
+        let roots: Vec<PathBuf> = config.library_roots.unwrap_or_default()
+            .into_iter()
+            .map(PathBuf::from)
+            .collect();
+
+        let library = if roots.is_empty() {
+            None
+        } else {
+            let library = LocalLibrary::new(roots, config.library_index.map(PathBuf::from), artwork_base_url.clone());
+            library.start(config.library_watch.unwrap_or(true));
+            Some(library)
+        };
+
+        Self {
+            recursive: config.recursive.unwrap_or(false),
+            artwork_base_url,
+            library,
+            search_limit: config.search_limit.unwrap_or(DEFAULT_SEARCH_LIMIT),
+            sandbox,
+        }
     }
 
-    fn priority(&self) -> u32 {
-        20
+    pub fn artwork_id(path: &Path) -> String {
+        general_purpose::URL_SAFE_NO_PAD.encode(path.to_string_lossy().as_bytes())
     }
 
-    fn search_terms(&self) -> Vec<&'static str> {
-        vec!["local", "file"]
+    pub fn artwork_path(id: &str) -> Option<PathBuf> {
+        let bytes = general_purpose::URL_SAFE_NO_PAD.decode(id).ok()?;
+        Some(PathBuf::from(String::from_utf8(bytes).ok()?))
     }
 
-    fn patterns(&self) -> Vec<&'static str> {
-        vec![r"^(local|file):"]
+    pub fn read_artwork(path: &Path) -> Option<(String, Vec<u8>)> {
+        let mut probed = Self::probe(path).ok()?;
+        let visual = Self::read_visual(&mut probed)?;
+        Some((visual.media_type, visual.data.into_vec()))
     }
 
-    async fn search(&self, query: &str, _search_type: &str) -> LoadTracksResponse {
-        self.resolve(query).await
+    pub fn clean_path(identifier: &str) -> &str {
+        identifier.strip_prefix("local:")
+            .or_else(|| identifier.strip_prefix("file:"))
+            .unwrap_or(identifier)
     }
 
-    async fn resolve(&self, url: &str) -> LoadTracksResponse {
-        let clean_path = if url.starts_with("local:") {
-            &url[6..]
-        } else if url.starts_with("file:") {
-            &url[5..]
-        } else {
-            url
-        };
-        
-        let path = Path::new(clean_path);
-        let file = match std::fs::File::open(path) {
-            Ok(f) => f,
-            Err(_) => return LoadTracksResponse {
-                load_type: LoadType::Empty,
-                data: LoadResultData::Empty(serde_json::json!({})),
-            }
-        };
+    fn empty() -> LoadTracksResponse {
+        LoadTracksResponse {
+            load_type: LoadType::Empty,
+            data: LoadResultData::Empty(serde_json::json!({})),
+        }
+    }
 
+    fn probe(path: &Path) -> Result<ProbeResult, ErrorData> {
+        let file = std::fs::File::open(path)
+            .map_err(|e| ErrorData::from_error(format!("Could not read {}", path.display()), Severity::Suspicious, &e))?;
         let mss = MediaSourceStream::new(Box::new(file), Default::default());
         let mut hint = Hint::new();
-        
+
         if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
             hint.with_extension(ext);
         }
 
-        let probed = match symphonia::default::get_probe()
-            .format(&hint, mss, &Default::default(), &Default::default()) {
-                Ok(p) => p,
-                Err(_) => return LoadTracksResponse {
-                    load_type: LoadType::Empty,
-                    data: LoadResultData::Empty(serde_json::json!({})),
-                }
-            };
+        let probed = symphonia::default::get_probe()
+            .format(&hint, mss, &Default::default(), &Default::default())
+            .map_err(|e| ErrorData::from_error("Unknown file format", Severity::Common, &e))?;
+
+        if !probed.format.tracks().iter().any(|t| t.codec_params.codec != CODEC_TYPE_NULL) {
+            return Err(ErrorData::new("Unknown file format", Severity::Common, "The file does not contain a supported audio track"));
+        }
+        Ok(probed)
+    }
 
-        let format = probed.format;
-        let duration = format.tracks().iter()
-            .filter_map(|t| t.codec_params.time_base.zip(t.codec_params.n_frames))
-            .map(|(tb, frames)| tb.calc_time(frames).seconds * 1000)
-            .next()
-            .unwrap_or(0);
+    fn read_tags(probed: &mut ProbeResult) -> Vec<Tag> {
+        let mut tags = Vec::new();
+        if let Some(metadata) = probed.metadata.get()
+            && let Some(rev) = metadata.current() {
+            tags.extend(rev.tags().iter().cloned());
+        }
+        if let Some(rev) = probed.format.metadata().current() {
+            tags.extend(rev.tags().iter().cloned());
+        }
+        tags
+    }
 
+    fn read_visual(probed: &mut ProbeResult) -> Option<Visual> {
+        let mut visuals = Vec::new();
+        if let Some(metadata) = probed.metadata.get()
+            && let Some(rev) = metadata.current() {
+            visuals.extend(rev.visuals().iter().cloned());
+        }
+        if let Some(rev) = probed.format.metadata().current() {
+            visuals.extend(rev.visuals().iter().cloned());
+        }
+
+        let front = visuals.iter().position(|v| v.usage == Some(StandardVisualKey::FrontCover));
+        match front {
+            Some(idx) => Some(visuals.swap_remove(idx)),
+            None => visuals.into_iter().next(),
+        }
+    }
+
+    fn media_info(path: &Path, format: &dyn FormatReader) -> MediaInfo {
+        let container = path.extension()
+            .and_then(|e| e.to_str())
+            .map(AudioContainer::from_extension)
+            .unwrap_or(AudioContainer::Unknown);
+
+        let track = format.tracks().iter().find(|t| t.codec_params.codec != CODEC_TYPE_NULL);
+        let codec = track.map(|t| AudioCodec::from_symphonia(t.codec_params.codec)).unwrap_or(AudioCodec::Unknown);
+
+        let mut info = MediaInfo::new(container, codec);
+        if let Some(track) = track {
+            info.sample_rate = track.codec_params.sample_rate;
+            info.duration = track.codec_params.time_base.zip(track.codec_params.n_frames)
+                .map(|(tb, frames)| {
+                    let time = tb.calc_time(frames);
+                    time.seconds * 1000 + (time.frac * 1000.0) as u64
+                });
+        }
+        info
+    }
+
+    fn load_file(path: &Path, artwork_base_url: &str) -> Result<LocalTrack, ErrorData> {
+        let mut probed = Self::probe(path)?;
+        let media = Self::media_info(path, probed.format.as_ref());
+        let tags = Self::read_tags(&mut probed);
+        let has_artwork = Self::read_visual(&mut probed).is_some();
+
+        let tag = |key: StandardTagKey| -> Option<String> {
+            tags.iter()
+                .find(|t| t.std_key == Some(key))
+                .map(|t| t.value.to_string().trim().to_string())
+                .filter(|v| !v.is_empty())
+        };
+
+        let track_number = tag(StandardTagKey::TrackNumber)
+            .and_then(|n| n.split('/').next().and_then(|n| n.trim().parse().ok()));
+
+        let year = tag(StandardTagKey::Date)
+            .or_else(|| tag(StandardTagKey::ReleaseDate))
+            .or_else(|| tag(StandardTagKey::OriginalDate))
+            .and_then(|d| d.get(0..4).and_then(|y| y.parse::<u32>().ok()));
+
+        let mut plugin_info = serde_json::Map::new();
+        if let Some(album) = tag(StandardTagKey::Album) {
+            plugin_info.insert("album".to_string(), album.into());
+        }
+        if let Some(genre) = tag(StandardTagKey::Genre) {
+            plugin_info.insert("genre".to_string(), genre.into());
+        }
+        if let Some(year) = year {
+            plugin_info.insert("year".to_string(), year.into());
+        }
+
+        let identifier = path.to_string_lossy().to_string();
         let info = DecodedInfo {
-            title: path.file_name().unwrap_or_default().to_string_lossy().to_string(),
-            author: "unknown".to_string(),
-            length: duration,
-            identifier: clean_path.to_string(),
+            title: tag(StandardTagKey::TrackTitle)
+                .unwrap_or_else(|| path.file_name().unwrap_or_default().to_string_lossy().to_string()),
+            author: tag(StandardTagKey::Artist)
+                .or_else(|| tag(StandardTagKey::AlbumArtist))
+                .unwrap_or_else(|| "unknown".to_string()),
+            length: media.duration.unwrap_or(0),
+            identifier: identifier.clone(),
             is_stream: false,
-            uri: Some(clean_path.to_string()),
-            artwork_url: None,
-            isrc: None,
+            uri: Some(identifier),
+            artwork_url: has_artwork.then(|| format!("{}/{}", artwork_base_url, Self::artwork_id(path))),
+            isrc: tag(StandardTagKey::IdentIsrc),
             source_name: "local".to_string(),
             position: 0,
+            source_data: encode_source_text(media.container.probe_name().unwrap_or("")),
         };
 
-        let track = DecodedTrack {
-            encoded: encode_track(&info),
-            info,
-            plugin_info: serde_json::json!({}),
-            user_data: serde_json::json!({}),
+        Ok(LocalTrack {
+            track: DecodedTrack {
+                encoded: encode_track(&info),
+                info,
+                plugin_info: serde_json::Value::Object(plugin_info),
+                user_data: serde_json::json!({}),
+            },
+            track_number,
+        })
+    }
+
+    fn load_directory(dir: &Path, recursive: bool, artwork_base_url: &str, sandbox: &LocalSandbox) -> LoadTracksResponse {
+        let files = Self::directory_files(dir, recursive);
+
+        let mut failure = None;
+        let mut tracks: Vec<(PathBuf, LocalTrack)> = files.into_iter()
+            .filter(|file| matches!(sandbox.check(file), LocalAccess::Allowed(_)))
+            .filter_map(|file| match Self::load_file(&file, artwork_base_url) {
+                Ok(track) => Some((file, track)),
+                Err(e) => {
+                    failure.get_or_insert(e);
+                    None
+                }
+            })
+            .collect();
+
+        tracks.sort_by(|(a_path, a), (b_path, b)| track_order(dir, (a_path, a.track_number), (b_path, b.track_number)));
+
+        if tracks.is_empty() {
+            return match failure {
+                Some(e) => LoadTracksResponse::error(ErrorData {
+                    message: format!("None of the files in {} could be loaded", dir.display()),
+                    ..e
+                }),
+                None => Self::empty(),
+            };
+        }
+
+        let name = dir.file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| dir.to_string_lossy().to_string());
+
+        LoadTracksResponse {
+            load_type: LoadType::Playlist,
+            data: LoadResultData::Playlist(PlaylistData {
+                info: PlaylistInfo {
+                    name,
+                    selected_track: -1,
+                },
+                plugin_info: serde_json::json!({}),
+                tracks: tracks.into_iter().map(|(_, local)| local.track).collect(),
+            }),
+        }
+    }
+
+    /// The first `MAX_DIRECTORY_TRACKS` audio files under `dir` in path order. Listing order is
+    /// arbitrary, so everything is listed and sorted before the cap applies.
+    fn directory_files(dir: &Path, recursive: bool) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        Self::collect_audio_files(dir, recursive, &mut files);
+        files.sort_by(|a, b| track_order(dir, (a, None), (b, None)));
+        files.truncate(MAX_DIRECTORY_TRACKS);
+        files
+    }
+
+    fn collect_audio_files(dir: &Path, recursive: bool, files: &mut Vec<PathBuf>) {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(e) => e,
+            Err(_) => return,
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(file_type) = entry.file_type() else { continue };
+
+            if file_type.is_dir() {
+                if recursive {
+                    Self::collect_audio_files(&path, recursive, files);
+                }
+            } else if path.extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| AudioContainer::from_extension(e) != AudioContainer::Unknown) {
+                files.push(path);
+            }
+        }
+    }
+}
+
+/// Orders directory listings by folder, then by track number with untagged files after tagged
+/// ones, then naturally by path. This has to stay a total order or `sort_by` may panic.
+fn track_order(dir: &Path, (a_path, a_number): (&Path, Option<u32>), (b_path, b_number): (&Path, Option<u32>)) -> Ordering {
+    natural_cmp(&a_path.parent().unwrap_or(dir).to_string_lossy(), &b_path.parent().unwrap_or(dir).to_string_lossy())
+        .then_with(|| a_number.is_none().cmp(&b_number.is_none()))
+        .then_with(|| a_number.cmp(&b_number))
+        .then_with(|| natural_cmp(&a_path.to_string_lossy(), &b_path.to_string_lossy()))
+}
+
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+
+    loop {
+        match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let x_num = take_digits(&mut a_chars);
+                let y_num = take_digits(&mut b_chars);
+                let x_trim = x_num.trim_start_matches('0');
+                let y_trim = y_num.trim_start_matches('0');
+
+                let ord = x_trim.len().cmp(&y_trim.len())
+                    .then_with(|| x_trim.cmp(y_trim))
+                    .then_with(|| x_num.len().cmp(&y_num.len()));
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            },
+            (Some(x), Some(y)) => {
+                let ord = x.to_lowercase().cmp(y.to_lowercase());
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+                a_chars.next();
+                b_chars.next();
+            }
+        }
+    }
+}
+
+fn take_digits(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.peek().copied().filter(char::is_ascii_digit) {
+        digits.push(c);
+        chars.next();
+    }
+    digits
+}
+
+#[async_trait]
+impl Source for LocalSource {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn priority(&self) -> u32 {
+        20
+    }
+
+    fn search_terms(&self) -> Vec<&'static str> {
+        vec!["local", "file"]
+    }
+
+    async fn search(&self, query: &str, _search_type: &str) -> LoadTracksResponse {
+        let path = Path::new(Self::clean_path(query));
+        let library = match (&self.library, self.sandbox.check(path)) {
+            (_, LocalAccess::Allowed(_)) => return self.resolve(query).await,
+            (_, LocalAccess::Denied) if path.is_absolute() => return sandbox::denied(Self::clean_path(query)),
+            (Some(library), _) => library.clone(),
+            (None, _) => return self.resolve(query).await,
         };
 
+        let query = query.to_string();
+        let limit = self.search_limit;
+        let tracks = tokio::task::spawn_blocking(move || library.search(&query, limit))
+            .await
+            .unwrap_or_default();
+
+        if tracks.is_empty() {
+            return Self::empty();
+        }
+
         LoadTracksResponse {
-            load_type: LoadType::Track,
-            data: LoadResultData::Track(track),
+            load_type: LoadType::Search,
+            data: LoadResultData::Search(tracks),
         }
     }
 
-    async fn load_stream(&self, identifier: &str) -> Option<FramedRead<File, WebmOpusDemuxer>> {
-        let clean_path = if identifier.starts_with("local:") {
-            &identifier[6..]
-        } else if identifier.starts_with("file:") {
-            &identifier[5..]
-        } else {
-            identifier
+    async fn resolve(&self, url: &str) -> LoadTracksResponse {
+        let path = match self.sandbox.check(Path::new(Self::clean_path(url))) {
+            LocalAccess::Allowed(path) => path,
+            LocalAccess::Missing => return Self::empty(),
+            LocalAccess::Denied => return sandbox::denied(Self::clean_path(url)),
         };
-        let file = File::open(clean_path).await.ok()?;
-        Some(FramedRead::new(file, WebmOpusDemuxer::new()))
+
+        let artwork_base_url = self.artwork_base_url.clone();
+
+        if path.is_dir() {
+            let recursive = self.recursive;
+            let sandbox = self.sandbox.clone();
+            return tokio::task::spawn_blocking(move || Self::load_directory(&path, recursive, &artwork_base_url, &sandbox))
+                .await
+                .unwrap_or_else(|e| LoadTracksResponse::error(ErrorData::from_error("Failed to read the directory", Severity::Fault, &e)));
+        }
+
+        match tokio::task::spawn_blocking(move || Self::load_file(&path, &artwork_base_url)).await {
+            Ok(Ok(local)) => LoadTracksResponse {
+                load_type: LoadType::Track,
+                data: LoadResultData::Track(Box::new(local.track)),
+            },
+            Ok(Err(e)) => LoadTracksResponse::error(e),
+            Err(e) => LoadTracksResponse::error(ErrorData::from_error("Failed to read the file", Severity::Fault, &e)),
+        }
+    }
+
+    async fn load_stream(&self, track: &DecodedInfo) -> Result<MediaStream, ErrorData> {
+        let clean_path = match self.sandbox.check(Path::new(Self::clean_path(&track.identifier))) {
+            LocalAccess::Allowed(path) => path,
+            LocalAccess::Missing => {
+                return Err(ErrorData::new("The file no longer exists", Severity::Common, format!("{} was not found", track.identifier)));
+            },
+            LocalAccess::Denied => {
+                log(Level::Warn, "LocalSource", format!("Refusing to stream {} outside of allowed_roots", track.identifier));
+                return Err(ErrorData::new("The file is not accessible", Severity::Common, "The path is outside of the configured allowed_roots"));
+            }
+        };
+
+        let probe_path = clean_path.clone();
+        let info = tokio::task::spawn_blocking(move || {
+            Self::probe(&probe_path).map(|probed| Self::media_info(&probe_path, probed.format.as_ref()))
+        })
+            .await
+            .map_err(|e| ErrorData::from_error("Failed to probe the file", Severity::Fault, &e))??;
+
+        let file = File::open(&clean_path).await
+            .map_err(|e| ErrorData::from_error(format!("Could not read {}", clean_path.display()), Severity::Suspicious, &e))?;
+        Ok(MediaStream::seekable(file, info))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(dir: &Path, mut files: Vec<(PathBuf, Option<u32>)>) -> Vec<String> {
+        files.sort_by(|(a_path, a), (b_path, b)| track_order(dir, (a_path, *a), (b_path, *b)));
+        files.into_iter().map(|(path, _)| path.to_string_lossy().to_string()).collect()
+    }
+
+    #[test]
+    fn sorts_mixed_tagged_and_untagged_files() {
+        let dir = Path::new("/music/album");
+        let files = vec![
+            (dir.join("b.mp3"), None),
+            (dir.join("track 10.flac"), Some(1)),
+            (dir.join("a.mp3"), None),
+            (dir.join("track 2.flac"), Some(3)),
+            (dir.join("cd2/01.flac"), Some(1)),
+            (dir.join("track 1.flac"), Some(2)),
+            (dir.join("intro.ogg"), Some(2)),
+            (dir.join("cd2/extra.flac"), None),
+        ];
+
+        assert_eq!(sorted(dir, files), vec![
+            "/music/album/track 10.flac",
+            "/music/album/intro.ogg",
+            "/music/album/track 1.flac",
+            "/music/album/track 2.flac",
+            "/music/album/a.mp3",
+            "/music/album/b.mp3",
+            "/music/album/cd2/01.flac",
+            "/music/album/cd2/extra.flac",
+        ]);
+    }
+
+    #[test]
+    fn sort_is_independent_of_listing_order() {
+        let dir = Path::new("/music");
+        let files: Vec<(PathBuf, Option<u32>)> = (0..40u32)
+            .map(|i| (dir.join(format!("{}/{:02} song.mp3", i % 3, (i * 7) % 40)), (i % 4 != 0).then_some(i % 9)))
+            .collect();
+        let expected = sorted(dir, files.clone());
+
+        let mut seed = 0x2545_f491_u32;
+        for _ in 0..200 {
+            let mut shuffled = files.clone();
+            for i in (1..shuffled.len()).rev() {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                shuffled.swap(i, seed as usize % (i + 1));
+            }
+            assert_eq!(sorted(dir, shuffled), expected);
+        }
+    }
+
+    #[test]
+    fn caps_directories_at_the_first_files_in_path_order() {
+        let dir = std::env::temp_dir().join(format!("aelira-local-cap-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("disc 2")).unwrap();
+        for i in (1..=MAX_DIRECTORY_TRACKS + 5).rev() {
+            std::fs::write(dir.join(format!("track {}.mp3", i)), b"").unwrap();
+        }
+        std::fs::write(dir.join("disc 2/track 1.mp3"), b"").unwrap();
+        std::fs::write(dir.join("cover.jpg"), b"").unwrap();
+
+        let files = LocalSource::directory_files(&dir, true);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(files.len(), MAX_DIRECTORY_TRACKS);
+        assert_eq!(files[0], dir.join("track 1.mp3"));
+        assert_eq!(files[9], dir.join("track 10.mp3"));
+        assert_eq!(files[MAX_DIRECTORY_TRACKS - 1], dir.join(format!("track {}.mp3", MAX_DIRECTORY_TRACKS)));
     }
-}
\ No newline at end of file
+}
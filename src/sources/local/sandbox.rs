@@ -0,0 +1,247 @@
+use std::path::{Component, Path, PathBuf};
+use crate::models::load_tracks::{ErrorData, LoadTracksResponse, Severity};
+use crate::utils::{log, Level};
+
+pub enum LocalAccess {
+    Allowed(PathBuf),
+    Missing,
+    Denied,
+}
+
+pub struct LocalSandbox {
+    roots: Vec<PathBuf>,
+}
+
+impl LocalSandbox {
+    pub fn new(roots: &[String]) -> Self {
+        let roots: Vec<PathBuf> = roots.iter()
+            .filter(|r| !r.trim().is_empty())
+            .map(|root| match std::fs::canonicalize(root) {
+                Ok(path) => path,
+                Err(e) => {
+                    log(Level::Warn, "LocalSandbox", format!("Allowed root {} is not accessible: {}", root, e));
+                    normalize(Path::new(root))
+                }
+            })
+            .collect();
+
+        if roots.is_empty() {
+            log(Level::Warn, "LocalSandbox", "No allowed_roots configured, local files cannot be loaded");
+        }
+
+        Self { roots }
+    }
+
+    pub fn check(&self, path: &Path) -> LocalAccess {
+        match std::fs::canonicalize(path) {
+            Ok(real) if self.contains(&real) => LocalAccess::Allowed(real),
+            Ok(_) => LocalAccess::Denied,
+            Err(_) if self.contains(&resolve_missing(path)) => LocalAccess::Missing,
+            Err(_) => LocalAccess::Denied,
+        }
+    }
+
+    fn contains(&self, path: &Path) -> bool {
+        self.roots.iter().any(|root| path.starts_with(root))
+    }
+}
+
+fn normalize(path: &Path) -> PathBuf {
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut normalized = PathBuf::new();
+
+    for component in absolute.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            },
+            Component::CurDir => {},
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// Resolves a path that doesn't exist through its nearest existing ancestor, so a missing file
+/// under a symlinked root or directory is judged by where it would really be.
+fn resolve_missing(path: &Path) -> PathBuf {
+    let normalized = normalize(path);
+    normalized.ancestors()
+        .skip(1)
+        .find_map(|ancestor| {
+            let real = std::fs::canonicalize(ancestor).ok()?;
+            Some(real.join(normalized.strip_prefix(ancestor).ok()?))
+        })
+        .unwrap_or(normalized)
+}
+
+pub fn is_explicit_path(identifier: &str) -> bool {
+    identifier.starts_with("local:")
+        || identifier.starts_with("file:")
+        || identifier.starts_with("./")
+        || identifier.starts_with("../")
+        || Path::new(identifier).is_absolute()
+}
+
+pub fn denied(path: &str) -> LoadTracksResponse {
+    load_failed(
+        format!("Access to {} is not allowed", path),
+        "The path is outside of the configured allowed_roots",
+    )
+}
+
+pub fn disabled() -> LoadTracksResponse {
+    load_failed(
+        "Local file access is disabled".to_string(),
+        "The local source is disabled in the configuration",
+    )
+}
+
+fn load_failed(message: String, cause: &str) -> LoadTracksResponse {
+    LoadTracksResponse::error(ErrorData::new(message, Severity::Common, cause))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    /// A music root and a sibling directory outside of it, removed when dropped.
+    struct Fixture {
+        dir: PathBuf,
+        root: PathBuf,
+        outside: PathBuf,
+    }
+
+    impl Fixture {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("aelira-sandbox-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            let root = dir.join("music");
+            let outside = dir.join("private");
+            std::fs::create_dir_all(root.join("album")).unwrap();
+            std::fs::create_dir_all(&outside).unwrap();
+            std::fs::write(root.join("album/song.mp3"), b"").unwrap();
+            std::fs::write(outside.join("secret.mp3"), b"").unwrap();
+            Self { dir, root, outside }
+        }
+
+        fn sandbox(&self) -> LocalSandbox {
+            LocalSandbox::new(&[self.root.to_string_lossy().to_string()])
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn access(sandbox: &LocalSandbox, path: &Path) -> &'static str {
+        match sandbox.check(path) {
+            LocalAccess::Allowed(_) => "allowed",
+            LocalAccess::Missing => "missing",
+            LocalAccess::Denied => "denied",
+        }
+    }
+
+    #[test]
+    fn allows_files_under_a_root() {
+        let fixture = Fixture::new("allowed");
+        let sandbox = fixture.sandbox();
+
+        let path = fixture.root.join("album/./song.mp3");
+        assert!(matches!(sandbox.check(&path), LocalAccess::Allowed(real) if real == fixture.root.join("album/song.mp3")));
+        assert_eq!(access(&sandbox, &fixture.root.join("album/other.mp3")), "missing");
+    }
+
+    #[test]
+    fn denies_traversal_out_of_a_root() {
+        let fixture = Fixture::new("traversal");
+        let sandbox = fixture.sandbox();
+
+        let cases = [
+            "album/../../private/secret.mp3",
+            "../private/secret.mp3",
+            "album/../../private/missing.mp3",
+            "../../../../../../etc/passwd",
+            "album/missing/../../../private",
+        ];
+        for case in cases {
+            assert_eq!(access(&sandbox, &fixture.root.join(case)), "denied", "{}", case);
+        }
+        assert_eq!(access(&sandbox, &fixture.root.join("album/../album/song.mp3")), "allowed");
+    }
+
+    #[test]
+    fn denies_symlinks_leaving_a_root() {
+        let fixture = Fixture::new("symlink");
+        symlink(fixture.outside.join("secret.mp3"), fixture.root.join("album/link.mp3")).unwrap();
+        symlink(&fixture.outside, fixture.root.join("private")).unwrap();
+        symlink(fixture.root.join("album/song.mp3"), fixture.root.join("inside.mp3")).unwrap();
+        let sandbox = fixture.sandbox();
+
+        assert_eq!(access(&sandbox, &fixture.root.join("album/link.mp3")), "denied");
+        assert_eq!(access(&sandbox, &fixture.root.join("private/secret.mp3")), "denied");
+        assert_eq!(access(&sandbox, &fixture.root.join("private/missing.mp3")), "denied");
+        assert_eq!(access(&sandbox, &fixture.root.join("inside.mp3")), "allowed");
+    }
+
+    #[test]
+    fn reports_missing_and_existing_files_outside_roots_alike() {
+        let fixture = Fixture::new("probe");
+        let sandbox = fixture.sandbox();
+
+        let existing = fixture.outside.join("secret.mp3");
+        let missing = fixture.outside.join("missing.mp3");
+        assert_eq!(access(&sandbox, &existing), "denied");
+        assert_eq!(access(&sandbox, &missing), "denied");
+        assert_eq!(access(&sandbox, Path::new("/etc/passwd")), "denied");
+        assert_eq!(access(&sandbox, Path::new("/etc/no-such-file")), "denied");
+
+        let body = |path: &Path| serde_json::to_value(denied(&path.to_string_lossy())).unwrap();
+        let (existing, missing) = (body(&existing), body(&missing));
+        assert_eq!(existing["loadType"], "error");
+        assert_eq!(existing["data"]["cause"], missing["data"]["cause"]);
+        assert_eq!(existing["data"]["severity"], missing["data"]["severity"]);
+    }
+
+    #[test]
+    fn resolves_roots_that_are_symlinks() {
+        let fixture = Fixture::new("linked-root");
+        let linked = fixture.dir.join("linked");
+        symlink(&fixture.root, &linked).unwrap();
+        let sandbox = LocalSandbox::new(&[linked.to_string_lossy().to_string()]);
+
+        assert_eq!(access(&sandbox, &linked.join("album/song.mp3")), "allowed");
+        assert_eq!(access(&sandbox, &fixture.root.join("album/song.mp3")), "allowed");
+        assert_eq!(access(&sandbox, &linked.join("album/missing.mp3")), "missing");
+        assert_eq!(access(&sandbox, &linked.join("../private/secret.mp3")), "denied");
+    }
+
+    #[test]
+    fn denies_everything_without_roots() {
+        let fixture = Fixture::new("no-roots");
+        let sandbox = LocalSandbox::new(&["  ".to_string()]);
+
+        assert_eq!(access(&sandbox, &fixture.root.join("album/song.mp3")), "denied");
+        assert_eq!(access(&sandbox, &fixture.root.join("album/missing.mp3")), "denied");
+    }
+
+    #[test]
+    fn recognizes_explicit_paths() {
+        let cases = [
+            ("/etc/passwd", true),
+            ("local:music/song.mp3", true),
+            ("file:/music/song.mp3", true),
+            ("./song.mp3", true),
+            ("../song.mp3", true),
+            ("song.mp3", false),
+            ("never gonna give you up", false),
+            ("https://example.com/song.mp3", false),
+        ];
+        for (identifier, explicit) in cases {
+            assert_eq!(is_explicit_path(identifier), explicit, "{}", identifier);
+        }
+    }
+}
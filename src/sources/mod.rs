@@ -1 +1,5 @@
+pub mod external;
+pub mod hls;
+pub mod http;
 pub mod local;
+pub mod playlist;
@@ -0,0 +1,506 @@
+use std::path::Path;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use crate::utils::encoding::{DecodedTrack, encode_track};
+use crate::utils::http::{client, is_http_url};
+
+const MAX_PLAYLIST_SIZE: usize = 4 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlaylistFormat {
+    M3u,
+    Pls,
+    Xspf,
+}
+
+#[derive(Default)]
+pub struct PlaylistEntry {
+    pub location: String,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub length: Option<u64>,
+}
+
+pub struct ParsedPlaylist {
+    pub name: Option<String>,
+    pub entries: Vec<PlaylistEntry>,
+}
+
+impl PlaylistFormat {
+    pub fn detect(identifier: &str) -> Option<Self> {
+        let path = strip_scheme(identifier);
+        let path = path.split(['?', '#']).next().unwrap_or(path);
+        let ext = Path::new(path).extension()?.to_str()?.to_ascii_lowercase();
+
+        match ext.as_str() {
+            "m3u" | "m3u8" => Some(PlaylistFormat::M3u),
+            "pls" => Some(PlaylistFormat::Pls),
+            "xspf" => Some(PlaylistFormat::Xspf),
+            _ => None,
+        }
+    }
+}
+
+impl PlaylistEntry {
+    pub fn apply(&self, track: &mut DecodedTrack) {
+        let mut changed = false;
+
+        if track.info.author == "unknown" {
+            if let Some(author) = &self.author {
+                track.info.author = author.clone();
+                changed = true;
+            }
+            if let Some(title) = &self.title {
+                track.info.title = title.clone();
+                changed = true;
+            }
+        }
+
+        if track.info.length == 0
+            && let Some(length) = self.length {
+            track.info.length = length;
+            changed = true;
+        }
+
+        if changed {
+            track.encoded = encode_track(&track.info);
+        }
+    }
+}
+
+/// Splits a `#N` suffix, which selects the Nth entry counting from 1, off a playlist identifier.
+pub fn split_selection(identifier: &str) -> (&str, Option<usize>) {
+    match identifier.rsplit_once('#') {
+        Some((base, number)) if !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit()) => {
+            (base, number.parse::<usize>().ok().and_then(|n| n.checked_sub(1)))
+        },
+        _ => (identifier, None),
+    }
+}
+
+fn strip_scheme(identifier: &str) -> &str {
+    identifier.strip_prefix("local:")
+        .or_else(|| identifier.strip_prefix("file:"))
+        .unwrap_or(identifier)
+}
+
+pub async fn fetch(identifier: &str) -> Result<String, String> {
+    if is_http_url(identifier) {
+        let response = client().get(identifier).send().await
+            .map_err(|e| format!("Failed to fetch playlist: {}", e))?;
+
+        if !response.status().is_success() {
+            return Err(format!("Playlist server responded with {}", response.status()));
+        }
+        if response.content_length().is_some_and(|len| len as usize > MAX_PLAYLIST_SIZE) {
+            return Err("Playlist is too large".to_string());
+        }
+
+        let bytes = response.bytes().await.map_err(|e| format!("Failed to read playlist: {}", e))?;
+        if bytes.len() > MAX_PLAYLIST_SIZE {
+            return Err("Playlist is too large".to_string());
+        }
+        return Ok(String::from_utf8_lossy(&bytes).into_owned());
+    }
+
+    let path = strip_scheme(identifier);
+    let metadata = tokio::fs::metadata(path).await.map_err(|e| format!("Failed to open playlist: {}", e))?;
+    if metadata.len() as usize > MAX_PLAYLIST_SIZE {
+        return Err("Playlist is too large".to_string());
+    }
+
+    let bytes = tokio::fs::read(path).await.map_err(|e| format!("Failed to read playlist: {}", e))?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+pub fn parse(format: PlaylistFormat, content: &str) -> Option<ParsedPlaylist> {
+    let content = content.trim_start_matches('\u{feff}');
+    match format {
+        PlaylistFormat::M3u => parse_m3u(content),
+        PlaylistFormat::Pls => parse_pls(content),
+        PlaylistFormat::Xspf => parse_xspf(content),
+    }
+}
+
+pub fn is_hls(content: &str) -> bool {
+    content.lines().any(|line| {
+        let line = line.trim();
+        line.starts_with("#EXT-X-TARGETDURATION") || line.starts_with("#EXT-X-STREAM-INF") || line.starts_with("#EXT-X-MEDIA-SEQUENCE")
+    })
+}
+
+fn parse_m3u(content: &str) -> Option<ParsedPlaylist> {
+    if is_hls(content) {
+        return None;
+    }
+
+    let mut name = None;
+    let mut entries = Vec::new();
+    let mut pending = PlaylistEntry::default();
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(value) = line.strip_prefix("#PLAYLIST:") {
+            let value = value.trim();
+            if !value.is_empty() {
+                name = Some(value.to_string());
+            }
+        } else if let Some(value) = line.strip_prefix("#EXTINF:") {
+            pending = parse_extinf(value);
+        } else if line.starts_with('#') {
+            continue;
+        } else {
+            pending.location = line.to_string();
+            entries.push(std::mem::take(&mut pending));
+        }
+    }
+
+    Some(ParsedPlaylist { name, entries })
+}
+
+fn parse_extinf(value: &str) -> PlaylistEntry {
+    let mut in_quotes = false;
+    let split = value.char_indices().find(|&(_, c)| {
+        if c == '"' {
+            in_quotes = !in_quotes;
+        }
+        c == ',' && !in_quotes
+    });
+
+    let (head, display) = match split {
+        Some((idx, _)) => (&value[..idx], value[idx + 1..].trim()),
+        None => (value, ""),
+    };
+
+    let length = head.split_whitespace().next()
+        .and_then(|d| d.parse::<f64>().ok())
+        .filter(|d| *d > 0.0)
+        .map(|d| (d * 1000.0) as u64);
+
+    let (author, title) = split_display_title(display);
+    PlaylistEntry {
+        location: String::new(),
+        title,
+        author,
+        length,
+    }
+}
+
+fn split_display_title(display: &str) -> (Option<String>, Option<String>) {
+    if display.is_empty() {
+        return (None, None);
+    }
+
+    match display.split_once(" - ") {
+        Some((author, title)) => (Some(author.trim().to_string()), Some(title.trim().to_string())),
+        None => (None, Some(display.to_string())),
+    }
+}
+
+fn parse_pls(content: &str) -> Option<ParsedPlaylist> {
+    let mut entries: Vec<(u32, PlaylistEntry)> = Vec::new();
+    let mut seen_header = false;
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.eq_ignore_ascii_case("[playlist]") {
+            seen_header = true;
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else { continue };
+        let key = key.trim().to_ascii_lowercase();
+        let value = value.trim();
+
+        let (field, index) = match ["file", "title", "length"].iter().find(|f| key.starts_with(**f)) {
+            Some(field) => match key[field.len()..].parse::<u32>() {
+                Ok(index) => (*field, index),
+                Err(_) => continue,
+            },
+            None => continue,
+        };
+
+        let entry = match entries.iter().position(|(i, _)| *i == index) {
+            Some(pos) => &mut entries[pos].1,
+            None => {
+                entries.push((index, PlaylistEntry::default()));
+                &mut entries.last_mut().unwrap().1
+            }
+        };
+
+        match field {
+            "file" => entry.location = value.to_string(),
+            "title" => {
+                let (author, title) = split_display_title(value);
+                entry.author = author;
+                entry.title = title;
+            },
+            _ => {
+                entry.length = value.parse::<i64>().ok()
+                    .filter(|l| *l > 0)
+                    .map(|l| l as u64 * 1000);
+            }
+        }
+    }
+
+    if !seen_header {
+        return None;
+    }
+
+    entries.sort_by_key(|(index, _)| *index);
+    Some(ParsedPlaylist {
+        name: None,
+        entries: entries.into_iter()
+            .map(|(_, entry)| entry)
+            .filter(|entry| !entry.location.is_empty())
+            .collect(),
+    })
+}
+
+fn parse_xspf(content: &str) -> Option<ParsedPlaylist> {
+    let mut reader = Reader::from_str(content);
+    let mut path: Vec<String> = Vec::new();
+    let mut text = String::new();
+    let mut name = None;
+    let mut entries = Vec::new();
+    let mut current: Option<PlaylistEntry> = None;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) => {
+                let tag = String::from_utf8_lossy(e.local_name().as_ref()).into_owned();
+                if tag == "track" {
+                    current = Some(PlaylistEntry::default());
+                }
+                path.push(tag);
+                text.clear();
+            },
+            Ok(Event::Text(e)) => {
+                if let Ok(t) = e.decode() {
+                    text.push_str(&t);
+                }
+            },
+            Ok(Event::CData(e)) => {
+                if let Ok(t) = e.decode() {
+                    text.push_str(&t);
+                }
+            },
+            Ok(Event::GeneralRef(e)) => {
+                if let Ok(Some(c)) = e.resolve_char_ref() {
+                    text.push(c);
+                } else if let Ok(entity) = e.decode()
+                    && let Some(resolved) = quick_xml::escape::resolve_xml_entity(&entity) {
+                    text.push_str(resolved);
+                }
+            },
+            Ok(Event::End(_)) => {
+                let value = std::mem::take(&mut text).trim().to_string();
+                let tag = path.pop();
+                let parent = path.last().map(String::as_str);
+
+                match (parent, tag.as_deref()) {
+                    (Some("playlist"), Some("title")) => name = Some(value),
+                    (_, Some("track")) => {
+                        if let Some(entry) = current.take()
+                            && !entry.location.is_empty() {
+                            entries.push(entry);
+                        }
+                    },
+                    (Some("track"), Some(field)) => {
+                        if let Some(entry) = current.as_mut() {
+                            match field {
+                                "location" if entry.location.is_empty() => entry.location = value,
+                                "title" => entry.title = Some(value),
+                                "creator" => entry.author = Some(value),
+                                "duration" => entry.length = value.parse().ok(),
+                                _ => {}
+                            }
+                        }
+                    },
+                    _ => {}
+                }
+            },
+            Ok(Event::Eof) => break,
+            Err(_) => return None,
+            _ => {}
+        }
+    }
+
+    Some(ParsedPlaylist { name, entries })
+}
+
+pub fn resolve_location(base: &str, location: &str) -> String {
+    if location.starts_with("file://")
+        && let Ok(url) = url::Url::parse(location)
+        && let Ok(path) = url.to_file_path() {
+        return path.to_string_lossy().into_owned();
+    }
+
+    if location.contains("://") || location.starts_with("local:") || location.starts_with("file:") {
+        return location.to_string();
+    }
+
+    if is_http_url(base) {
+        return url::Url::parse(base)
+            .and_then(|b| b.join(location))
+            .map(|u| u.to_string())
+            .unwrap_or_else(|_| location.to_string());
+    }
+
+    let location_path = Path::new(location);
+    if location_path.is_absolute() {
+        return location.to_string();
+    }
+
+    match Path::new(strip_scheme(base)).parent() {
+        Some(dir) => dir.join(location_path).to_string_lossy().into_owned(),
+        None => location.to_string(),
+    }
+}
+
+pub fn default_name(identifier: &str) -> String {
+    let path = strip_scheme(identifier);
+    let path = path.split(['?', '#']).next().unwrap_or(path);
+    Path::new(path).file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "Playlist".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Expected<'a> = (&'a str, Option<&'a str>, Option<&'a str>, Option<u64>);
+
+    fn check(format: PlaylistFormat, content: &str, name: Option<&str>, expected: &[Expected<'_>]) {
+        let parsed = parse(format, content).unwrap_or_else(|| panic!("{:?} failed to parse {:?}", format, content));
+        assert_eq!(parsed.name.as_deref(), name, "{:?}", content);
+
+        let entries: Vec<Expected<'_>> = parsed.entries.iter()
+            .map(|e| (e.location.as_str(), e.author.as_deref(), e.title.as_deref(), e.length))
+            .collect();
+        assert_eq!(entries, expected, "{:?}", content);
+    }
+
+    #[test]
+    fn parses_m3u() {
+        let cases: &[(&str, Option<&str>, &[Expected<'_>])] = &[
+            ("a.mp3\nb.mp3\n", None, &[("a.mp3", None, None, None), ("b.mp3", None, None, None)]),
+            (
+                "\u{feff}#EXTM3U\r\n#PLAYLIST:Road Trip\r\n#EXTINF:123,Artist - Song\r\nmusic/song.mp3\r\n\r\n#EXTINF:-1,Live Radio\r\nhttp://radio.example/stream\r\n",
+                Some("Road Trip"),
+                &[
+                    ("music/song.mp3", Some("Artist"), Some("Song"), Some(123_000)),
+                    ("http://radio.example/stream", None, Some("Live Radio"), None),
+                ],
+            ),
+            (
+                "#EXTM3U\n#EXTINF:5 tvg-name=\"a,b\",Title, with comma\n../up.flac\n# comment\n",
+                None,
+                &[("../up.flac", None, Some("Title, with comma"), Some(5_000))],
+            ),
+        ];
+
+        for (content, name, expected) in cases {
+            check(PlaylistFormat::M3u, content, *name, expected);
+        }
+    }
+
+    #[test]
+    fn rejects_hls_as_m3u() {
+        assert!(parse(PlaylistFormat::M3u, "#EXTM3U\n#EXT-X-TARGETDURATION:6\n#EXTINF:6,\nseg0.ts\n").is_none());
+    }
+
+    #[test]
+    fn parses_pls() {
+        let cases: &[(&str, &[Expected<'_>])] = &[
+            (
+                "[playlist]\nFile2=b.ogg\nFile1=a.ogg\nTitle1=Band - First\nLength1=61\nLength2=-1\nNumberOfEntries=2\n",
+                &[("a.ogg", Some("Band"), Some("First"), Some(61_000)), ("b.ogg", None, None, None)],
+            ),
+            (
+                "\u{feff}[Playlist]\r\nfile1=http://radio.example:8000/live\r\ntitle1=Radio\r\nTitle3=Orphan\r\n",
+                &[("http://radio.example:8000/live", None, Some("Radio"), None)],
+            ),
+        ];
+
+        for (content, expected) in cases {
+            check(PlaylistFormat::Pls, content, None, expected);
+        }
+        assert!(parse(PlaylistFormat::Pls, "File1=a.ogg\n").is_none());
+    }
+
+    #[test]
+    fn parses_xspf() {
+        let cases: &[(&str, Option<&str>, &[Expected<'_>])] = &[
+            (
+                r#"<?xml version="1.0" encoding="UTF-8"?>
+<playlist version="1" xmlns="http://xspf.org/ns/0/">
+  <title>Mix &amp; Match</title>
+  <trackList>
+    <track>
+      <location>songs/one.flac</location>
+      <location>ignored.flac</location>
+      <title>One</title>
+      <creator>Someone</creator>
+      <duration>200500</duration>
+    </track>
+    <track><title>No location</title></track>
+    <track><location><![CDATA[file:///music/two%20words.mp3]]></location></track>
+  </trackList>
+</playlist>"#,
+                Some("Mix & Match"),
+                &[
+                    ("songs/one.flac", Some("Someone"), Some("One"), Some(200_500)),
+                    ("file:///music/two%20words.mp3", None, None, None),
+                ],
+            ),
+            (
+                "\u{feff}<playlist>\r\n<trackList>\r\n<track><location>a.mp3</location></track>\r\n</trackList>\r\n</playlist>\r\n",
+                None,
+                &[("a.mp3", None, None, None)],
+            ),
+        ];
+
+        for (content, name, expected) in cases {
+            check(PlaylistFormat::Xspf, content, *name, expected);
+        }
+    }
+
+    #[test]
+    fn resolves_relative_locations() {
+        let cases = [
+            ("http://example.com/lists/mix.m3u", "songs/a.mp3", "http://example.com/lists/songs/a.mp3"),
+            ("http://example.com/lists/mix.m3u", "/root.mp3", "http://example.com/root.mp3"),
+            ("http://example.com/lists/mix.m3u", "https://cdn.example/b.mp3", "https://cdn.example/b.mp3"),
+            ("/music/lists/mix.m3u", "a.mp3", "/music/lists/a.mp3"),
+            ("/music/lists/mix.m3u", "../b.flac", "/music/lists/../b.flac"),
+            ("local:/music/lists/mix.pls", "c.ogg", "/music/lists/c.ogg"),
+            ("/music/lists/mix.xspf", "/elsewhere/d.mp3", "/elsewhere/d.mp3"),
+            ("/music/lists/mix.xspf", "file:///music/two%20words.mp3", "/music/two words.mp3"),
+        ];
+
+        for (base, location, expected) in cases {
+            assert_eq!(resolve_location(base, location), expected, "{} + {}", base, location);
+        }
+    }
+
+    #[test]
+    fn splits_selected_entry() {
+        let cases = [
+            ("http://example.com/mix.m3u#3", "http://example.com/mix.m3u", Some(2)),
+            ("/music/mix.pls#1", "/music/mix.pls", Some(0)),
+            ("/music/mix.pls#0", "/music/mix.pls", None),
+            ("/music/mix.xspf", "/music/mix.xspf", None),
+            ("/music/mix.m3u#intro", "/music/mix.m3u#intro", None),
+        ];
+
+        for (identifier, base, selected) in cases {
+            assert_eq!(split_selection(identifier), (base, selected), "{}", identifier);
+        }
+    }
+}
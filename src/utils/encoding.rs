@@ -1,8 +1,11 @@
-use bytes::{Buf, BufMut, BytesMut};
+use bytes::{BufMut, BytesMut};
 use base64::{Engine as _, engine::general_purpose};
-use std::io::Cursor;
 use serde::{Serialize, Deserialize};
 
+const TRACK_INFO_VERSION: u8 = 3;
+const TRACK_INFO_VERSIONED: u32 = 1;
+const MAX_UTF_LENGTH: usize = u16::MAX as usize;
+
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct DecodedInfo {
@@ -16,6 +19,8 @@ pub struct DecodedInfo {
     pub isrc: Option<String>,
     pub source_name: String,
     pub position: u64,
+    #[serde(skip)]
+    pub source_data: Vec<u8>,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -27,30 +32,109 @@ pub struct DecodedTrack {
     pub user_data: serde_json::Value,
 }
 
-pub fn decode_track(encoded: &str) -> Result<DecodedTrack, Box<dyn std::error::Error>> {
-    let bytes = general_purpose::STANDARD.decode(encoded)?;
-    let mut cursor = Cursor::new(bytes);
+#[derive(Debug)]
+pub enum DecodeError {
+    Base64(base64::DecodeError),
+    Truncated(&'static str),
+    UnknownVersion(u8),
+    InvalidUtf8(&'static str),
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::Base64(e) => write!(f, "invalid base64: {}", e),
+            DecodeError::Truncated(field) => write!(f, "track data ends before its {}", field),
+            DecodeError::UnknownVersion(version) => write!(f, "unknown track version {}", version),
+            DecodeError::InvalidUtf8(field) => write!(f, "the {} is not valid modified UTF-8", field),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DecodeError::Base64(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize, field: &'static str) -> Result<&'a [u8], DecodeError> {
+        if self.data.len() < len {
+            return Err(DecodeError::Truncated(field));
+        }
+        let (head, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(head)
+    }
 
-    let header = cursor.get_i32();
+    fn array<const N: usize>(&mut self, field: &'static str) -> Result<[u8; N], DecodeError> {
+        let mut out = [0u8; N];
+        out.copy_from_slice(self.take(N, field)?);
+        Ok(out)
+    }
+
+    fn u8(&mut self, field: &'static str) -> Result<u8, DecodeError> {
+        Ok(self.array::<1>(field)?[0])
+    }
+
+    fn u32(&mut self, field: &'static str) -> Result<u32, DecodeError> {
+        Ok(u32::from_be_bytes(self.array(field)?))
+    }
+
+    fn u64(&mut self, field: &'static str) -> Result<u64, DecodeError> {
+        Ok(u64::from_be_bytes(self.array(field)?))
+    }
+
+    fn utf(&mut self, field: &'static str) -> Result<String, DecodeError> {
+        let len = u16::from_be_bytes(self.array(field)?) as usize;
+        decode_modified_utf8(self.take(len, field)?).ok_or(DecodeError::InvalidUtf8(field))
+    }
+
+    fn nullable_utf(&mut self, field: &'static str) -> Result<Option<String>, DecodeError> {
+        match self.u8(field)? {
+            0 => Ok(None),
+            _ => self.utf(field).map(Some),
+        }
+    }
+}
+
+pub fn decode_track(encoded: &str) -> Result<DecodedTrack, DecodeError> {
+    let bytes = general_purpose::STANDARD.decode(encoded).map_err(DecodeError::Base64)?;
+    let mut reader = Reader { data: &bytes };
+
+    let header = reader.u32("header")?;
+    let flags = header >> 30;
     let size = (header & 0x3fffffff) as usize;
-    
-    let mut data = vec![0u8; size];
-    cursor.copy_to_slice(&mut data);
-    let mut msg = Cursor::new(data);
-
-    let version = msg.get_u8();
-    let title = read_utf(&mut msg)?;
-    let author = read_utf(&mut msg)?;
-    let length = msg.get_u64();
-    let identifier = read_utf(&mut msg)?;
-    let is_stream = msg.get_u8() != 0;
-    
-    let uri = if version >= 2 { read_nullable_text(&mut msg)? } else { None };
-    let artwork_url = if version >= 3 { read_nullable_text(&mut msg)? } else { None };
-    let isrc = if version >= 3 { read_nullable_text(&mut msg)? } else { None };
-    
-    let source_name = read_utf(&mut msg)?;
-    let position = msg.get_u64();
+    let mut msg = Reader { data: reader.take(size, "message body")? };
+
+    let version = if flags & TRACK_INFO_VERSIONED != 0 { msg.u8("version")? } else { 1 };
+    if !(1..=TRACK_INFO_VERSION).contains(&version) {
+        return Err(DecodeError::UnknownVersion(version));
+    }
+
+    let title = msg.utf("title")?;
+    let author = msg.utf("author")?;
+    let length = msg.u64("length")?;
+    let identifier = msg.utf("identifier")?;
+    let is_stream = msg.u8("stream flag")? != 0;
+
+    let uri = if version >= 2 { msg.nullable_utf("uri")? } else { None };
+    let artwork_url = if version >= 3 { msg.nullable_utf("artwork url")? } else { None };
+    let isrc = if version >= 3 { msg.nullable_utf("isrc")? } else { None };
+
+    let source_name = msg.utf("source name")?;
+
+    // Source managers append their own fields before the position, which is always the last field.
+    let source_data_len = msg.data.len().checked_sub(8).ok_or(DecodeError::Truncated("position"))?;
+    let source_data = msg.take(source_data_len, "source data")?.to_vec();
+    let position = msg.u64("position")?;
 
     Ok(DecodedTrack {
         encoded: encoded.to_string(),
@@ -65,6 +149,7 @@ pub fn decode_track(encoded: &str) -> Result<DecodedTrack, Box<dyn std::error::E
             isrc,
             source_name,
             position,
+            source_data,
         },
         plugin_info: serde_json::json!({}),
         user_data: serde_json::json!({}),
@@ -73,54 +158,39 @@ pub fn decode_track(encoded: &str) -> Result<DecodedTrack, Box<dyn std::error::E
 
 pub fn encode_track(info: &DecodedInfo) -> String {
     let mut buf = BytesMut::new();
-    let version = if info.artwork_url.is_some() || info.isrc.is_some() { 3 } else if info.uri.is_some() { 2 } else { 1 };
-    
-    buf.put_u8(version);
+
+    buf.put_u8(TRACK_INFO_VERSION);
     write_utf(&mut buf, &info.title);
     write_utf(&mut buf, &info.author);
     buf.put_u64(info.length);
     write_utf(&mut buf, &info.identifier);
     buf.put_u8(if info.is_stream { 1 } else { 0 });
-    
-    if version >= 2 {
-        write_nullable_text(&mut buf, info.uri.as_deref());
-    }
-    if version >= 3 {
-        write_nullable_text(&mut buf, info.artwork_url.as_deref());
-        write_nullable_text(&mut buf, info.isrc.as_deref());
-    }
-    
+    write_nullable_text(&mut buf, info.uri.as_deref());
+    write_nullable_text(&mut buf, info.artwork_url.as_deref());
+    write_nullable_text(&mut buf, info.isrc.as_deref());
     write_utf(&mut buf, &info.source_name);
+    buf.put_slice(&info.source_data);
     buf.put_u64(info.position);
 
-    let header = (buf.len() as u32 & 0x3fffffff) | (1 << 30);
+    let header = (buf.len() as u32 & 0x3fffffff) | (TRACK_INFO_VERSIONED << 30);
     let mut res = BytesMut::with_capacity(4 + buf.len());
     res.put_u32(header);
     res.put(buf);
-    
-    general_purpose::STANDARD.encode(res)
-}
 
-fn read_utf(cursor: &mut Cursor<Vec<u8>>) -> Result<String, Box<dyn std::error::Error>> {
-    let len = cursor.get_u16() as usize;
-    let mut bytes = vec![0u8; len];
-    cursor.copy_to_slice(&mut bytes);
-    Ok(String::from_utf8(bytes)?)
+    general_purpose::STANDARD.encode(res)
 }
 
-fn read_nullable_text(cursor: &mut Cursor<Vec<u8>>) -> Result<Option<String>, Box<dyn std::error::Error>> {
-    let present = cursor.get_u8() != 0;
-    if present {
-        Ok(Some(read_utf(cursor)?))
-    } else {
-        Ok(None)
-    }
+/// Source data holding a single string, the way Lavalink's HTTP and local sources store their probe info.
+pub fn encode_source_text(text: &str) -> Vec<u8> {
+    let mut buf = BytesMut::new();
+    write_utf(&mut buf, text);
+    buf.to_vec()
 }
 
 fn write_utf(buf: &mut BytesMut, s: &str) {
-    let bytes = s.as_bytes();
+    let bytes = encode_modified_utf8(s);
     buf.put_u16(bytes.len() as u16);
-    buf.put_slice(bytes);
+    buf.put_slice(&bytes);
 }
 
 fn write_nullable_text(buf: &mut BytesMut, s: Option<&str>) {
@@ -132,3 +202,251 @@ fn write_nullable_text(buf: &mut BytesMut, s: Option<&str>) {
         None => buf.put_u8(0),
     }
 }
+
+/// Java's `DataOutput.writeUTF` encoding: NUL takes two bytes and characters outside the BMP are
+/// written as a surrogate pair of three bytes each. Strings are cut at the last whole character
+/// that fits the 65535 byte limit, where Java would throw instead.
+fn encode_modified_utf8(s: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(s.len());
+    let mut units = [0u16; 2];
+
+    for c in s.chars() {
+        let start = out.len();
+        for &unit in c.encode_utf16(&mut units).iter() {
+            match unit {
+                0x0001..=0x007f => out.push(unit as u8),
+                0x0000 | 0x0080..=0x07ff => {
+                    out.push(0xc0 | (unit >> 6) as u8);
+                    out.push(0x80 | (unit & 0x3f) as u8);
+                },
+                _ => {
+                    out.push(0xe0 | (unit >> 12) as u8);
+                    out.push(0x80 | ((unit >> 6) & 0x3f) as u8);
+                    out.push(0x80 | (unit & 0x3f) as u8);
+                },
+            }
+        }
+
+        if out.len() > MAX_UTF_LENGTH {
+            out.truncate(start);
+            break;
+        }
+    }
+
+    out
+}
+
+fn decode_modified_utf8(bytes: &[u8]) -> Option<String> {
+    let mut units = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let b = bytes[i] as u16;
+        let (unit, width) = match b {
+            0x00..=0x7f => (b, 1),
+            0xc0..=0xdf => {
+                let b2 = continuation(bytes.get(i + 1))?;
+                (((b & 0x1f) << 6) | b2, 2)
+            },
+            0xe0..=0xef => {
+                let b2 = continuation(bytes.get(i + 1))?;
+                let b3 = continuation(bytes.get(i + 2))?;
+                (((b & 0x0f) << 12) | (b2 << 6) | b3, 3)
+            },
+            _ => return None,
+        };
+        units.push(unit);
+        i += width;
+    }
+
+    Some(String::from_utf16_lossy(&units))
+}
+
+fn continuation(byte: Option<&u8>) -> Option<u16> {
+    byte.filter(|b| *b & 0xc0 == 0x80).map(|b| (*b & 0x3f) as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Built byte by byte the way Lavalink writes tracks, not with `encode_track`.
+    const V3_TRACK: &str = "QAAAjAMAF05ldmVyIEdvbm5hIEdpdmUgWW91IFVwAAtSaWNrIEFzdGxleQAAAAAAAzwgAAtkUXc0dzlXZ1hjUQABACtodHRwczovL3d3dy55b3V0dWJlLmNvbS93YXRjaD92PWRRdzR3OVdnWGNRAAEADEdCQVJMOTMwMDEzNQAHeW91dHViZQAAAAAAAAAA";
+    /// Version 2 with a uri and "mp3" as source data.
+    const V2_TRACK: &str = "QAAAZQIABlN0cmVhbQAFUmFkaW9//////////wAaaHR0cHM6Ly9yYWRpby5leGFtcGxlL2xpdmUBAQAaaHR0cHM6Ly9yYWRpby5leGFtcGxlL2xpdmUABGh0dHAAA21wMwAAAAAAAAAA";
+    /// Version 1, which has no version byte, with "flac" as source data and a 5 s position.
+    const V1_TRACK: &str = "AAAAPgAEU29uZwAGQXJ0aXN0AAAAAAACvyAAEC9tdXNpYy9zb25nLmZsYWMAAAVsb2NhbAAEZmxhYwAAAAAAABOI";
+
+    fn info(title: &str) -> DecodedInfo {
+        DecodedInfo {
+            title: title.to_string(),
+            author: "Artist".to_string(),
+            length: 1000,
+            identifier: "id".to_string(),
+            is_stream: false,
+            uri: None,
+            artwork_url: None,
+            isrc: None,
+            source_name: "test".to_string(),
+            position: 0,
+            source_data: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn reads_version_3_tracks() {
+        let track = decode_track(V3_TRACK).unwrap().info;
+        assert_eq!(track.title, "Never Gonna Give You Up");
+        assert_eq!(track.author, "Rick Astley");
+        assert_eq!(track.length, 212_000);
+        assert_eq!(track.identifier, "dQw4w9WgXcQ");
+        assert!(!track.is_stream);
+        assert_eq!(track.uri.as_deref(), Some("https://www.youtube.com/watch?v=dQw4w9WgXcQ"));
+        assert_eq!(track.artwork_url, None);
+        assert_eq!(track.isrc.as_deref(), Some("GBARL9300135"));
+        assert_eq!(track.source_name, "youtube");
+        assert_eq!(track.position, 0);
+        assert!(track.source_data.is_empty());
+    }
+
+    #[test]
+    fn reads_version_2_and_1_tracks() {
+        let v2 = decode_track(V2_TRACK).unwrap().info;
+        assert_eq!((v2.title.as_str(), v2.author.as_str()), ("Stream", "Radio"));
+        assert_eq!(v2.length, i64::MAX as u64);
+        assert!(v2.is_stream);
+        assert_eq!(v2.uri.as_deref(), Some("https://radio.example/live"));
+        assert_eq!((v2.artwork_url, v2.isrc), (None, None));
+        assert_eq!(v2.source_name, "http");
+        assert_eq!(v2.source_data, encode_source_text("mp3"));
+
+        let v1 = decode_track(V1_TRACK).unwrap().info;
+        assert_eq!((v1.title.as_str(), v1.author.as_str()), ("Song", "Artist"));
+        assert_eq!(v1.length, 180_000);
+        assert_eq!(v1.identifier, "/music/song.flac");
+        assert_eq!(v1.uri, None);
+        assert_eq!(v1.source_name, "local");
+        assert_eq!(v1.source_data, encode_source_text("flac"));
+        assert_eq!(v1.position, 5000);
+    }
+
+    #[test]
+    fn writes_version_3_tracks_byte_for_byte() {
+        let track = decode_track(V3_TRACK).unwrap();
+        assert_eq!(encode_track(&track.info), V3_TRACK);
+    }
+
+    #[test]
+    fn keeps_source_data_when_upgrading_old_versions() {
+        for encoded in [V1_TRACK, V2_TRACK] {
+            let original = decode_track(encoded).unwrap().info;
+            let upgraded = encode_track(&original);
+            assert_ne!(upgraded, encoded);
+
+            let reread = decode_track(&upgraded).unwrap().info;
+            assert_eq!(reread.source_data, original.source_data, "{}", encoded);
+            assert_eq!(reread.position, original.position, "{}", encoded);
+            assert_eq!(encode_track(&reread), upgraded);
+        }
+    }
+
+    #[test]
+    fn round_trips_tracks() {
+        let mut full = info("Title");
+        full.uri = Some("https://example.com/track".to_string());
+        full.artwork_url = Some("https://example.com/art.jpg".to_string());
+        full.isrc = Some("USXXX0000001".to_string());
+        full.is_stream = true;
+        full.position = 42_000;
+        full.source_data = [encode_source_text("probe"), vec![0, 1, 2, 3, 0xFF]].concat();
+
+        for track in [info("Title"), info(""), info("a\0b"), info("😀 Ünïcode ♫"), full] {
+            let encoded = encode_track(&track);
+            assert_eq!(encode_track(&decode_track(&encoded).unwrap().info), encoded, "{}", track.title);
+        }
+    }
+
+    #[test]
+    fn writes_modified_utf8() {
+        let cases: [(&str, &[u8]); 5] = [
+            ("abc", b"abc"),
+            ("\0", &[0xC0, 0x80]),
+            ("a\0b", &[0x61, 0xC0, 0x80, 0x62]),
+            ("é♫", &[0xC3, 0xA9, 0xE2, 0x99, 0xAB]),
+            ("😀", &[0xED, 0xA0, 0xBD, 0xED, 0xB8, 0x80]),
+        ];
+        for (text, bytes) in cases {
+            assert_eq!(encode_modified_utf8(text), bytes, "{:?}", text);
+            assert_eq!(decode_modified_utf8(bytes).as_deref(), Some(text), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn cuts_long_strings_at_a_whole_character() {
+        let text = format!("{}😀", "a".repeat(MAX_UTF_LENGTH - 4));
+        assert_eq!(encode_modified_utf8(&text).len(), MAX_UTF_LENGTH - 4);
+
+        let track = decode_track(&encode_track(&info(&"é".repeat(40_000)))).unwrap();
+        assert_eq!(track.info.title, "é".repeat(MAX_UTF_LENGTH / 2));
+    }
+
+    /// Wraps a message body in a header with the versioned flag set.
+    fn versioned(body: &[u8]) -> String {
+        let header = body.len() as u32 | (TRACK_INFO_VERSIONED << 30);
+        general_purpose::STANDARD.encode([header.to_be_bytes().as_slice(), body].concat())
+    }
+
+    fn truncated_field(encoded: &str) -> &'static str {
+        match decode_track(encoded) {
+            Err(DecodeError::Truncated(field)) => field,
+            Err(e) => panic!("{}: {}", encoded, e),
+            Ok(_) => panic!("{} decoded", encoded),
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_base64() {
+        for encoded in ["not base64!", "QAAAjAM", "QAAA\u{e9}"] {
+            assert!(matches!(decode_track(encoded), Err(DecodeError::Base64(_))), "{}", encoded);
+        }
+    }
+
+    #[test]
+    fn reports_the_field_a_track_ends_in() {
+        let bytes = general_purpose::STANDARD.decode(V3_TRACK).unwrap();
+        let body = &bytes[4..];
+
+        let mut fields: Vec<&str> = (0..body.len()).map(|len| truncated_field(&versioned(&body[..len]))).collect();
+        fields.dedup();
+        assert_eq!(fields, [
+            "version", "title", "author", "length", "identifier", "stream flag",
+            "uri", "artwork url", "isrc", "source name", "position",
+        ]);
+
+        for len in 0..4 {
+            assert_eq!(truncated_field(&general_purpose::STANDARD.encode(&bytes[..len])), "header");
+        }
+        assert_eq!(truncated_field(&general_purpose::STANDARD.encode(&bytes[..bytes.len() - 1])), "message body");
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        let bytes = general_purpose::STANDARD.decode(V3_TRACK).unwrap();
+        for version in [0, 4, 255] {
+            let mut body = bytes[4..].to_vec();
+            body[0] = version;
+            assert!(matches!(decode_track(&versioned(&body)), Err(DecodeError::UnknownVersion(v)) if v == version));
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_modified_utf8() {
+        let cases: [&[u8]; 5] = [&[0xFF], &[0x80], &[0xC3], &[0xC3, 0x41], &[0xE2, 0x99]];
+        for text in cases {
+            let mut body = vec![TRACK_INFO_VERSION];
+            body.extend((text.len() as u16).to_be_bytes());
+            body.extend_from_slice(text);
+            assert!(matches!(decode_track(&versioned(&body)), Err(DecodeError::InvalidUtf8("title"))), "{:?}", text);
+        }
+    }
+}
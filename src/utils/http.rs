@@ -0,0 +1,18 @@
+use std::sync::OnceLock;
+use std::time::Duration;
+
+static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+pub fn client() -> &'static reqwest::Client {
+    CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .user_agent(concat!("Aelira/", env!("CARGO_PKG_VERSION")))
+            .connect_timeout(Duration::from_secs(10))
+            .build()
+            .expect("Failed to build HTTP client")
+    })
+}
+
+pub fn is_http_url(value: &str) -> bool {
+    value.starts_with("http://") || value.starts_with("https://")
+}
@@ -1,4 +1,6 @@
 pub mod encoding;
+pub mod http;
+pub mod text;
 
 use std::fmt::Display;
 use std::time::SystemTime;
@@ -24,4 +26,15 @@ pub fn log(level: Level, component: &str, message: impl Display) {
     };
 
     println!("\x1b[90m[{}]\x1b[0m {}{}\x1b[0m \x1b[90m[{}]\x1b[0m {}", timestamp, color, label, component, message);
-}
\ No newline at end of file
+}
+
+/// Formats an error followed by its sources, one "Caused by:" line each.
+pub fn cause_chain(error: &dyn std::error::Error) -> String {
+    let mut chain = error.to_string();
+    let mut source = error.source();
+    while let Some(inner) = source {
+        chain.push_str(&format!("\nCaused by: {}", inner));
+        source = inner.source();
+    }
+    chain
+}
@@ -0,0 +1,51 @@
+pub fn tokenize(value: &str) -> Vec<String> {
+    value.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+pub fn match_token(token: &str, word: &str) -> f32 {
+    if token == word {
+        return 1.0;
+    }
+    if word.starts_with(token) {
+        return 0.8;
+    }
+    if token.len() >= 3 && word.contains(token) {
+        return 0.6;
+    }
+
+    let max_distance = match token.chars().count() {
+        0..=3 => return 0.0,
+        4..=7 => 1,
+        _ => 2,
+    };
+
+    match levenshtein(token, word) {
+        d if d <= max_distance => 0.5 - d as f32 * 0.1,
+        _ => 0.0,
+    }
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    if a.len().abs_diff(b.len()) > 2 {
+        return usize::MAX;
+    }
+
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut curr = vec![0; b.len() + 1];
+
+    for (i, ca) in a.iter().enumerate() {
+        curr[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == cb { 0 } else { 1 };
+            curr[j + 1] = (prev[j + 1] + 1).min(curr[j] + 1).min(prev[j] + cost);
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+
+    prev[b.len()]
+}